@@ -0,0 +1,279 @@
+use anyhow::Context;
+use chrono::Local;
+use clap::Subcommand;
+use itertools::Itertools;
+use owo_colors::OwoColorize;
+
+use crate::data::{
+    local, ArchivedMod, Cacheable, Changelogs, CompoundKey, Files, ModInfoFull, ModStatus, Tracked,
+};
+use crate::formatting::pluralize_mod;
+use crate::nexus::{pool, NexusApi, NexusError};
+use crate::Flags;
+
+#[derive(Clone, Debug, Subcommand)]
+pub enum ArchiveCommand {
+    /// Archive the info, file list, and changelogs of every published mod you track,
+    /// fetching whatever isn't cached yet.
+    Save {
+        /// Only archive mods for this game.
+        #[clap(value_parser = crate::config::game_name)]
+        game: Option<String>,
+        /// The number of API calls allowed for fetching what isn't cached.
+        #[clap(short, long, default_value_t = crate::REQ_LIMIT)]
+        limit: u16,
+    },
+    /// List archived mods that are no longer published.
+    List {
+        /// Only list mods for this game.
+        #[clap(value_parser = crate::config::game_name)]
+        game: Option<String>,
+        /// List every archived mod, published or not.
+        #[clap(long)]
+        all: bool,
+    },
+    /// Show everything archived for a mod.
+    Show {
+        /// The id of the mod to show
+        mod_id: u32,
+        /// Which game the mod belongs to; Nexus short name
+        #[clap(default_value = crate::config::get().game.as_str(), value_parser = crate::config::game_name)]
+        game: String,
+    },
+}
+
+pub fn handle<N: NexusApi + Clone + Send>(
+    flags: &Flags,
+    cmd: &ArchiveCommand,
+    nexus: &mut N,
+) -> anyhow::Result<()> {
+    match cmd {
+        ArchiveCommand::Save { game, limit } => save(flags, game.as_deref(), *limit, nexus),
+        ArchiveCommand::List { game, all } => list(flags, game.as_deref(), *all),
+        ArchiveCommand::Show { game, mod_id } => show(flags, game, *mod_id),
+    }
+}
+
+/// The pieces of a mod we archive, each its own api call.
+#[derive(Debug, Clone, Copy)]
+enum Piece {
+    Info,
+    Files,
+    Changelogs,
+}
+
+/// What the Nexus gave us for a piece.
+#[derive(Debug)]
+enum Fetched {
+    Info(Box<ModInfoFull>),
+    Files(Box<Files>),
+    Changelogs(Box<Changelogs>),
+}
+
+impl Fetched {
+    fn store(&self) -> anyhow::Result<usize> {
+        match self {
+            Fetched::Info(v) => v.store(),
+            Fetched::Files(v) => v.store(),
+            Fetched::Changelogs(v) => v.store(),
+        }
+    }
+}
+
+fn fetch_piece<N: NexusApi>(
+    key: &CompoundKey,
+    piece: Piece,
+    nexus: &mut N,
+) -> Result<Option<Fetched>, NexusError> {
+    let fetched = match piece {
+        Piece::Info => ModInfoFull::fetch(key, nexus, None)?.map(Fetched::Info),
+        Piece::Files => Files::fetch(key, nexus, None)?.map(Fetched::Files),
+        Piece::Changelogs => Changelogs::fetch(key, nexus, None)?.map(Fetched::Changelogs),
+    };
+    Ok(fetched)
+}
+
+/// The pieces of a mod we have yet to cache. Mods that are already gone have nothing left
+/// worth fetching.
+fn missing_pieces(key: &CompoundKey) -> Vec<Piece> {
+    match local::<ModInfoFull>(key) {
+        None => vec![Piece::Info, Piece::Files, Piece::Changelogs],
+        Some(modinfo) if modinfo.status() != ModStatus::Published => Vec::new(),
+        Some(_) => {
+            let mut missing = Vec::new();
+            if local::<Files>(key).is_none() {
+                missing.push(Piece::Files);
+            }
+            if local::<Changelogs>(key).is_none() {
+                missing.push(Piece::Changelogs);
+            }
+            missing
+        }
+    }
+}
+
+fn save<N: NexusApi + Clone + Send>(
+    flags: &Flags,
+    game: Option<&str>,
+    limit: u16,
+    nexus: &mut N,
+) -> anyhow::Result<()> {
+    let tracked = Tracked::get(&Tracked::listkey(), flags.refresh, nexus)
+        .context("Unable to fetch your tracked mods.")?;
+    let keys: Vec<CompoundKey> = tracked
+        .mods
+        .iter()
+        .filter(|m| game.is_none_or(|g| m.domain_name == g))
+        .map(|m| CompoundKey::new(m.domain_name.clone(), m.mod_id))
+        .collect();
+
+    let todo: Vec<(CompoundKey, Piece)> = keys
+        .iter()
+        .flat_map(|key| {
+            missing_pieces(key)
+                .into_iter()
+                .map(move |piece| (key.clone(), piece))
+        })
+        .collect();
+    if !todo.is_empty() {
+        println!(
+            "Fetching up to {} of {} missing pieces of mod info, file lists, and changelogs.",
+            limit.min(todo.len() as u16).blue(),
+            todo.len().blue()
+        );
+    }
+    let mut todo = todo.into_iter();
+    let mut spent: u16 = 0;
+    let nexus = &*nexus;
+    let fetched = pool::run(
+        nexus,
+        flags.jobs as usize,
+        || {
+            if spent >= limit {
+                return None;
+            }
+            spent += 1;
+            todo.next()
+        },
+        |(key, piece), client| fetch_piece(key, *piece, client),
+        |(key, piece), result| {
+            match result {
+                Ok(Some(fetched)) => {
+                    fetched.store()?;
+                }
+                Ok(None) => {}
+                Err(e) if e.is_fatal() => return Err(e.into()),
+                Err(e) => log::warn!("   ! unable to fetch {:?} for {}: {}", piece, key, e),
+            }
+            Ok(())
+        },
+    );
+
+    // Archive what we have, even if fetching stopped early.
+    let mut archived = 0;
+    let mut incomplete = 0;
+    for key in keys.iter() {
+        if ArchivedMod::archive_cached(key)? {
+            archived += 1;
+            if !missing_pieces(key).is_empty() {
+                incomplete += 1;
+            }
+        }
+    }
+    println!("Archived {}.", pluralize_mod(archived));
+    if incomplete > 0 {
+        println!(
+            "{} still missing a file list or changelogs; run this again to fetch them.",
+            pluralize_mod(incomplete)
+        );
+    }
+    fetched
+}
+
+fn list(flags: &Flags, game: Option<&str>, all: bool) -> anyhow::Result<()> {
+    let archived: Vec<ArchivedMod> = ArchivedMod::all(game)?
+        .into_iter()
+        .filter(|entry| {
+            all || local::<ModInfoFull>(&entry.modinfo.key())
+                .is_none_or(|current| current.status() != ModStatus::Published)
+        })
+        .collect();
+
+    if flags.json {
+        let pretty = serde_json::to_string_pretty(&archived)?;
+        println!("{}", pretty);
+        return Ok(());
+    }
+    if archived.is_empty() {
+        println!("No archived mods are gone from the Nexus.");
+        return Ok(());
+    }
+
+    for entry in archived.iter() {
+        let key = entry.modinfo.key();
+        let status = match local::<ModInfoFull>(&key) {
+            Some(current) => current.status().to_string(),
+            None => "not cached".to_string(),
+        };
+        println!(
+            "{} {} {}\n    {} archived {}",
+            key.to_string().dimmed(),
+            entry.modinfo.name().green(),
+            format!("<{}>", entry.modinfo.uploaded_by()).cyan(),
+            status.red(),
+            entry.archived.with_timezone(&Local).format("%Y-%m-%d")
+        );
+    }
+    Ok(())
+}
+
+fn show(flags: &Flags, game: &str, mod_id: u32) -> anyhow::Result<()> {
+    let key = CompoundKey::new(game.to_string(), mod_id);
+    let Some(entry) = ArchivedMod::load(&key)? else {
+        println!("Nothing archived for {}.", key.yellow());
+        return Ok(());
+    };
+    if flags.json {
+        let pretty = serde_json::to_string_pretty(&entry)?;
+        println!("{}", pretty);
+        return Ok(());
+    }
+
+    println!(
+        "{}",
+        format!(
+            "archived {}",
+            entry
+                .archived
+                .with_timezone(&Local)
+                .format("%Y-%m-%d %H:%M")
+        )
+        .dimmed()
+    );
+    entry.modinfo.print_full_info();
+
+    match &entry.files {
+        Some(files) => {
+            println!("\n{}\n", "Files".bold());
+            for f in files.files() {
+                f.print_compact_info();
+                println!();
+            }
+        }
+        None => println!("\n{}", "No file list archived.".dimmed()),
+    }
+
+    match &entry.changelogs {
+        Some(changelogs) if !changelogs.versions().is_empty() => {
+            println!("\n{}", "Changelogs".bold());
+            for (version, logs) in changelogs.versions().iter().sorted_by_key(|(v, _)| *v) {
+                println!("\n{}:", version.red());
+                for log in logs {
+                    println!("    {}", log);
+                }
+            }
+        }
+        _ => println!("\n{}", "No changelogs archived.".dimmed()),
+    }
+    Ok(())
+}
@@ -0,0 +1,307 @@
+//! Looking after the cache itself: what's in it, whether every record still reads, and
+//! clearing out what nobody needs any more.
+
+use std::collections::{BTreeSet, HashSet};
+
+use anyhow::Context;
+use chrono::{Duration, Utc};
+use clap::Subcommand;
+use humansize::format_size;
+use owo_colors::OwoColorize;
+use prettytable::{row, Table};
+use serde_json::json;
+
+use crate::data::{
+    cached_types, index, local, record_fetched_at, record_viewed_at, set_records_viewed,
+    Changelogs, Files, Tracked, FETCHED, VIEWED,
+};
+use crate::storage::migrations::UNMIGRATED;
+use crate::storage::MODS;
+use crate::{storage, Cacheable, Flags};
+
+#[derive(Clone, Debug, Subcommand)]
+pub enum CacheCommand {
+    /// Show how many records each bucket holds and how much room the cache takes up.
+    Stats,
+    /// Check that every record reads as its type and is filed under its own key.
+    Check {
+        /// Remove the records that fail the check.
+        #[clap(long)]
+        remove: bool,
+    },
+    /// Remove untracked mods nobody has looked at lately, plus the file lists, changelogs,
+    /// and bookkeeping left behind by mods that are gone.
+    Gc {
+        /// Keep untracked mods viewed or fetched within this many days.
+        #[clap(long, default_value_t = 90)]
+        days: u32,
+        /// Say what would be removed without removing anything.
+        #[clap(long)]
+        dry_run: bool,
+    },
+    /// Flush the cache to disk and reclaim what space the backend can.
+    Compact,
+    /// Rebuild the full-text search index for every game with cached mods.
+    Reindex,
+}
+
+pub fn handle(flags: &Flags, cmd: &CacheCommand) -> anyhow::Result<()> {
+    match cmd {
+        CacheCommand::Stats => stats(flags),
+        CacheCommand::Check { remove } => check(*remove),
+        CacheCommand::Gc { days, dry_run } => gc(*days, *dry_run),
+        CacheCommand::Compact => compact(),
+        CacheCommand::Reindex => reindex(),
+    }
+}
+
+fn stats(flags: &Flags) -> anyhow::Result<()> {
+    let mut buckets = Vec::new();
+    for bucket in storage().buckets()? {
+        let records = storage().scan(&bucket, "")?;
+        let bytes: usize = records.iter().map(|(k, v)| k.len() + v.len()).sum();
+        buckets.push((bucket, records.len(), bytes));
+    }
+    let on_disk = storage().size_on_disk()?;
+
+    if flags.json {
+        let report = json!({
+            "buckets": buckets
+                .iter()
+                .map(|(bucket, records, bytes)| json!({
+                    "bucket": bucket,
+                    "records": records,
+                    "bytes": bytes,
+                }))
+                .collect::<Vec<_>>(),
+            "size_on_disk": on_disk,
+        });
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        return Ok(());
+    }
+
+    let mut table = Table::new();
+    table.set_format(*prettytable::format::consts::FORMAT_CLEAN);
+    table.set_titles(row![r->"records", r->"data", "bucket"]);
+    for (bucket, records, bytes) in buckets.iter() {
+        table.add_row(row![
+            r->records.bold(),
+            r->format_size(*bytes, humansize::DECIMAL),
+            bucket
+        ]);
+    }
+    println!("{table}");
+    println!(
+        "The cache takes up {} on disk.",
+        format_size(on_disk, humansize::DECIMAL).bold()
+    );
+    Ok(())
+}
+
+/// A record that fails the integrity check.
+struct Problem {
+    bucket: &'static str,
+    key: String,
+    error: String,
+}
+
+fn check(remove: bool) -> anyhow::Result<()> {
+    let mut checked = 0;
+    let mut problems = Vec::new();
+    for kind in cached_types() {
+        for (key, json) in storage().scan(kind.bucket, "")? {
+            checked += 1;
+            match (kind.read)(&json) {
+                Ok(actual) if actual == key => {}
+                Ok(actual) => problems.push(Problem {
+                    bucket: kind.bucket,
+                    key,
+                    error: format!("holds the record for {}", actual),
+                }),
+                Err(e) => problems.push(Problem {
+                    bucket: kind.bucket,
+                    key,
+                    error: e.to_string(),
+                }),
+            }
+        }
+    }
+
+    for problem in problems.iter() {
+        println!(
+            "{}/{}: {}",
+            problem.bucket,
+            problem.key.yellow(),
+            problem.error.red()
+        );
+    }
+    println!(
+        "Checked {} records; {} failed.",
+        checked.blue(),
+        problems.len().bold()
+    );
+
+    if remove {
+        for problem in problems.iter() {
+            remove_record(problem.bucket, &problem.key)?;
+        }
+        // Every record the migration report lists now reads or is gone.
+        for (key, _json) in storage().scan(UNMIGRATED, "")? {
+            storage().remove(UNMIGRATED, &key)?;
+        }
+        if !problems.is_empty() {
+            println!("Removed {} records.", problems.len().bold());
+        }
+    } else if !problems.is_empty() {
+        println!(
+            "Run `modcache cache check --remove` to remove them; they'll be refetched as needed."
+        );
+    }
+    Ok(())
+}
+
+/// Remove a record along with our notes about when it was fetched and viewed, and any
+/// mention of it in the search index.
+fn remove_record(bucket: &str, key: &str) -> anyhow::Result<()> {
+    storage().remove(bucket, key)?;
+    if bucket == MODS {
+        index::remove(key)?;
+    }
+    let stamp = format!("{}/{}", bucket, key);
+    storage().remove(FETCHED, &stamp)?;
+    storage().remove(VIEWED, &stamp)?;
+    Ok(())
+}
+
+fn gc(days: u32, dry_run: bool) -> anyhow::Result<()> {
+    if local::<Tracked>(&Tracked::listkey()).is_none() {
+        anyhow::bail!(
+            "Your tracked mods aren't cached, so there's no telling which mods to keep. Run `modcache tracked` first."
+        );
+    }
+    // Every profile's tracked mods are kept, not just yours.
+    let mut tracked: HashSet<String> = HashSet::new();
+    for (key, json) in storage().scan(Tracked::bucket_name(), "")? {
+        let list: Tracked = serde_json::from_str(&json)
+            .with_context(|| format!("Unable to read the tracked list {}", key))?;
+        tracked.extend(
+            list.mods
+                .iter()
+                .map(|m| format!("{}/{}", m.domain_name, m.mod_id)),
+        );
+    }
+    let cutoff = Utc::now() - Duration::days(days as i64);
+
+    // Untracked mods nobody has viewed, or fetched, since the cutoff. Mods cached before we
+    // noted either have no telling how old they are, so they're kept.
+    let mut stale: Vec<String> = Vec::new();
+    let mut undated: Vec<String> = Vec::new();
+    let mut kept: HashSet<String> = HashSet::new();
+    for (key, _json) in storage().scan(MODS, "")? {
+        let last_touched = record_viewed_at(MODS, &key).max(record_fetched_at(MODS, &key));
+        match last_touched {
+            _ if tracked.contains(&key) => {}
+            None => undated.push(key.clone()),
+            Some(when) if when < cutoff => {
+                stale.push(key);
+                continue;
+            }
+            Some(_) => {}
+        }
+        kept.insert(key);
+    }
+
+    // File lists and changelogs for mods we don't have.
+    let mut orphans: Vec<(&'static str, String)> = Vec::new();
+    for bucket in [Files::bucket_name(), Changelogs::bucket_name()] {
+        for (key, _json) in storage().scan(bucket, "")? {
+            if !kept.contains(&key) {
+                orphans.push((bucket, key));
+            }
+        }
+    }
+
+    // Notes about when things were fetched or viewed, for things that were already gone.
+    // Removing a record takes its own notes with it.
+    let removing: HashSet<String> = stale
+        .iter()
+        .map(|key| format!("{}/{}", MODS, key))
+        .chain(orphans.iter().map(|(b, k)| format!("{}/{}", b, k)))
+        .collect();
+    let mut notes: Vec<(&'static str, String)> = Vec::new();
+    for bucket in [FETCHED, VIEWED] {
+        for (stamp, _json) in storage().scan(bucket, "")? {
+            let gone = match stamp.split_once('/') {
+                Some((b, k)) => !removing.contains(&stamp) && storage().get(b, k)?.is_none(),
+                None => true,
+            };
+            if gone {
+                notes.push((bucket, stamp));
+            }
+        }
+    }
+
+    let verb = if dry_run { "Would remove" } else { "Removing" };
+    println!(
+        "{} {} untracked mods not viewed in {} days, {} orphaned file lists and changelogs, and {} stale notes.",
+        verb,
+        stale.len().bold(),
+        days,
+        orphans.len().bold(),
+        notes.len().bold()
+    );
+    if !undated.is_empty() {
+        println!(
+            "Keeping {} untracked mods with no record of when they were fetched or viewed; {} counting their age from today.",
+            undated.len().bold(),
+            if dry_run { "a real run starts" } else { "now" }
+        );
+    }
+    for key in stale.iter() {
+        log::info!("    {}/{}", MODS, key);
+    }
+    for (bucket, key) in orphans.iter() {
+        log::info!("    {}/{}", bucket, key);
+    }
+    if dry_run {
+        return Ok(());
+    }
+
+    for key in stale.iter() {
+        remove_record(MODS, key)?;
+    }
+    // Counting their age as a view, not a fetch, so nothing takes the old copies for new.
+    set_records_viewed(MODS, &undated, Utc::now())?;
+    for (bucket, key) in orphans.iter() {
+        remove_record(bucket, key)?;
+    }
+    for (bucket, stamp) in notes.iter() {
+        storage().remove(bucket, stamp)?;
+    }
+    Ok(())
+}
+
+fn compact() -> anyhow::Result<()> {
+    let before = storage().size_on_disk()?;
+    storage().compact()?;
+    let after = storage().size_on_disk()?;
+    println!(
+        "The cache took up {} on disk and now takes up {}.",
+        format_size(before, humansize::DECIMAL),
+        format_size(after, humansize::DECIMAL).bold()
+    );
+    Ok(())
+}
+
+fn reindex() -> anyhow::Result<()> {
+    let games: BTreeSet<String> = storage()
+        .scan(MODS, "")?
+        .into_iter()
+        .filter_map(|(key, _json)| key.split_once('/').map(|(game, _id)| game.to_string()))
+        .collect();
+    for game in games.iter() {
+        let count = index::rebuild(game)?;
+        println!("Indexed {} mods for {}.", count.bold(), game.yellow());
+    }
+    Ok(())
+}
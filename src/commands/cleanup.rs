@@ -2,31 +2,50 @@ use std::collections::HashSet;
 
 use owo_colors::OwoColorize;
 
+use super::find_game;
 use crate::data::modinfo::ModInfoFull;
 use crate::data::tracked::Tracked;
-use crate::data::Cacheable;
-use crate::nexus::NexusClient;
-use crate::{Flags, GameMetadata};
-
-pub fn hidden(flags: &Flags, game: &String, nexus: &mut NexusClient) -> anyhow::Result<()> {
-    let Some(metadata) = GameMetadata::get(game, flags.refresh, nexus) else {
-        println!(
-            "No game identified as {} found on the Nexus. Recheck the slug!",
-            game.yellow().bold()
-        );
-        return Ok(())
+use crate::data::{note_all_viewed, Cacheable};
+use crate::nexus::NexusApi;
+use crate::Flags;
+
+/// The ids of the mods tracked for a game, or `None` if the list can't be had, as when
+/// offline. That's said here, so callers can carry on with every mod instead.
+fn tracked_ids<N: NexusApi>(flags: &Flags, game: &str, nexus: &mut N) -> Option<HashSet<u32>> {
+    match Tracked::get(&Tracked::listkey(), flags.refresh, nexus) {
+        Ok(all_tracked) => Some(
+            all_tracked
+                .by_game(game)
+                .iter()
+                .map(|xs| xs.mod_id)
+                .collect(),
+        ),
+        Err(e) => {
+            log::warn!("Couldn't get your tracked mods: {}", e);
+            None
+        }
+    }
+}
+
+pub fn hidden<N: NexusApi>(flags: &Flags, game: &str, nexus: &mut N) -> anyhow::Result<()> {
+    let Some(metadata) = find_game(game, flags.refresh, nexus)? else {
+        return Ok(());
     };
 
     let mut mods = metadata.mods_hidden();
-    if let Some(all_tracked) = Tracked::get(&Tracked::listkey(), flags.refresh, nexus) {
-        // filter tracked mods from hidden
-        let tracked: HashSet<u32> = all_tracked
-            .by_game(game)
-            .iter()
-            .map(|xs| xs.mod_id)
-            .collect();
+    // filter tracked mods from hidden, if we know which those are
+    let tracked = tracked_ids(flags, game, nexus);
+    if let Some(tracked) = &tracked {
         mods.retain(|xs| tracked.contains(&xs.mod_id()));
     }
+    if let Err(e) = note_all_viewed::<ModInfoFull>(mods.iter().map(|m| m.key())) {
+        log::warn!("Couldn't note that the mods listed were viewed: {}", e);
+    }
+    let which = if tracked.is_some() {
+        "hidden but tracked"
+    } else {
+        "hidden"
+    };
 
     if flags.json {
         let pretty: String = serde_json::to_string_pretty(&mods)?;
@@ -34,18 +53,21 @@ pub fn hidden(flags: &Flags, game: &String, nexus: &mut NexusClient) -> anyhow::
     } else {
         if mods.is_empty() {
             println!(
-                "\nNo hidden but tracked mods in cache for {}",
+                "\nNo {} mods in cache for {}",
+                which,
                 metadata.name().yellow().bold()
             );
         } else if mods.len() == 1 {
             println!(
-                "\nOne hidden but tracked mod in cache for {}:\n",
+                "\nOne {} mod in cache for {}:\n",
+                which,
                 metadata.name().yellow().bold()
             );
         } else {
             println!(
-                "\n{} hidden but tracked mods in cache for {}:\n",
+                "\n{} {} mods in cache for {}:\n",
                 mods.len(),
+                which,
                 metadata.name().yellow().bold()
             );
         }
@@ -54,14 +76,18 @@ pub fn hidden(flags: &Flags, game: &String, nexus: &mut NexusClient) -> anyhow::
         // refresh.
         for m in mods.into_iter() {
             let refreshed = if flags.refresh {
-                ModInfoFull::get(&m.key(), true, nexus)
+                match ModInfoFull::get(&m.key(), true, nexus) {
+                    Ok(v) => Some(*v),
+                    Err(e) if e.is_fatal() => return Err(e.into()),
+                    Err(e) => {
+                        log::warn!("Couldn't refresh {}: {}", m.key(), e);
+                        None
+                    }
+                }
             } else {
                 None
             };
-            let info = match refreshed {
-                Some(v) => *v,
-                None => m,
-            };
+            let info = refreshed.unwrap_or(m);
             println!("{}", info.compact_info());
         }
     }
@@ -69,16 +95,15 @@ pub fn hidden(flags: &Flags, game: &String, nexus: &mut NexusClient) -> anyhow::
     Ok(())
 }
 
-pub fn removed(flags: &Flags, game: &String, nexus: &mut NexusClient) -> anyhow::Result<()> {
-    let Some(metadata) = GameMetadata::get(game, flags.refresh, nexus) else {
-        println!(
-            "No game identified as {} found on the Nexus. Recheck the slug!",
-            game.yellow().bold()
-        );
-        return Ok(())
+pub fn removed<N: NexusApi>(flags: &Flags, game: &str, nexus: &mut N) -> anyhow::Result<()> {
+    let Some(metadata) = find_game(game, flags.refresh, nexus)? else {
+        return Ok(());
     };
 
     let mods = metadata.mods_removed();
+    if let Err(e) = note_all_viewed::<ModInfoFull>(mods.iter().map(|m| m.key())) {
+        log::warn!("Couldn't note that the mods listed were viewed: {}", e);
+    }
     if flags.json {
         let pretty = serde_json::to_string_pretty(&mods)?;
         println!("{}", pretty);
@@ -100,16 +125,15 @@ pub fn removed(flags: &Flags, game: &String, nexus: &mut NexusClient) -> anyhow:
     Ok(())
 }
 
-pub fn wastebinned(flags: &Flags, game: &String, nexus: &mut NexusClient) -> anyhow::Result<()> {
-    let Some(metadata) = GameMetadata::get(game, flags.refresh, nexus) else {
-        println!(
-            "No game identified as {} found on the Nexus. Recheck the slug!",
-            game.yellow().bold()
-        );
-        return Ok(())
+pub fn wastebinned<N: NexusApi>(flags: &Flags, game: &str, nexus: &mut N) -> anyhow::Result<()> {
+    let Some(metadata) = find_game(game, flags.refresh, nexus)? else {
+        return Ok(());
     };
 
     let mods = metadata.mods_wastebinned();
+    if let Err(e) = note_all_viewed::<ModInfoFull>(mods.iter().map(|m| m.key())) {
+        log::warn!("Couldn't note that the mods listed were viewed: {}", e);
+    }
     if flags.json {
         let pretty = serde_json::to_string_pretty(&mods)?;
         println!("{}", pretty);
@@ -134,37 +158,35 @@ pub fn wastebinned(flags: &Flags, game: &String, nexus: &mut NexusClient) -> any
     Ok(())
 }
 
-pub fn untrack_removed(
+pub fn untrack_removed<N: NexusApi>(
     flags: &Flags,
-    game: &String,
-    nexus: &mut NexusClient,
+    game: &str,
+    nexus: &mut N,
 ) -> anyhow::Result<()> {
-    let Some(metadata) = GameMetadata::get(game, flags.refresh, nexus) else {
-        println!(
-            "No game identified as {} found on the Nexus. Recheck the slug!",
-            game.yellow().bold()
-        );
-        return Ok(())
+    let Some(metadata) = find_game(game, flags.refresh, nexus)? else {
+        return Ok(());
     };
 
-    let maybe = Tracked::get(&Tracked::listkey(), flags.refresh, nexus);
-    if let Some(all_tracked) = maybe {
-        let tracked: HashSet<u32> = all_tracked
-            .by_game(game)
-            .iter()
-            .map(|xs| xs.mod_id)
-            .collect();
-        let mods = metadata.mods_removed();
+    let mods = metadata.mods_removed();
+    let Some(tracked) = tracked_ids(flags, game, nexus) else {
+        if !mods.is_empty() {
+            println!("\nThese removed mods might be tracked; untrack them when you can:\n");
+        }
         for m in mods.into_iter() {
-            // we minimize api calls to the nexus
-            if tracked.contains(&m.mod_id()) {
-                match nexus.untrack(game, m.mod_id()) {
-                    Ok(_) => {
-                        println!("untracked {}", m.mod_id().red());
-                    }
-                    Err(e) => {
-                        println!("Error untracking {}:\n{:?}", m.mod_id(), e);
-                    }
+            println!("{}", m.compact_info());
+        }
+        return Ok(());
+    };
+    for m in mods.into_iter() {
+        // we minimize api calls to the nexus
+        if tracked.contains(&m.mod_id()) {
+            match nexus.untrack(game, m.mod_id()) {
+                Ok(_) => {
+                    println!("untracked {}", m.mod_id().red());
+                }
+                Err(e) if e.is_fatal() => return Err(e.into()),
+                Err(e) => {
+                    println!("Error untracking {}: {}", m.mod_id(), e);
                 }
             }
         }
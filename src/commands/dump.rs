@@ -0,0 +1,269 @@
+//! Export the cache to a portable dump, and import one. A dump is newline-delimited json:
+//! a header line saying what wrote it, then one line per record with its bucket, key, when
+//! it was fetched, and the record itself. Name the file `.gz` to compress it. Dumps are the
+//! same whichever backend wrote them, so they also move a cache from sled to SQLite.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use owo_colors::OwoColorize;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::data::{
+    cached_types, index, record_fetched_at, set_record_fetched, CachedType, Changelogs, Files,
+    ModInfoFull,
+};
+use crate::storage::migrations::{upgrade_record, SCHEMA_VERSION};
+use crate::storage::MODS;
+use crate::{storage, Cacheable, Flags};
+
+static FORMAT: &str = "modcache-dump";
+/// The version of the dump format itself, as opposed to the records in it.
+static FORMAT_VERSION: u32 = 1;
+
+/// Records about you, not about mods, stay on this machine.
+static PRIVATE: &[&str] = &["authed_users"];
+/// Your own tracked and endorsed lists. A teammate's dump shouldn't replace them, so these
+/// are only exported when asked for, and only imported into a cache that has none.
+static PERSONAL: &[&str] = &["mod_ref_lists", "endorsements"];
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Header {
+    format: String,
+    version: u32,
+    schema_version: u32,
+    exported: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Record {
+    bucket: String,
+    key: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    fetched: Option<DateTime<Utc>>,
+    value: Value,
+}
+
+fn exported_types(personal: bool) -> Vec<CachedType> {
+    cached_types()
+        .into_iter()
+        .filter(|kind| !PRIVATE.contains(&kind.bucket))
+        .filter(|kind| personal || !PERSONAL.contains(&kind.bucket))
+        .collect()
+}
+
+fn is_compressed(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "gz")
+}
+
+/// Write every shareable record in the cache to a dump file, and your own lists if asked.
+pub fn export(_flags: &Flags, path: &Path, personal: bool) -> anyhow::Result<()> {
+    let file =
+        File::create(path).with_context(|| format!("Unable to create {}", path.display()))?;
+    let out = BufWriter::new(file);
+    let counts = if is_compressed(path) {
+        let (out, counts) = write_dump(GzEncoder::new(out, Compression::default()), personal)?;
+        out.finish()?.flush()?;
+        counts
+    } else {
+        let (mut out, counts) = write_dump(out, personal)?;
+        out.flush()?;
+        counts
+    };
+
+    let total: usize = counts.values().sum();
+    println!(
+        "Exported {} records to {}:",
+        total.blue(),
+        path.display().yellow()
+    );
+    for (bucket, count) in counts.iter() {
+        println!("    {:>8} {}", count, bucket);
+    }
+    Ok(())
+}
+
+fn write_dump<W: Write>(
+    mut out: W,
+    personal: bool,
+) -> anyhow::Result<(W, BTreeMap<&'static str, usize>)> {
+    let header = Header {
+        format: FORMAT.to_string(),
+        version: FORMAT_VERSION,
+        schema_version: SCHEMA_VERSION,
+        exported: Utc::now(),
+    };
+    writeln!(out, "{}", serde_json::to_string(&header)?)?;
+
+    let mut counts = BTreeMap::new();
+    for kind in exported_types(personal) {
+        let mut count = 0;
+        for (key, json) in storage().scan(kind.bucket, "")? {
+            let value: Value = match serde_json::from_str(&json) {
+                Ok(v) => v,
+                Err(e) => {
+                    log::warn!("Skipping unreadable {}/{}: {}", kind.bucket, key, e);
+                    continue;
+                }
+            };
+            let record = Record {
+                bucket: kind.bucket.to_string(),
+                fetched: record_fetched_at(kind.bucket, &key),
+                key,
+                value,
+            };
+            writeln!(out, "{}", serde_json::to_string(&record)?)?;
+            count += 1;
+        }
+        counts.insert(kind.bucket, count);
+    }
+    Ok((out, counts))
+}
+
+/// What became of the records in a dump.
+#[derive(Debug, Default)]
+struct Tally {
+    added: usize,
+    updated: usize,
+    kept: usize,
+    rejected: usize,
+}
+
+/// Load a dump into the cache. Records we don't have are added. Where we have a record
+/// already, the newer one wins: by when the Nexus says a mod was updated, if both say,
+/// then by when each copy was fetched. Ties keep what's here.
+pub fn import(_flags: &Flags, path: &Path) -> anyhow::Result<()> {
+    let file = File::open(path).with_context(|| format!("Unable to open {}", path.display()))?;
+    let reader: Box<dyn BufRead> = if is_compressed(path) {
+        Box::new(BufReader::new(GzDecoder::new(file)))
+    } else {
+        Box::new(BufReader::new(file))
+    };
+    let mut lines = reader.lines();
+
+    let first = lines.next().transpose()?.unwrap_or_default();
+    let header: Header = serde_json::from_str(&first)
+        .ok()
+        .filter(|h: &Header| h.format == FORMAT)
+        .with_context(|| format!("{} is not a modcache dump", path.display()))?;
+    if header.version > FORMAT_VERSION || header.schema_version > SCHEMA_VERSION {
+        anyhow::bail!(
+            "{} was exported by a newer modcache; upgrade this one to import it",
+            path.display()
+        );
+    }
+    println!(
+        "Importing a dump exported {}.",
+        header.exported.format("%Y-%m-%d %H:%M").bold()
+    );
+
+    let types: BTreeMap<&str, CachedType> = exported_types(true)
+        .into_iter()
+        .map(|kind| (kind.bucket, kind))
+        .collect();
+    let mut tally = Tally::default();
+    for (idx, line) in lines.enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        // The header is line 1.
+        let lineno = idx + 2;
+        match import_record(&line, header.schema_version, &types) {
+            Ok(Imported::Added) => tally.added += 1,
+            Ok(Imported::Updated) => tally.updated += 1,
+            Ok(Imported::Kept) => tally.kept += 1,
+            Err(e) => {
+                log::warn!("Line {}: {:#}", lineno, e);
+                tally.rejected += 1;
+            }
+        }
+    }
+
+    println!(
+        "Added {}, updated {}, and kept {} records already here that were as new or newer.",
+        tally.added.blue(),
+        tally.updated.blue(),
+        tally.kept.blue()
+    );
+    if tally.rejected > 0 {
+        println!(
+            "{} records couldn't be imported, for the reasons above.",
+            tally.rejected.red()
+        );
+    }
+    Ok(())
+}
+
+enum Imported {
+    Added,
+    Updated,
+    Kept,
+}
+
+fn import_record(
+    line: &str,
+    schema_version: u32,
+    types: &BTreeMap<&str, CachedType>,
+) -> anyhow::Result<Imported> {
+    let mut record: Record = serde_json::from_str(line)?;
+    let Some(kind) = types.get(record.bucket.as_str()) else {
+        anyhow::bail!("{} is not something we import", record.bucket);
+    };
+    upgrade_record(kind.bucket, schema_version, &mut record.value)?;
+    let json = record.value.to_string();
+    let key = (kind.read)(&json).with_context(|| format!("{}/{}", kind.bucket, record.key))?;
+    if key != record.key {
+        anyhow::bail!(
+            "{}/{} holds the record for {}",
+            kind.bucket,
+            record.key,
+            key
+        );
+    }
+
+    let result = match storage().get(kind.bucket, &key)? {
+        None => Imported::Added,
+        Some(_) if PERSONAL.contains(&kind.bucket) => return Ok(Imported::Kept),
+        Some(existing) if is_newer(kind.bucket, &record, &existing) => Imported::Updated,
+        Some(_) => return Ok(Imported::Kept),
+    };
+    storage().set(kind.bucket, &key, &json)?;
+    set_record_fetched(kind.bucket, &key, record.fetched)?;
+    // The index holds each mod's file descriptions and changelogs too.
+    if kind.bucket == MODS {
+        index::update(&serde_json::from_str::<ModInfoFull>(&json)?)?;
+    } else if kind.bucket == Files::bucket_name() {
+        index::reindex(&serde_json::from_str::<Files>(&json)?.key())?;
+    } else if kind.bucket == Changelogs::bucket_name() {
+        index::reindex(&serde_json::from_str::<Changelogs>(&json)?.key())?;
+    }
+    Ok(result)
+}
+
+/// Whether an imported record is newer than the one we have.
+fn is_newer(bucket: &str, incoming: &Record, existing: &str) -> bool {
+    let Ok(existing) = serde_json::from_str::<Value>(existing) else {
+        return true;
+    };
+    let theirs = incoming.value["updated_timestamp"].as_u64();
+    let ours = existing["updated_timestamp"].as_u64();
+    if let (Some(theirs), Some(ours)) = (theirs, ours) {
+        if theirs != ours {
+            return theirs > ours;
+        }
+    }
+    match (incoming.fetched, record_fetched_at(bucket, &incoming.key)) {
+        (Some(theirs), Some(ours)) => theirs > ours,
+        (Some(_), None) => true,
+        (None, _) => false,
+    }
+}
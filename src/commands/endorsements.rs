@@ -1,3 +1,4 @@
+use anyhow::Context;
 use owo_colors::OwoColorize;
 use prettytable::{row, Table};
 
@@ -6,12 +7,16 @@ use crate::data::{
     Cacheable, CompoundKey, EndorsementList, EndorsementStatus, GameMetadata, UserEndorsement,
 };
 use crate::formatting::pluralize_mod;
-use crate::nexus::NexusClient;
+use crate::nexus::NexusApi;
 use crate::Flags;
 
 /// Display mod endorsements for a specific game, sorted by status.
-fn show_endorsements(game: &str, modlist: &[UserEndorsement], client: &mut NexusClient) {
-    let game_meta = GameMetadata::get(&game.to_string(), false, client).unwrap();
+fn show_endorsements<N: NexusApi>(
+    game: &str,
+    modlist: &[UserEndorsement],
+    client: &mut N,
+) -> anyhow::Result<()> {
+    let game_meta = GameMetadata::get(&game.to_string(), false, client)?;
     println!(
         "\n{} opinions for {}",
         modlist.len().blue(),
@@ -32,7 +37,7 @@ fn show_endorsements(game: &str, modlist: &[UserEndorsement], client: &mut Nexus
         table.set_format(*prettytable::format::consts::FORMAT_CLEAN);
         list.iter().for_each(|opinion| {
             let key = CompoundKey::new(game.to_string(), opinion.mod_id());
-            if let Some(mod_info) = ModInfoFull::get(&key, false, client) {
+            if let Ok(mod_info) = ModInfoFull::get(&key, false, client) {
                 table.add_row(row![
                     format!("{}", opinion.status()),
                     format!(
@@ -59,21 +64,21 @@ fn show_endorsements(game: &str, modlist: &[UserEndorsement], client: &mut Nexus
     emit_table(endorsed);
     println!("abstained on {}:", pluralize_mod(abstained.len()));
     emit_table(abstained);
+    Ok(())
 }
 
-pub fn handle(flags: &Flags, game: &Option<String>, nexus: &mut NexusClient) -> anyhow::Result<()> {
-    let maybe: Option<Box<EndorsementList>> =
-        EndorsementList::get(&EndorsementList::listkey(), flags.refresh, nexus);
-
-    let Some(opinions) = maybe else {
-        log::error!("Something went wrong fetching endorsements. Rerun with -v to get more details.");
-        return Ok(());
-    };
+pub fn handle<N: NexusApi>(
+    flags: &Flags,
+    game: &Option<String>,
+    nexus: &mut N,
+) -> anyhow::Result<()> {
+    let opinions = EndorsementList::get(&EndorsementList::listkey(), flags.refresh, nexus)
+        .context("Unable to fetch your endorsements.")?;
     let mapping = opinions.get_game_map();
 
     if let Some(game) = game {
         if let Some(modlist) = mapping.get(game) {
-            show_endorsements(game, modlist, nexus);
+            show_endorsements(game, modlist, nexus)?;
         } else {
             println!("No opinions expressed on mods for {}.", game);
         }
@@ -88,7 +93,7 @@ pub fn handle(flags: &Flags, game: &Option<String>, nexus: &mut NexusClient) ->
                 mapping.len().blue()
             );
             for (game, modlist) in mapping.iter() {
-                show_endorsements(game, modlist, nexus);
+                show_endorsements(game, modlist, nexus)?;
             }
         }
         return Ok(());
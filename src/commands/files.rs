@@ -1,18 +1,34 @@
 use anyhow::Result;
 
 use crate::data::{Cacheable, CompoundKey, Files};
-use crate::nexus::NexusClient;
+use crate::formatting::emit_fetched_age;
+use crate::nexus::NexusApi;
 use crate::Flags;
 
-pub fn mod_files(game: &str, mod_id: u32, flags: &Flags, nexus: &mut NexusClient) -> Result<()> {
+/// Get the file list for a mod, or nothing if the Nexus has never heard of the mod.
+fn find_files<N: NexusApi>(
+    game: &str,
+    mod_id: u32,
+    refresh: bool,
+    nexus: &mut N,
+) -> Result<Option<Box<Files>>> {
     let key = CompoundKey::new(game.to_string(), mod_id);
-    if let Some(all_files) = Files::get(&key, flags.refresh, nexus) {
+    match Files::get(&key, refresh, nexus) {
+        Ok(v) => Ok(Some(v)),
+        Err(e) if e.is_not_found() => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+pub fn mod_files<N: NexusApi>(game: &str, mod_id: u32, flags: &Flags, nexus: &mut N) -> Result<()> {
+    if let Some(all_files) = find_files(game, mod_id, flags.refresh, nexus)? {
         let all_files = *all_files;
 
         for f in all_files.files() {
             f.print_compact_info();
             println!();
         }
+        emit_fetched_age::<Files>(&all_files.key());
         // let pretty = serde_json::to_string_pretty(&files)?;
         // println!("{}", pretty);
         return Ok(());
@@ -21,9 +37,13 @@ pub fn mod_files(game: &str, mod_id: u32, flags: &Flags, nexus: &mut NexusClient
     Ok(())
 }
 
-pub fn primary_file(game: &str, mod_id: u32, flags: &Flags, nexus: &mut NexusClient) -> Result<()> {
-    let key = CompoundKey::new(game.to_string(), mod_id);
-    if let Some(modfiles) = Files::get(&key, flags.refresh, nexus) {
+pub fn primary_file<N: NexusApi>(
+    game: &str,
+    mod_id: u32,
+    flags: &Flags,
+    nexus: &mut N,
+) -> Result<()> {
+    if let Some(modfiles) = find_files(game, mod_id, flags.refresh, nexus)? {
         if let Some(found) = modfiles.primary_file() {
             println!("\n");
             found.print_full_info();
@@ -34,15 +54,14 @@ pub fn primary_file(game: &str, mod_id: u32, flags: &Flags, nexus: &mut NexusCli
     Ok(())
 }
 
-pub fn file_by_id(
+pub fn file_by_id<N: NexusApi>(
     game: &str,
     mod_id: u32,
     file_id: usize,
     flags: &Flags,
-    nexus: &mut NexusClient,
+    nexus: &mut N,
 ) -> Result<()> {
-    let key = CompoundKey::new(game.to_string(), mod_id);
-    if let Some(mod_files) = Files::get(&key, flags.refresh, nexus) {
+    if let Some(mod_files) = find_files(game, mod_id, flags.refresh, nexus)? {
         if let Some(found) = mod_files.file_by_id(file_id) {
             println!("\n");
             found.print_full_info();
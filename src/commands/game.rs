@@ -1,26 +1,31 @@
 use owo_colors::OwoColorize;
 
-use crate::data::Cacheable;
-use crate::formatting::pluralize_mod;
-use crate::nexus::NexusClient;
-use crate::{Flags, GameMetadata, Tracked};
+use super::find_game;
+use crate::data::{Cacheable, GameMetadata};
+use crate::formatting::{emit_fetched_age, pluralize_mod};
+use crate::nexus::NexusApi;
+use crate::{Flags, Tracked};
 
-pub fn handle(flags: &Flags, game: &String, nexus: &mut NexusClient) -> anyhow::Result<()> {
-    if let Some(metadata) = GameMetadata::get(game, flags.refresh, nexus) {
-        if flags.json {
-            let pretty = serde_json::to_string_pretty(&metadata)?;
-            println!("{}", pretty);
-        } else {
-            metadata.emit_fancy();
+pub fn handle<N: NexusApi>(flags: &Flags, game: &String, nexus: &mut N) -> anyhow::Result<()> {
+    let Some(metadata) = find_game(game, flags.refresh, nexus)? else {
+        return Ok(());
+    };
 
-            let mods = metadata.mods();
-            println!(
-                "There are {} in cache for this game.",
-                pluralize_mod(mods.len())
-            );
+    if flags.json {
+        let pretty = serde_json::to_string_pretty(&metadata)?;
+        println!("{}", pretty);
+    } else {
+        metadata.emit_fancy();
+        emit_fetched_age::<GameMetadata>(game);
+
+        let mods = metadata.mods();
+        println!(
+            "There are {} in cache for this game.",
+            pluralize_mod(mods.len())
+        );
 
-            let tracked = Tracked::get(&Tracked::listkey(), flags.refresh, nexus);
-            if let Some(tracked) = tracked {
+        match Tracked::get(&Tracked::listkey(), flags.refresh, nexus) {
+            Ok(tracked) => {
                 let filtered = tracked.by_game(game);
                 println!(
                     "You are tracking {} for {}.",
@@ -28,12 +33,8 @@ pub fn handle(flags: &Flags, game: &String, nexus: &mut NexusClient) -> anyhow::
                     game.yellow()
                 );
             }
+            Err(e) => log::warn!("Couldn't fetch your tracked mods: {}", e),
         }
-    } else {
-        println!(
-            "No game identified as {} found on the Nexus. Recheck the slug!",
-            game.yellow().bold()
-        );
     }
 
     Ok(())
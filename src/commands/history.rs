@@ -0,0 +1,150 @@
+use chrono::{DateTime, Local};
+use owo_colors::OwoColorize;
+use similar::{ChangeTag, TextDiff};
+
+use crate::data::{Cacheable, CompoundKey, ModHistory, ModInfoFull, ModSnapshot};
+use crate::nexus::NexusApi;
+use crate::Flags;
+
+static INDENT: &str = "                  ";
+
+/// Show how a mod has changed over the snapshots we've taken of it.
+pub fn handle<N: NexusApi>(
+    flags: &Flags,
+    game: &str,
+    mod_id: u32,
+    nexus: &mut N,
+) -> anyhow::Result<()> {
+    let key = CompoundKey::new(game.to_string(), mod_id);
+    if flags.refresh {
+        // Storing the refreshed mod adds to its history if anything changed.
+        if let Err(e) = ModInfoFull::get(&key, true, nexus) {
+            log::warn!("Couldn't refresh {}: {}", key, e);
+        }
+    }
+
+    let Some(history) = ModHistory::load(&key)? else {
+        println!(
+            "No history for {} yet. It starts the next time the mod is fetched from the Nexus.",
+            key.yellow()
+        );
+        return Ok(());
+    };
+
+    if flags.json {
+        let pretty = serde_json::to_string_pretty(&history)?;
+        println!("{}", pretty);
+        return Ok(());
+    }
+
+    let name = history
+        .snapshots
+        .last()
+        .map(|s| s.name.clone())
+        .unwrap_or_default();
+    println!(
+        "\n{} {} {}\n",
+        "History of".bold(),
+        name.green(),
+        format!("({})", key).dimmed()
+    );
+
+    let mut previous: Option<(&ModSnapshot, &str)> = None;
+    for (snapshot, description) in history.described() {
+        let when = match snapshot.seen {
+            Some(t) => t.with_timezone(&Local).format("%Y-%m-%d %H:%M").to_string(),
+            None => "before history".to_string(),
+        };
+        print!("{:<18}", when.blue());
+        match previous {
+            None => println!(
+                "first seen: {} {}, {}, {} endorsements, by {}",
+                snapshot.name.green(),
+                snapshot.version.red(),
+                snapshot.status,
+                snapshot.endorsement_count,
+                snapshot.uploaded_by.cyan()
+            ),
+            Some(before) => {
+                let changes = describe_changes(before, (snapshot, description));
+                if changes.is_empty() {
+                    println!("{}", "no changes we track".dimmed());
+                }
+                for (i, line) in changes.iter().enumerate() {
+                    if i == 0 {
+                        println!("{}", line);
+                    } else {
+                        println!("{}{}", INDENT, line);
+                    }
+                }
+            }
+        }
+        previous = Some((snapshot, description));
+    }
+    println!();
+
+    Ok(())
+}
+
+/// What changed between two snapshots, each with the description the mod had then.
+fn describe_changes(
+    (before, before_description): (&ModSnapshot, &str),
+    (after, after_description): (&ModSnapshot, &str),
+) -> Vec<String> {
+    let mut changes = Vec::new();
+    let mut changed = |label: &str, old: &str, new: &str| {
+        if old != new {
+            changes.push(format!("{} {} → {}", label.bold(), old.red(), new.green()));
+        }
+    };
+    changed("name", &before.name, &after.name);
+    changed("version", &before.version, &after.version);
+    changed(
+        "status",
+        &before.status.to_string(),
+        &after.status.to_string(),
+    );
+    changed("author", &before.author, &after.author);
+    changed("uploaded by", &before.uploaded_by, &after.uploaded_by);
+
+    if before.endorsement_count != after.endorsement_count {
+        let delta = after.endorsement_count as i64 - before.endorsement_count as i64;
+        changes.push(format!(
+            "{} {} → {} ({:+})",
+            "endorsements".bold(),
+            before.endorsement_count,
+            after.endorsement_count,
+            delta
+        ));
+    }
+    if before.updated_timestamp != after.updated_timestamp {
+        let when = DateTime::from_timestamp(after.updated_timestamp as i64, 0)
+            .map(|t| t.with_timezone(&Local).format("%Y-%m-%d").to_string())
+            .unwrap_or_default();
+        changes.push(format!("{} {}", "updated on the Nexus".bold(), when));
+    }
+    if before.summary != after.summary {
+        changes.push(format!("{}", "summary edited:".bold()));
+        changes.extend(diff_lines(&before.summary, &after.summary));
+    }
+    if before_description != after_description {
+        changes.push(format!("{}", "description edited:".bold()));
+        changes.extend(diff_lines(before_description, after_description));
+    }
+    changes
+}
+
+/// The lines that differ between two texts, marked as removed or added.
+fn diff_lines(old: &str, new: &str) -> Vec<String> {
+    TextDiff::from_lines(old, new)
+        .iter_all_changes()
+        .filter_map(|change| {
+            let line = change.value().trim_end();
+            match change.tag() {
+                ChangeTag::Delete => Some(format!("  - {}", line).red().to_string()),
+                ChangeTag::Insert => Some(format!("  + {}", line).green().to_string()),
+                ChangeTag::Equal => None,
+            }
+        })
+        .collect()
+}
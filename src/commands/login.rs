@@ -0,0 +1,61 @@
+//! Save an api key for later runs, checking it with the Nexus first, and forget it again.
+
+use std::io::{BufRead, IsTerminal};
+
+use anyhow::Context;
+use owo_colors::OwoColorize;
+
+use crate::data::Cacheable;
+use crate::nexus::NexusClient;
+use crate::{config, credentials, AuthenticatedUser, Flags};
+
+/// Ask for an api key, or read it from stdin if that's not a terminal.
+fn read_api_key() -> anyhow::Result<String> {
+    let apikey = if std::io::stdin().is_terminal() {
+        rpassword::prompt_password(
+            "Your Nexus api key, from https://www.nexusmods.com/users/myaccount?tab=api: ",
+        )?
+    } else {
+        let mut line = String::new();
+        std::io::stdin().lock().read_line(&mut line)?;
+        line
+    };
+    let apikey = apikey.trim().to_string();
+    if apikey.is_empty() {
+        anyhow::bail!("No api key given.");
+    }
+    Ok(apikey)
+}
+
+pub fn login(flags: &Flags, to_file: bool, nexus: &mut NexusClient) -> anyhow::Result<()> {
+    let apikey = read_api_key()?;
+    nexus.set_api_key(apikey.clone());
+    let user = AuthenticatedUser::get(&"ignored".to_string(), true, nexus)
+        .context("The Nexus didn't accept that api key")?;
+    let stored = credentials::save(config::profile(), &apikey, to_file)?;
+
+    if flags.json {
+        let pretty = serde_json::to_string_pretty(&user)?;
+        println!("{}", pretty);
+    } else {
+        println!("You are logged in as:\n{}", user);
+        println!("\nYour api key is saved in {}.", stored.bold());
+    }
+    if config::get().api_key().is_some() {
+        log::warn!(
+            "An api key is also set in NEXUS_API_KEY or your profile; that one is used instead."
+        );
+    }
+    Ok(())
+}
+
+pub fn logout(_flags: &Flags) -> anyhow::Result<()> {
+    let removed = credentials::remove(config::profile())?;
+    if removed.is_empty() {
+        println!("There was no saved api key to forget.");
+    }
+    for stored in removed.iter() {
+        println!("Removed the api key saved in {}.", stored.bold());
+    }
+    Ok(())
+}
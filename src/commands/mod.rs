@@ -1,16 +1,53 @@
+use owo_colors::OwoColorize;
+
+use crate::data::{Cacheable, GameMetadata};
+use crate::nexus::NexusApi;
+
+pub mod archive;
+pub mod cache;
 pub mod cleanup;
+pub mod dump;
 pub mod endorsements;
 pub mod files;
 pub mod game;
+pub mod history;
+pub mod login;
 pub mod mod_actions;
 pub mod mods; // unfortunate, but this is the best name IMO
 pub mod populate;
+pub mod quota;
 pub mod search;
+pub mod sync;
 pub mod tracked;
 pub mod validate;
 
+pub use archive::handle as handle_archive;
+pub use cache::handle as handle_cache;
 pub use endorsements::handle as handle_endorsements;
 pub use game::handle as handle_game;
+pub use history::handle as handle_history;
 pub use populate::handle as handle_populate;
+pub use quota::handle as handle_quota;
+pub use sync::handle as handle_sync;
 pub use tracked::handle as handle_tracked;
 pub use validate::validate as handle_validate;
+
+/// Look up a game by its Nexus domain name, telling the user if there's no such game.
+/// Any other failure to get it is an error.
+pub fn find_game<N: NexusApi>(
+    game: &str,
+    refresh: bool,
+    nexus: &mut N,
+) -> anyhow::Result<Option<Box<GameMetadata>>> {
+    match GameMetadata::get(&game.to_string(), refresh, nexus) {
+        Ok(metadata) => Ok(Some(metadata)),
+        Err(e) if e.is_not_found() => {
+            println!(
+                "No game identified as {} found on the Nexus. Recheck the slug!",
+                game.yellow().bold()
+            );
+            Ok(None)
+        }
+        Err(e) => Err(e.into()),
+    }
+}
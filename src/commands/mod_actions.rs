@@ -1,13 +1,13 @@
 use owo_colors::OwoColorize;
 
-use crate::nexus::NexusClient;
+use crate::nexus::NexusApi;
 use crate::Flags;
 
-pub fn track(
+pub fn track<N: NexusApi>(
     _flags: &Flags,
     game: &str,
     mod_id: u32,
-    nexus: &mut NexusClient,
+    nexus: &mut N,
 ) -> anyhow::Result<()> {
     let message = nexus.track(game, mod_id)?;
     let pretty = serde_json::to_string_pretty(&message)?;
@@ -15,11 +15,11 @@ pub fn track(
     Ok(())
 }
 
-pub fn untrack(
+pub fn untrack<N: NexusApi>(
     flags: &Flags,
     game: &str,
     ids: &[u32],
-    nexus: &mut NexusClient,
+    nexus: &mut N,
 ) -> anyhow::Result<()> {
     for mod_id in ids.iter() {
         match nexus.untrack(game, *mod_id) {
@@ -31,19 +31,20 @@ pub fn untrack(
                     println!("{}", message.message);
                 }
             }
+            Err(e) if e.is_fatal() => return Err(e.into()),
             Err(e) => {
-                println!("Error untracking {}:\n{:?}", mod_id, e);
+                println!("Error untracking {}: {}", mod_id, e);
             }
         }
     }
     Ok(())
 }
 
-pub fn endorse(
+pub fn endorse<N: NexusApi>(
     flags: &Flags,
     game: &str,
     ids: &[u32],
-    nexus: &mut NexusClient,
+    nexus: &mut N,
 ) -> anyhow::Result<()> {
     for mod_id in ids.iter() {
         match nexus.endorse(game, *mod_id) {
@@ -60,19 +61,20 @@ pub fn endorse(
                     );
                 }
             }
+            Err(e) if e.is_fatal() => return Err(e.into()),
             Err(e) => {
-                println!("Error endorsing {}:\n{:?}", mod_id, e);
+                println!("Error endorsing {}: {}", mod_id, e);
             }
         }
     }
     Ok(())
 }
 
-pub fn abstain(
+pub fn abstain<N: NexusApi>(
     flags: &Flags,
     game: &str,
     mod_id: u32,
-    nexus: &mut NexusClient,
+    nexus: &mut N,
 ) -> anyhow::Result<()> {
     let response = nexus.abstain(game, mod_id)?;
     if flags.json {
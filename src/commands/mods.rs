@@ -1,50 +1,56 @@
 use anyhow::anyhow;
-use owo_colors::OwoColorize;
 
+use super::find_game;
 use crate::data::modinfo::ModInfoFull;
-use crate::data::{Cacheable, CompoundKey};
-use crate::nexus::NexusClient;
-use crate::{Flags, GameMetadata};
+use crate::data::{note_all_viewed, note_viewed, Cacheable, CompoundKey};
+use crate::formatting::emit_fetched_age;
+use crate::nexus::NexusApi;
+use crate::Flags;
 
-pub fn show_game_mods(flags: &Flags, game: &String, nexus: &mut NexusClient) -> anyhow::Result<()> {
-    if let Some(metadata) = GameMetadata::get(game, flags.refresh, nexus) {
-        for m in metadata.mods().into_iter() {
-            if flags.json {
-                let pretty = serde_json::to_string_pretty(&m)?;
-                println!("{}", pretty);
-            } else {
-                println!("{}", m);
-            }
+pub fn show_game_mods<N: NexusApi>(flags: &Flags, game: &str, nexus: &mut N) -> anyhow::Result<()> {
+    let Some(metadata) = find_game(game, flags.refresh, nexus)? else {
+        return Ok(());
+    };
+    let mods = metadata.mods();
+    if let Err(e) = note_all_viewed::<ModInfoFull>(mods.iter().map(|m| m.key())) {
+        log::warn!("Couldn't note that {}'s mods were viewed: {}", game, e);
+    }
+    for m in mods.into_iter() {
+        if flags.json {
+            let pretty = serde_json::to_string_pretty(&m)?;
+            println!("{}", pretty);
+        } else {
+            println!("{}", m);
         }
-    } else {
-        println!(
-            "No game identified as {} found on the Nexus. Recheck the slug!",
-            game.yellow().bold()
-        );
     }
     Ok(())
 }
 
-pub fn show_single_mod(
+pub fn show_single_mod<N: NexusApi>(
     flags: &Flags,
     game: &String,
     mod_id: u32,
-    nexus: &mut NexusClient,
+    nexus: &mut N,
 ) -> anyhow::Result<()> {
     let key = CompoundKey::new(game.to_string(), mod_id);
     match ModInfoFull::get(&key, flags.refresh, nexus) {
-        Some(modinfo) => {
+        Ok(modinfo) => {
             if flags.json {
                 let pretty = serde_json::to_string_pretty(&modinfo)?;
                 println!("{}", pretty);
             } else {
                 modinfo.print_full_info();
+                emit_fetched_age::<ModInfoFull>(&key);
+            }
+            if let Err(e) = note_viewed::<ModInfoFull>(&key) {
+                log::warn!("Couldn't note that {} was viewed: {}", key, e);
             }
             Ok(())
         }
-        None => {
+        Err(e) if e.is_not_found() => {
             println!("{}/{} not found on the Nexus!", game, mod_id);
             Err(anyhow!("{}/{} not found on the Nexus!", game, mod_id))
         }
+        Err(e) => Err(anyhow::Error::new(e).context(format!("Unable to fetch {}", key))),
     }
 }
@@ -1,30 +1,188 @@
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+use anyhow::Context;
+use chrono::{DateTime, Utc};
 use owo_colors::OwoColorize;
+use serde::{Deserialize, Serialize};
 
+use super::find_game;
 use crate::data::modinfo::ModInfoFull;
 use crate::data::tracked::Tracked;
 use crate::data::{local, Cacheable, CompoundKey};
 use crate::formatting::pluralize_mod;
-use crate::nexus::NexusClient;
-use crate::{Flags, GameMetadata};
+use crate::interrupt;
+use crate::nexus::limits::RateLimits;
+use crate::nexus::{pool, NexusApi, NexusError};
+use crate::storage;
+use crate::Flags;
 
-pub fn handle(
-    flags: &Flags,
-    game: &String,
-    limit: u16,
-    nexus: &mut NexusClient,
-) -> anyhow::Result<()> {
-    let gamemeta = GameMetadata::get(game, flags.refresh, nexus);
-    if gamemeta.is_none() {
-        log::warn!("{} can't be found on the Nexus! Bailing.", game);
-        return Ok(());
+/// How many api calls populate is allowed to spend.
+#[derive(Debug, Clone, Copy)]
+pub enum Budget {
+    /// No more than this many.
+    Fixed(u16),
+    /// Whatever the hourly and daily quotas allow, less a reserve.
+    Auto,
+}
+
+impl FromStr for Budget {
+    type Err = anyhow::Error;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input.to_lowercase().as_str() {
+            "auto" => Ok(Budget::Auto),
+            v => match v.parse::<u16>() {
+                Ok(limit) => Ok(Budget::Fixed(limit)),
+                Err(_) => anyhow::bail!("budget must be `auto` or a number of api calls"),
+            },
+        }
     }
+}
+
+/// The quota when we started handing out calls, and how many we'd spent by then. The live
+/// quota only hears about a call once it's sent, and the pool hands one to every worker
+/// before any of them get that far.
+#[derive(Debug, Clone, Copy)]
+struct Snapshot {
+    remaining: u16,
+    spent: u16,
+}
 
-    let tracked = Tracked::get(&Tracked::listkey(), flags.refresh, nexus);
-    if tracked.is_none() {
-        anyhow::bail!("Unable to fetch any tracked mods.");
+impl Snapshot {
+    fn take<N: NexusApi>(spent: u16, nexus: &N) -> Self {
+        let limits = nexus.rate_limits();
+        Self {
+            remaining: limits.hourly_remaining.min(limits.daily_remaining),
+            spent,
+        }
+    }
+}
+
+impl Budget {
+    /// How many more calls we may make, given how many we've made so far.
+    fn available<N: NexusApi>(&self, spent: u16, reserve: u16, since: &Snapshot, nexus: &N) -> u16 {
+        match self {
+            Budget::Fixed(limit) => limit.saturating_sub(spent),
+            Budget::Auto => {
+                let limits = nexus.rate_limits();
+                let live = limits.hourly_remaining.min(limits.daily_remaining);
+                let ours = since
+                    .remaining
+                    .saturating_sub(spent.saturating_sub(since.spent));
+                live.min(ours).saturating_sub(reserve)
+            }
+        }
+    }
+
+    /// Fail if the reserve would keep this budget from ever spending a call, so waiting for
+    /// the next hour would never help.
+    fn check_reserve(&self, reserve: u16, limits: &RateLimits) -> anyhow::Result<()> {
+        if matches!(self, Budget::Auto) && reserve >= limits.hourly_limit {
+            anyhow::bail!(
+                "A reserve of {} api calls leaves nothing of your hourly limit of {} to populate with. Pass a smaller --reserve.",
+                reserve,
+                limits.hourly_limit
+            );
+        }
+        Ok(())
+    }
+
+    /// The most we'd spend in an hour and in a day, run every hour.
+    fn rates(&self, reserve: u16, limits: &RateLimits) -> (usize, usize) {
+        let (hourly, daily) = match self {
+            Budget::Fixed(limit) => ((*limit).min(limits.hourly_limit), limits.daily_limit),
+            Budget::Auto => (
+                limits.hourly_limit.saturating_sub(reserve),
+                limits.daily_limit.saturating_sub(reserve),
+            ),
+        };
+        (hourly.max(1) as usize, daily.max(1) as usize)
+    }
+}
+
+/// About how many hours it takes to fetch this many mods at these rates. Once the daily
+/// quota is spent, nothing happens until midnight.
+fn hours_to_fetch(count: usize, per_hour: usize, per_day: usize) -> usize {
+    if per_hour * 24 <= per_day {
+        return count.div_ceil(per_hour);
+    }
+    let full_days = (count - 1) / per_day;
+    let rest = count - full_days * per_day;
+    full_days * 24 + rest.div_ceil(per_hour)
+}
+
+static CHECKPOINTS: &str = "populate_checkpoints";
+/// How many times a mod may fail to cache before we stop trying.
+static MAX_FAILURES: u32 = 3;
+
+/// How far populating a game has gotten, kept in the kv store so an interrupted run
+/// picks up where it left off.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct Checkpoint {
+    /// The last mod we asked the Nexus about.
+    last_mod_id: Option<u32>,
+    /// Mods the Nexus says don't exist. No sense spending api calls on them again.
+    missing: Vec<u32>,
+    /// How many mods this effort has cached so far.
+    cached: usize,
+    started: DateTime<Utc>,
+    /// Mods that failed to cache, and how many times. Once one has failed `MAX_FAILURES`
+    /// times it's skipped and no longer counted as left to do, so the daemon can finish.
+    #[serde(default)]
+    failures: BTreeMap<u32, u32>,
+}
+
+impl Default for Checkpoint {
+    fn default() -> Self {
+        Self {
+            last_mod_id: None,
+            missing: Vec::new(),
+            cached: 0,
+            started: Utc::now(),
+            failures: BTreeMap::new(),
+        }
+    }
+}
+
+impl Checkpoint {
+    fn load(game: &str) -> Option<Self> {
+        let found = storage().get(CHECKPOINTS, game).ok()??;
+        serde_json::from_str(&found).ok()
+    }
+
+    fn save(&self, game: &str) -> anyhow::Result<()> {
+        storage().set(CHECKPOINTS, game, &serde_json::to_string(self)?)
+    }
+
+    fn clear(game: &str) -> anyhow::Result<()> {
+        storage().remove(CHECKPOINTS, game)
+    }
+
+    fn gave_up_on(&self, mod_id: u32) -> bool {
+        self.failures
+            .get(&mod_id)
+            .is_some_and(|count| *count >= MAX_FAILURES)
+    }
+}
+
+/// Cache as many of the tracked mods for one game as the budget allows, responding with
+/// how many are left to fetch. `spent` carries the count of api calls across games.
+fn populate_game<N: NexusApi + Clone + Send>(
+    flags: &Flags,
+    game: &str,
+    budget: Budget,
+    reserve: u16,
+    spent: &mut u16,
+    nexus: &mut N,
+) -> anyhow::Result<usize> {
+    if find_game(game, flags.refresh, nexus)?.is_none() {
+        return Ok(0);
     }
-    let tracked = tracked.unwrap();
-    let filtered = tracked.by_game(game);
+
+    let tracked = Tracked::get(&Tracked::listkey(), flags.refresh, nexus)
+        .context("Unable to fetch any tracked mods.")?;
+    let mut filtered = tracked.by_game(game);
     println!(
         "You are tracking {} total and {} for {}.",
         pluralize_mod(tracked.mods.len()),
@@ -32,44 +190,287 @@ pub fn handle(
         game.yellow()
     );
 
+    let mut checkpoint = Checkpoint::load(game).unwrap_or_default();
+    if let Some(last) = checkpoint.last_mod_id {
+        println!(
+            "Resuming after mod #{}; {} cached since {}.",
+            last,
+            pluralize_mod(checkpoint.cached),
+            checkpoint
+                .started
+                .with_timezone(&chrono::Local)
+                .format("%Y-%m-%d %H:%M")
+        );
+        if let Some(idx) = filtered.iter().position(|m| m.mod_id == last) {
+            filtered.rotate_left(idx + 1);
+        }
+    }
+
+    if matches!(budget, Budget::Auto) {
+        let limits = nexus.rate_limits();
+        println!(
+            "You have {} api calls left this hour and {} today; keeping {} in reserve.",
+            limits.hourly_remaining.bold(),
+            limits.daily_remaining.bold(),
+            reserve
+        );
+    }
+    let snapshot = Snapshot::take(*spent, nexus);
     println!(
         "Now iterating tracked mods, caching the first {} uncached found",
-        limit
+        budget.available(*spent, reserve, &snapshot, nexus)
     );
 
-    let mut mod_iter = filtered.iter();
-    let mut item = mod_iter.next();
-    let mut fetches: u16 = 0;
-
-    while item.is_some() {
-        let modinfo = item.unwrap();
-        let key = CompoundKey::new(modinfo.domain_name.clone(), modinfo.mod_id);
-        // Find the next uncached mod.
-        let maybe_mod = if local::<ModInfoFull>(&key).is_some() {
-            None
-        } else if let Some(m) = ModInfoFull::fetch(&key, nexus, None) {
-            m.store()?;
-            fetches += 1;
-            Some(m)
-        } else {
-            log::info!(
-                "   ! unable to find {}/{} for caching",
-                modinfo.domain_name,
-                modinfo.mod_id.red()
-            );
-            None
-        };
+    let todo: Vec<CompoundKey> = filtered
+        .iter()
+        .filter(|m| !checkpoint.missing.contains(&m.mod_id) && !checkpoint.gave_up_on(m.mod_id))
+        .map(|m| CompoundKey::new(m.domain_name.clone(), m.mod_id))
+        .filter(|key| local::<ModInfoFull>(key).is_none())
+        .collect();
+    let mut todo = todo.into_iter();
+    let mut failed: usize = 0;
+
+    // Mods finish in whatever order the workers get to them, so the checkpoint's last mod
+    // is approximate. Resuming skips anything cached or missing, so no harm done.
+    let nexus = &*nexus;
+    let fetched = pool::run(
+        nexus,
+        flags.jobs as usize,
+        || {
+            if budget.available(*spent, reserve, &snapshot, nexus) < 1 {
+                return None;
+            }
+            let key = todo.next()?;
+            *spent += 1;
+            Some(key)
+        },
+        |key, client| ModInfoFull::fetch(key, client, None),
+        |key, result| {
+            checkpoint.last_mod_id = Some(key.mod_id());
+            match result {
+                Ok(Some(fullmod)) => {
+                    fullmod.store()?;
+                    checkpoint.cached += 1;
+                    checkpoint.failures.remove(&key.mod_id());
+                    println!("   {} -> cache", fullmod.compact_info());
+                }
+                Ok(None) => {}
+                Err(e) if e.is_not_found() => {
+                    log::info!("   ! unable to find {} for caching", key);
+                    checkpoint.missing.push(key.mod_id());
+                }
+                // No point asking about the next mod.
+                Err(e) if e.is_fatal() => {
+                    failed += 1;
+                    return Err(e.into());
+                }
+                Err(e) => {
+                    let count = checkpoint.failures.entry(key.mod_id()).or_default();
+                    *count += 1;
+                    if *count >= MAX_FAILURES {
+                        log::warn!(
+                            "   ! unable to cache {} after {} tries; giving up: {}",
+                            key,
+                            count,
+                            e
+                        );
+                    } else {
+                        failed += 1;
+                        log::warn!("   ! unable to cache {}: {}", key, e);
+                    }
+                }
+            }
+            checkpoint.save(game)
+        },
+    );
+    if let Err(e) = fetched {
+        return Err(e.context(format!(
+            "Stopped after caching {}",
+            pluralize_mod(checkpoint.cached)
+        )));
+    }
+    // Keep counting, so we can say how much is left to do.
+    let uncached = failed + todo.count();
+
+    let gave_up = checkpoint
+        .failures
+        .values()
+        .filter(|count| **count >= MAX_FAILURES)
+        .count();
 
-        if let Some(fullmod) = maybe_mod {
-            println!("   {} -> cache", fullmod.compact_info());
+    if uncached == 0 && gave_up == 0 {
+        Checkpoint::clear(game)?;
+        println!("Nothing left to cache for {}.", game.yellow());
+    } else if uncached == 0 {
+        // Keep the checkpoint so the next run doesn't try them all over again.
+        checkpoint.last_mod_id = None;
+        checkpoint.save(game)?;
+        println!(
+            "Nothing left to cache for {}, except {} that failed {} times.",
+            game.yellow(),
+            pluralize_mod(gave_up),
+            MAX_FAILURES
+        );
+    } else {
+        let (per_hour, per_day) = budget.rates(reserve, &nexus.rate_limits());
+        let hours = hours_to_fetch(uncached, per_hour, per_day);
+        println!(
+            "{} not yet cached. At {} an hour, that's about {} more {}.",
+            pluralize_mod(uncached),
+            per_hour,
+            hours.bold(),
+            if hours == 1 { "hour" } else { "hours" }
+        );
+    }
+
+    Ok(uncached)
+}
+
+/// Populate each game in turn, sharing the budget between them.
+pub fn handle<N: NexusApi + Clone + Send>(
+    flags: &Flags,
+    games: &[String],
+    budget: Budget,
+    reserve: u16,
+    nexus: &mut N,
+) -> anyhow::Result<()> {
+    let mut spent: u16 = 0;
+    for game in games {
+        populate_game(flags, game, budget, reserve, &mut spent, nexus)?;
+    }
+    Ok(())
+}
+
+/// Keep populating until every tracked mod for these games is cached, spending each hour's
+/// quota as it arrives and sleeping in between.
+pub fn daemon<N: NexusApi + Clone + Send>(
+    flags: &Flags,
+    games: &[String],
+    reserve: u16,
+    nexus: &mut N,
+) -> anyhow::Result<()> {
+    Budget::Auto.check_reserve(reserve, &nexus.rate_limits())?;
+    loop {
+        let mut remaining = 0;
+        for game in games {
+            let mut spent: u16 = 0;
+            match populate_game(flags, game, Budget::Auto, reserve, &mut spent, nexus) {
+                Ok(uncached) => remaining += uncached,
+                Err(e) => {
+                    // Running out of quota and losing the network clear up given time, as
+                    // might anything that isn't fatal. A bad key or none, being offline or on
+                    // a cassette, and Ctrl-C won't change however long we sleep.
+                    if e.downcast_ref::<NexusError>().is_some_and(|e| {
+                        e.is_fatal()
+                            && !matches!(
+                                e,
+                                NexusError::RateLimited { .. } | NexusError::Transport { .. }
+                            )
+                    }) {
+                        return Err(e);
+                    }
+                    log::warn!("{:#}", e);
+                    remaining += 1;
+                }
+            }
+        }
+        if remaining == 0 {
+            println!("Every tracked mod is cached.");
+            return Ok(());
         }
 
-        if fetches < limit {
-            item = mod_iter.next();
+        let limits = nexus.rate_limits();
+        let until = if limits.daily_remaining <= reserve {
+            limits.daily_reset
         } else {
-            item = None;
+            limits.hourly_reset
+        };
+        let wait = (until - Utc::now())
+            .to_std()
+            .unwrap_or_default()
+            .max(std::time::Duration::from_secs(1));
+        println!(
+            "Sleeping until {} ({}).",
+            until
+                .with_timezone(&chrono::Local)
+                .format("%H:%M:%S")
+                .bold(),
+            chrono_humanize::HumanTime::from(until)
+        );
+        interrupt::sleep(wait);
+        if interrupt::requested() {
+            return Err(NexusError::Interrupted.into());
         }
     }
+}
 
-    Ok(())
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits(hourly: u16, daily: u16) -> RateLimits {
+        RateLimits {
+            hourly_limit: hourly,
+            daily_limit: daily,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn hours_round_up() {
+        assert_eq!(hours_to_fetch(1, 100, 2500), 1);
+        assert_eq!(hours_to_fetch(100, 100, 2500), 1);
+        assert_eq!(hours_to_fetch(101, 100, 2500), 2);
+        assert_eq!(hours_to_fetch(2400, 100, 2500), 24);
+    }
+
+    #[test]
+    fn hours_wait_for_midnight_once_the_day_is_spent() {
+        // 100 an hour would be 2400 a day, but the day allows only 500.
+        assert_eq!(hours_to_fetch(500, 100, 500), 5);
+        assert_eq!(hours_to_fetch(501, 100, 500), 25);
+        assert_eq!(hours_to_fetch(1000, 100, 500), 29);
+        assert_eq!(hours_to_fetch(1001, 100, 500), 49);
+    }
+
+    #[test]
+    fn hours_ignore_a_daily_limit_that_never_binds() {
+        assert_eq!(hours_to_fetch(2401, 100, 2400), 25);
+        assert_eq!(hours_to_fetch(5000, 100, 10_000), 50);
+    }
+
+    #[test]
+    fn fixed_budget_is_capped_by_the_hourly_limit() {
+        let limits = limits(100, 2500);
+        assert_eq!(Budget::Fixed(30).rates(20, &limits), (30, 2500));
+        assert_eq!(Budget::Fixed(500).rates(20, &limits), (100, 2500));
+    }
+
+    #[test]
+    fn auto_budget_keeps_the_reserve_from_both_limits() {
+        assert_eq!(Budget::Auto.rates(20, &limits(100, 2500)), (80, 2480));
+        assert_eq!(Budget::Auto.rates(20, &limits(20, 10)), (1, 1));
+    }
+
+    #[test]
+    fn auto_budget_needs_room_beyond_the_reserve() {
+        assert!(Budget::Auto.check_reserve(99, &limits(100, 2500)).is_ok());
+        let err = Budget::Auto
+            .check_reserve(100, &limits(100, 2500))
+            .unwrap_err();
+        assert!(err.to_string().contains("hourly limit of 100"), "{}", err);
+        assert!(Budget::Auto.check_reserve(500, &limits(100, 2500)).is_err());
+        // A fixed budget spends what it's given, whatever the reserve.
+        assert!(Budget::Fixed(10)
+            .check_reserve(500, &limits(100, 2500))
+            .is_ok());
+    }
+
+    #[test]
+    fn budget_parses() {
+        assert!(matches!("auto".parse::<Budget>(), Ok(Budget::Auto)));
+        assert!(matches!("AUTO".parse::<Budget>(), Ok(Budget::Auto)));
+        assert!(matches!("250".parse::<Budget>(), Ok(Budget::Fixed(250))));
+        assert!("lots".parse::<Budget>().is_err());
+    }
 }
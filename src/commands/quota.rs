@@ -0,0 +1,43 @@
+use chrono::{DateTime, Local, Utc};
+use chrono_humanize::HumanTime;
+use owo_colors::OwoColorize;
+
+use crate::nexus::NexusApi;
+use crate::Flags;
+
+fn reset_time(when: DateTime<Utc>) -> String {
+    format!(
+        "{} ({})",
+        when.with_timezone(&Local).format("%H:%M:%S"),
+        HumanTime::from(when)
+    )
+}
+
+/// Show the remaining api calls as the Nexus last reported them. Makes no requests.
+pub fn handle<N: NexusApi>(flags: &Flags, nexus: &mut N) -> anyhow::Result<()> {
+    let limits = nexus.rate_limits();
+    if flags.json {
+        let pretty = serde_json::to_string_pretty(&limits)?;
+        println!("{}", pretty);
+        return Ok(());
+    }
+
+    match limits.as_of {
+        Some(when) => println!("As of {}, you have:", HumanTime::from(when).bold()),
+        None => println!("The Nexus hasn't reported your limits yet. Assuming the defaults:"),
+    }
+    println!(
+        "    {} of {} requests remaining this hour; resets at {}",
+        limits.hourly_remaining.bold(),
+        limits.hourly_limit,
+        reset_time(limits.hourly_reset)
+    );
+    println!(
+        "    {} of {} requests remaining today; resets at {}",
+        limits.daily_remaining.bold(),
+        limits.daily_limit,
+        reset_time(limits.daily_reset)
+    );
+
+    Ok(())
+}
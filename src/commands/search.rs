@@ -1,17 +1,24 @@
+use std::collections::HashMap;
+
 use owo_colors::OwoColorize;
 
+use super::find_game;
 use crate::data::modinfo::ModInfoFull;
-use crate::data::Cacheable;
-use crate::nexus::NexusClient;
+use crate::data::query::Query;
+use crate::data::{note_all_viewed, Cacheable};
+use crate::nexus::{pool, NexusApi};
 use crate::{Flags, GameMetadata, SortByKey, SortKey};
 
-fn emit_search_results(
+fn emit_search_results<N: NexusApi + Clone + Send>(
     flags: &Flags,
     filter: &str,
     metadata: GameMetadata,
     mods: Vec<ModInfoFull>,
-    nexus: &mut NexusClient,
+    nexus: &mut N,
 ) -> anyhow::Result<()> {
+    if let Err(e) = note_all_viewed::<ModInfoFull>(mods.iter().map(|m| m.key())) {
+        log::warn!("Couldn't note that the mods found were viewed: {}", e);
+    }
     if flags.json {
         let pretty = serde_json::to_string_pretty(&mods)?;
         println!("{}", pretty);
@@ -34,16 +41,26 @@ fn emit_search_results(
             );
         }
 
+        // Refresh them all at once, then show them in order.
+        let mut refreshed: HashMap<u32, ModInfoFull> = HashMap::new();
+        if flags.refresh {
+            let mut keys = mods.iter().map(|m| m.key());
+            pool::run(
+                &*nexus,
+                flags.jobs as usize,
+                || keys.next(),
+                |key, client| ModInfoFull::get(key, true, client),
+                |key, result| {
+                    if let Ok(v) = result {
+                        refreshed.insert(key.mod_id(), *v);
+                    }
+                    Ok(())
+                },
+            )?;
+        }
+
         for m in mods.into_iter() {
-            let refreshed = if flags.refresh {
-                ModInfoFull::get(&m.key(), true, nexus)
-            } else {
-                None
-            };
-            let info = match refreshed {
-                Some(v) => *v,
-                None => m,
-            };
+            let info = refreshed.remove(&m.mod_id()).unwrap_or(m);
             println!("{info}");
         }
 
@@ -51,68 +68,60 @@ fn emit_search_results(
     }
 }
 
-pub fn by_name(
+/// Run a query against a game's cached mods and show what it finds.
+fn run_query<N: NexusApi + Clone + Send>(
     flags: &Flags,
-    game: &String,
+    game: &str,
     filter: &str,
+    query: &Query,
     sort: &SortKey,
-    nexus: &mut NexusClient,
+    nexus: &mut N,
 ) -> anyhow::Result<()> {
-    let Some(metadata) = GameMetadata::get(game, flags.refresh, nexus) else {
-        println!(
-            "No game identified as {} found on the Nexus. Recheck the slug!",
-            game.yellow().bold()
-        );
+    let Some(metadata) = find_game(game, flags.refresh, nexus)? else {
         return Ok(());
     };
 
-    let mut mods = metadata.mods_name_match(filter);
+    let mut mods = metadata.mods_matching(query)?;
     mods.sort(sort);
-    emit_search_results(flags, filter, *metadata, mods, nexus)?;
-
-    Ok(())
+    emit_search_results(flags, filter, *metadata, mods, nexus)
 }
 
-pub fn by_author(
+/// Find mods with names matching a pattern, or, with `fuzzy`, names roughly like it.
+pub fn by_name<N: NexusApi + Clone + Send>(
     flags: &Flags,
-    game: &String,
+    game: &str,
     filter: &str,
+    fuzzy: bool,
     sort: &SortKey,
-    nexus: &mut NexusClient,
+    nexus: &mut N,
 ) -> anyhow::Result<()> {
-    let Some(metadata) = GameMetadata::get(game, flags.refresh, nexus) else {
-        println!(
-            "No game identified as {} found on the Nexus. Recheck the slug!",
-            game.yellow().bold()
-        );
-        return Ok(());
+    let query = if fuzzy {
+        Query::Fuzzy(filter.to_string())
+    } else {
+        Query::name(filter)?
     };
-
-    let mut mods = metadata.mods_author_match(filter);
-    mods.sort(sort);
-    emit_search_results(flags, filter, *metadata, mods, nexus)?;
-
-    Ok(())
+    run_query(flags, game, filter, &query, sort, nexus)
 }
 
-pub fn full_text(
+pub fn by_author<N: NexusApi + Clone + Send>(
     flags: &Flags,
-    game: &String,
+    game: &str,
     filter: &str,
     sort: &SortKey,
-    nexus: &mut NexusClient,
+    nexus: &mut N,
 ) -> anyhow::Result<()> {
-    let Some(metadata) = GameMetadata::get(game, flags.refresh, nexus) else {
-        println!(
-            "No game identified as {} found on the Nexus. Recheck the slug!",
-            game.yellow().bold()
-        );
-        return Ok(());
-    };
-
-    let mut mods = metadata.mods_match_text(filter);
-    mods.sort(sort);
-    emit_search_results(flags, filter, *metadata, mods, nexus)?;
+    run_query(flags, game, filter, &Query::author(filter)?, sort, nexus)
+}
 
-    Ok(())
+/// Search with the query language: words to look for, `field:value` conditions, and
+/// AND, OR, and NOT.
+pub fn full_text<N: NexusApi + Clone + Send>(
+    flags: &Flags,
+    game: &str,
+    filter: &str,
+    sort: &SortKey,
+    nexus: &mut N,
+) -> anyhow::Result<()> {
+    let query: Query = filter.parse()?;
+    run_query(flags, game, filter, &query, sort, nexus)
 }
@@ -0,0 +1,122 @@
+use std::collections::{HashMap, HashSet};
+
+use anyhow::Context;
+use owo_colors::OwoColorize;
+
+use super::find_game;
+use crate::data::modinfo::ModInfoFull;
+use crate::data::tracked::Tracked;
+use crate::data::{fetched_at, note_fetched, Cacheable, CompoundKey, UpdatePeriod};
+use crate::formatting::pluralize_mod;
+use crate::nexus::{pool, NexusApi};
+use crate::Flags;
+
+/// Ask the Nexus which of a game's mods changed recently, then re-fetch only the ones we
+/// have cached or track. This keeps a whole game fresh for a handful of api calls, instead
+/// of one conditional request per mod.
+pub fn handle<N: NexusApi + Clone + Send>(
+    flags: &Flags,
+    game: &str,
+    period: UpdatePeriod,
+    nexus: &mut N,
+) -> anyhow::Result<()> {
+    let Some(metadata) = find_game(game, false, nexus)? else {
+        return Ok(());
+    };
+
+    let updates = nexus
+        .updated_mods(game, period)
+        .with_context(|| format!("Unable to fetch the mods updated for {}", game))?;
+
+    let cached: HashMap<u32, ModInfoFull> = metadata
+        .mods()
+        .into_iter()
+        .map(|modinfo| (modinfo.mod_id(), modinfo))
+        .collect();
+    let tracked: HashSet<u32> = match Tracked::get(&Tracked::listkey(), flags.refresh, nexus) {
+        Ok(tracked) => tracked.by_game(game).iter().map(|m| m.mod_id).collect(),
+        Err(e) => {
+            log::warn!(
+                "Couldn't fetch your tracked mods; syncing cached mods only. {}",
+                e
+            );
+            HashSet::new()
+        }
+    };
+
+    // A cached mod is stale if the Nexus has seen activity since we fetched our copy. Activity
+    // doesn't always change the mod's own updated time, so that's only the fallback for mods
+    // cached before we noted fetch times. A tracked mod we never cached is worth fetching
+    // now that we know it's active.
+    let changed: Vec<u32> = updates
+        .iter()
+        .filter(|update| match cached.get(&update.mod_id) {
+            Some(modinfo) => {
+                let checked = fetched_at::<ModInfoFull>(&modinfo.key())
+                    .map_or(modinfo.updated_timestamp(), |when| when.timestamp() as u64);
+                update.latest() > checked
+            }
+            None => tracked.contains(&update.mod_id),
+        })
+        .map(|update| update.mod_id)
+        .collect();
+    println!(
+        "The Nexus reports {} for {} changed in the last {}; {} of yours need refreshing.",
+        pluralize_mod(updates.len()),
+        game.yellow(),
+        match period {
+            UpdatePeriod::Day => "day",
+            UpdatePeriod::Week => "week",
+            UpdatePeriod::Month => "month",
+        },
+        changed.len().bold()
+    );
+
+    let mut refreshed: usize = 0;
+    let mut changed = changed.into_iter();
+    let fetched = pool::run(
+        &*nexus,
+        flags.jobs as usize,
+        || changed.next(),
+        |mod_id, client| {
+            let key = CompoundKey::new(game.to_string(), *mod_id);
+            let etag = cached.get(mod_id).map(|modinfo| modinfo.etag().to_string());
+            ModInfoFull::fetch(&key, client, etag)
+        },
+        |mod_id, result| {
+            match result {
+                Ok(Some(fetched)) => {
+                    let merged = match cached.get(&mod_id) {
+                        Some(modinfo) => modinfo.update(&fetched),
+                        None => *fetched,
+                    };
+                    merged.store()?;
+                    refreshed += 1;
+                    println!("   {} -> cache", merged.compact_info());
+                }
+                Ok(None) => {
+                    log::info!("   {}/{} hasn't changed after all", game, mod_id);
+                    let key = CompoundKey::new(game.to_string(), mod_id);
+                    if let Err(e) = note_fetched::<ModInfoFull>(&key) {
+                        log::warn!("Failed to note when {key} was checked! {e:?}");
+                    }
+                }
+                // Every other request would fail the same way.
+                Err(e) if e.is_fatal() => return Err(e.into()),
+                Err(e) => {
+                    log::warn!("   ! unable to refresh {}/{}: {}", game, mod_id.red(), e);
+                }
+            }
+            Ok(())
+        },
+    );
+    if let Err(e) = fetched {
+        return Err(e.context(format!(
+            "Stopped after refreshing {}",
+            pluralize_mod(refreshed)
+        )));
+    }
+
+    println!("Refreshed {}.", pluralize_mod(refreshed));
+    Ok(())
+}
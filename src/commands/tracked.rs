@@ -1,23 +1,24 @@
 use std::collections::HashMap;
 
+use anyhow::Context;
 use itertools::Itertools;
 use owo_colors::OwoColorize;
 
+use super::find_game;
 use crate::data::modinfo::{ModInfoFull, ModStatus};
 use crate::data::tracked::Tracked;
-use crate::data::{local, Cacheable, CompoundKey};
-use crate::formatting::{emit_modlist_with_caption, pluralize_mod};
-use crate::nexus::NexusClient;
-use crate::{Flags, GameMetadata};
+use crate::data::{local, note_all_viewed, Cacheable, CompoundKey};
+use crate::formatting::{emit_fetched_age, emit_modlist_with_caption, pluralize_mod};
+use crate::nexus::NexusApi;
+use crate::Flags;
 
-pub fn handle(flags: &Flags, game: &Option<String>, nexus: &mut NexusClient) -> anyhow::Result<()> {
-    let maybe = Tracked::get(&Tracked::listkey(), flags.refresh, nexus);
-    let Some(tracked) = maybe else {
-        log::error!(
-            "Something went wrong fetching tracked mods. Rerun with -v to get more details."
-        );
-        return Ok(());
-    };
+pub fn handle<N: NexusApi>(
+    flags: &Flags,
+    game: &Option<String>,
+    nexus: &mut N,
+) -> anyhow::Result<()> {
+    let tracked = Tracked::get(&Tracked::listkey(), flags.refresh, nexus)
+        .context("Unable to fetch your tracked mods.")?;
 
     if game.is_none() {
         if flags.json {
@@ -25,6 +26,7 @@ pub fn handle(flags: &Flags, game: &Option<String>, nexus: &mut NexusClient) ->
             println!("{}", pretty);
         } else {
             println!("{}", tracked);
+            emit_fetched_age::<Tracked>(&Tracked::listkey());
         }
         return Ok(());
     }
@@ -36,7 +38,9 @@ pub fn handle(flags: &Flags, game: &Option<String>, nexus: &mut NexusClient) ->
         return Ok(());
     }
 
-    let mut game_meta = GameMetadata::get(&game, flags.refresh, nexus).unwrap();
+    let Some(mut game_meta) = find_game(&game, flags.refresh, nexus)? else {
+        return Ok(());
+    };
     // bucket mods by category, treating removed and wastebinned mods separately.
     let mut uncached = 0;
     // I note that this list of special-cases is looking very pattern-like.
@@ -44,9 +48,11 @@ pub fn handle(flags: &Flags, game: &Option<String>, nexus: &mut NexusClient) ->
     let mut removed: Vec<ModInfoFull> = Vec::new();
     let mut moderated: Vec<ModInfoFull> = Vec::new();
     let mut cat_map: HashMap<u16, Vec<ModInfoFull>> = HashMap::new();
+    let mut shown: Vec<CompoundKey> = Vec::new();
     filtered.iter().for_each(|m| {
         let key = CompoundKey::new(game.clone(), m.mod_id);
         if let Some(mod_info) = local::<ModInfoFull>(&key) {
+            shown.push(key);
             let bucket = cat_map.entry(mod_info.category_id()).or_default();
             match mod_info.status() {
                 ModStatus::Wastebinned => {
@@ -67,6 +73,10 @@ pub fn handle(flags: &Flags, game: &Option<String>, nexus: &mut NexusClient) ->
         }
     });
 
+    if let Err(e) = note_all_viewed::<ModInfoFull>(shown) {
+        log::warn!("Couldn't note that your tracked mods were viewed: {}", e);
+    }
+
     for (catid, mods) in cat_map.into_iter().sorted_by_key(|xs| xs.0) {
         if let Some(category) = game_meta.category_from_id(catid) {
             println!("----- {}:", category.name().purple());
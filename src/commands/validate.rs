@@ -1,11 +1,11 @@
 use owo_colors::OwoColorize;
 
 use crate::data::Cacheable;
-use crate::nexus::NexusClient;
+use crate::nexus::NexusApi;
 use crate::{AuthenticatedUser, Flags};
 
-pub fn validate(flags: &Flags, nexus: &mut NexusClient) -> anyhow::Result<()> {
-    if let Some(user) = AuthenticatedUser::fetch(&"ignored", nexus, None) {
+pub fn validate<N: NexusApi>(flags: &Flags, nexus: &mut N) -> anyhow::Result<()> {
+    if let Some(user) = AuthenticatedUser::fetch(&"ignored".to_string(), nexus, None)? {
         if flags.json {
             let pretty = serde_json::to_string_pretty(&user)?;
             println!("{}", pretty);
@@ -0,0 +1,301 @@
+//! Settings from `config.toml` in modcache's config directory: on Linux that's
+//! `~/.config/modcache/config.toml`, or wherever `XDG_CONFIG_HOME` says. Set
+//! `MODCACHE_CONFIG` to use a file somewhere else. Every setting is optional, and the
+//! command line and environment win over anything set here.
+//!
+//! Several people can share one cache with profiles. Each `[profiles.<name>]` table has its
+//! own api key and may override any of the settings above it. A profile's tracked and
+//! endorsement lists are its own; mods, games, and everything else are shared.
+
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use anyhow::Context;
+use once_cell::sync::OnceCell;
+use serde::Deserialize;
+
+use crate::storage::Backend;
+
+static CONFIG: OnceCell<Config> = OnceCell::new();
+
+/// Aliases everyone gets, whatever their config says.
+static BUILTIN_ALIASES: &[(&str, &str)] = &[
+    ("sse", "skyrimspecialedition"),
+    ("fo4", "fallout4"),
+    ("sf", "starfield"),
+];
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// The game commands act on when none is named.
+    pub game: String,
+    /// Short names for games, like `fo4` for `fallout4`.
+    pub aliases: BTreeMap<String, String>,
+    /// How search results are sorted unless `--sort` says otherwise. Without this, full-text
+    /// searches put the best matches first and the others sort by id.
+    pub sort: Option<String>,
+    /// Set to `json` to emit json everywhere, as if every command were passed `--json`.
+    pub output: Output,
+    /// Where the cache lives; `NEXUS_CACHE_PATH` overrides this.
+    pub cache_path: Option<String>,
+    /// Which backend keeps the cache, `sled` or `sqlite`; `NEXUS_CACHE_BACKEND` overrides this.
+    pub cache_backend: Option<String>,
+    /// How many api calls `populate --budget auto` leaves for interactive use.
+    pub reserve: u16,
+    /// Named profiles, each with its own account.
+    pub profiles: BTreeMap<String, Profile>,
+    /// The profile chosen for this run, if any.
+    #[serde(skip)]
+    pub profile: Option<String>,
+    /// The backend chosen for this run, from `NEXUS_CACHE_BACKEND` or `cache_backend`.
+    #[serde(skip)]
+    pub backend: Backend,
+}
+
+/// One person's settings. Anything left out comes from the rest of the config.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Profile {
+    /// The Nexus api key for this profile's account.
+    pub api_key: Option<String>,
+    pub game: Option<String>,
+    pub sort: Option<String>,
+    pub output: Option<Output>,
+    pub reserve: Option<u16>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            game: "skyrimspecialedition".to_string(),
+            aliases: BTreeMap::new(),
+            sort: None,
+            output: Output::Text,
+            cache_path: None,
+            cache_backend: None,
+            reserve: 10,
+            profiles: BTreeMap::new(),
+            profile: None,
+            backend: Backend::default(),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Output {
+    #[default]
+    Text,
+    Json,
+}
+
+impl FromStr for Output {
+    type Err = anyhow::Error;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input.to_lowercase().as_str() {
+            "text" => Ok(Output::Text),
+            "json" => Ok(Output::Json),
+            _ => anyhow::bail!("output must be `text` or `json`"),
+        }
+    }
+}
+
+impl Config {
+    /// Where we look for the config file.
+    pub fn path() -> Option<PathBuf> {
+        match std::env::var("MODCACHE_CONFIG") {
+            Ok(path) => Some(PathBuf::from(path)),
+            Err(_) => dirs::config_dir().map(|dir| dir.join("modcache").join("config.toml")),
+        }
+    }
+
+    /// Read the config file. No file means the defaults.
+    pub fn load() -> anyhow::Result<Self> {
+        let Some(path) = Self::path().filter(|p| p.exists()) else {
+            return Ok(Self::default());
+        };
+        let text = std::fs::read_to_string(&path)
+            .with_context(|| format!("Unable to read {}", path.display()))?;
+        let config: Config = toml::from_str(&text)
+            .with_context(|| format!("Unable to make sense of {}", path.display()))?;
+        Ok(config)
+    }
+
+    /// Switch to a named profile, letting its settings override the rest.
+    pub fn use_profile(&mut self, name: &str) -> anyhow::Result<()> {
+        let Some(profile) = self.profiles.get(name).cloned() else {
+            anyhow::bail!(
+                "There's no profile named `{}`. Add a [profiles.{}] table to {}.",
+                name,
+                name,
+                Self::path()
+                    .map(|p| p.display().to_string())
+                    .unwrap_or_else(|| "your config file".to_string())
+            );
+        };
+        if let Some(game) = profile.game {
+            self.game = game;
+        }
+        if let Some(sort) = profile.sort {
+            self.sort = Some(sort);
+        }
+        if let Some(output) = profile.output {
+            self.output = output;
+        }
+        if let Some(reserve) = profile.reserve {
+            self.reserve = reserve;
+        }
+        self.profile = Some(name.to_string());
+        Ok(())
+    }
+
+    /// The api key for the profile in use. Without a profile, that's `NEXUS_API_KEY`. A
+    /// profile never borrows that key, so nobody acts on the Nexus as someone else.
+    pub fn api_key(&self) -> Option<String> {
+        match &self.profile {
+            Some(name) => self.profiles.get(name).and_then(|p| p.api_key.clone()),
+            None => std::env::var("NEXUS_API_KEY").ok(),
+        }
+    }
+
+    /// The full Nexus name for a game, if this is an alias for one.
+    pub fn resolve_game(&self, game: &str) -> String {
+        if let Some(full) = self.aliases.get(game) {
+            return full.clone();
+        }
+        BUILTIN_ALIASES
+            .iter()
+            .find(|(alias, _full)| *alias == game)
+            .map(|(_alias, full)| full.to_string())
+            .unwrap_or_else(|| game.to_string())
+    }
+}
+
+/// Load the config for the rest of the run. Call this before parsing the command line,
+/// because the defaults there come from here. That includes the profile's defaults, so the
+/// profile is picked out of the arguments ahead of clap.
+pub fn init() -> anyhow::Result<()> {
+    let mut config = Config::load()?;
+    if let Some(name) = profile_from_args().or_else(|| std::env::var("MODCACHE_PROFILE").ok()) {
+        config.use_profile(&name)?;
+    }
+    config.game = config.resolve_game(&config.game);
+    let backend = std::env::var("NEXUS_CACHE_BACKEND")
+        .ok()
+        .or_else(|| config.cache_backend.clone());
+    if let Some(name) = backend {
+        config.backend = name
+            .parse()
+            .with_context(|| format!("Unable to keep the cache in `{}`", name))?;
+    }
+    // Only the first load counts.
+    let _ = CONFIG.set(config);
+    Ok(())
+}
+
+/// The value of `--profile`, if it's on the command line.
+fn profile_from_args() -> Option<String> {
+    let mut args = std::env::args().skip(1).take_while(|arg| arg != "--");
+    while let Some(arg) = args.next() {
+        if arg == "--profile" {
+            return args.next();
+        }
+        if let Some(name) = arg.strip_prefix("--profile=") {
+            return Some(name.to_string());
+        }
+    }
+    None
+}
+
+/// The config for this run, or the defaults if it was never loaded.
+pub fn get() -> &'static Config {
+    CONFIG.get_or_init(Config::default)
+}
+
+/// The name of the profile in use, or the empty string for none.
+pub fn profile() -> &'static str {
+    get().profile.as_deref().unwrap_or_default()
+}
+
+/// Parse a game named on the command line, turning aliases into the Nexus's own names.
+pub fn game_name(input: &str) -> Result<String, Infallible> {
+    Ok(get().resolve_game(input))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Config {
+        toml::from_str(text).expect("config parses")
+    }
+
+    #[test]
+    fn aliases_expand() {
+        let config = parse(
+            r#"
+            [aliases]
+            sky = "skyrim"
+            sse = "skyrim"
+            "#,
+        );
+        assert_eq!(config.resolve_game("sky"), "skyrim");
+        assert_eq!(config.resolve_game("fo4"), "fallout4");
+        assert_eq!(config.resolve_game("starfield"), "starfield");
+        assert_eq!(config.resolve_game("SKY"), "SKY");
+        // The config's own aliases beat the built-in ones.
+        assert_eq!(config.resolve_game("sse"), "skyrim");
+        assert_eq!(
+            Config::default().resolve_game("sse"),
+            "skyrimspecialedition"
+        );
+    }
+
+    #[test]
+    fn use_profile_overrides_what_it_sets() {
+        let mut config = parse(
+            r#"
+            game = "fallout4"
+            sort = "name"
+            reserve = 25
+
+            [profiles.sam]
+            api_key = "sams-key"
+            game = "starfield"
+            output = "json"
+            "#,
+        );
+        config.use_profile("sam").unwrap();
+        assert_eq!(config.profile.as_deref(), Some("sam"));
+        assert_eq!(config.game, "starfield");
+        assert_eq!(config.output, Output::Json);
+        assert_eq!(config.sort.as_deref(), Some("name"));
+        assert_eq!(config.reserve, 25);
+        assert_eq!(config.api_key().as_deref(), Some("sams-key"));
+    }
+
+    #[test]
+    fn a_profile_without_a_key_has_none() {
+        let mut config = parse("[profiles.guest]\n");
+        config.use_profile("guest").unwrap();
+        assert_eq!(config.profile.as_deref(), Some("guest"));
+        assert_eq!(config.api_key(), None);
+    }
+
+    #[test]
+    fn an_unknown_profile_is_an_error() {
+        let mut config = parse("[profiles.sam]\n");
+        let err = config.use_profile("alex").unwrap_err();
+        assert!(
+            err.to_string().contains("no profile named `alex`"),
+            "{}",
+            err
+        );
+        assert_eq!(config.profile, None);
+        assert_eq!(config.game, "skyrimspecialedition");
+    }
+}
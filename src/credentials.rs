@@ -0,0 +1,249 @@
+//! Api keys saved by `modcache login`, so they needn't sit in plaintext in `.env`. Keys go in
+//! the system keyring when there is one: the Secret Service on Linux, the Keychain on macOS,
+//! the Credential Manager on Windows. Headless boxes often have no keyring, so there the key
+//! is encrypted with a passphrase and kept in `credentials.json` beside the config file. Set
+//! `MODCACHE_PASSPHRASE` to unlock it without a prompt, as a daemon must.
+
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use argon2::Argon2;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use serde::{Deserialize, Serialize};
+
+use crate::config::{self, Config};
+use crate::data::profile_key;
+
+/// What we're called in the keyring.
+static SERVICE: &str = "modcache";
+static CREDENTIALS_FILE: &str = "credentials.json";
+
+/// Where a key was stored.
+#[derive(Debug, Clone)]
+pub enum Stored {
+    Keyring,
+    File(PathBuf),
+}
+
+impl Display for Stored {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Stored::Keyring => write!(f, "your system keyring"),
+            Stored::File(path) => write!(f, "the encrypted file {}", path.display()),
+        }
+    }
+}
+
+/// An api key encrypted with a key derived from a passphrase. Everything is base64.
+#[derive(Serialize, Deserialize, Debug)]
+struct Sealed {
+    salt: String,
+    nonce: String,
+    key: String,
+}
+
+impl Sealed {
+    fn seal(apikey: &str, passphrase: &str) -> anyhow::Result<Self> {
+        let mut salt = [0u8; 16];
+        OsRng.fill_bytes(&mut salt);
+        let cipher = ChaCha20Poly1305::new(&derive_key(passphrase, &salt)?);
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let sealed = cipher
+            .encrypt(&nonce, apikey.as_bytes())
+            .map_err(|_| anyhow::anyhow!("unable to encrypt the api key"))?;
+        Ok(Self {
+            salt: BASE64.encode(salt),
+            nonce: BASE64.encode(nonce),
+            key: BASE64.encode(sealed),
+        })
+    }
+
+    fn open(&self, passphrase: &str) -> anyhow::Result<String> {
+        let salt = BASE64.decode(&self.salt)?;
+        let nonce = BASE64.decode(&self.nonce)?;
+        if nonce.len() != 12 {
+            anyhow::bail!("the stored nonce is the wrong length");
+        }
+        let cipher = ChaCha20Poly1305::new(&derive_key(passphrase, &salt)?);
+        let apikey = cipher
+            .decrypt(
+                Nonce::from_slice(&nonce),
+                BASE64.decode(&self.key)?.as_slice(),
+            )
+            .map_err(|_| anyhow::anyhow!("wrong passphrase, or the stored key is damaged"))?;
+        Ok(String::from_utf8(apikey)?)
+    }
+}
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> anyhow::Result<Key> {
+    let mut key = Key::default();
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| anyhow::anyhow!("unable to derive a key from the passphrase: {}", e))?;
+    Ok(key)
+}
+
+/// The name a profile's key is stored under, in the keyring and the file alike.
+fn account(profile: &str) -> String {
+    profile_key("api_key", profile)
+}
+
+fn entry(profile: &str) -> keyring::Result<keyring::Entry> {
+    keyring::Entry::new(SERVICE, &account(profile))
+}
+
+/// The encrypted file lives beside the config file.
+fn credentials_path() -> Option<PathBuf> {
+    Config::path().and_then(|p| p.parent().map(|dir| dir.join(CREDENTIALS_FILE)))
+}
+
+fn read_file(path: &Path) -> anyhow::Result<BTreeMap<String, Sealed>> {
+    if !path.exists() {
+        return Ok(BTreeMap::new());
+    }
+    let json = std::fs::read_to_string(path)
+        .with_context(|| format!("Unable to read {}", path.display()))?;
+    serde_json::from_str(&json)
+        .with_context(|| format!("Unable to make sense of {}", path.display()))
+}
+
+/// Write the file readable only by its owner, or remove it once it holds nothing.
+fn write_file(path: &Path, sealed: &BTreeMap<String, Sealed>) -> anyhow::Result<()> {
+    if sealed.is_empty() {
+        std::fs::remove_file(path)?;
+        return Ok(());
+    }
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options
+        .open(path)
+        .with_context(|| format!("Unable to write {}", path.display()))?;
+    // The mode above only applies to a new file. Tighten an existing one before writing.
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(std::fs::Permissions::from_mode(0o600))?;
+    }
+    file.write_all(serde_json::to_string_pretty(sealed)?.as_bytes())?;
+    Ok(())
+}
+
+/// The passphrase for the encrypted file, from `MODCACHE_PASSPHRASE` or the terminal.
+/// A new passphrase is asked for twice.
+fn passphrase(path: &Path, new: bool) -> anyhow::Result<String> {
+    if let Ok(passphrase) = std::env::var("MODCACHE_PASSPHRASE") {
+        if passphrase.is_empty() {
+            anyhow::bail!("MODCACHE_PASSPHRASE is set, but empty. The passphrase can't be empty.");
+        }
+        return Ok(passphrase);
+    }
+    let prompt = if new {
+        format!("Choose a passphrase for {}: ", path.display())
+    } else {
+        format!("Passphrase for {}: ", path.display())
+    };
+    let passphrase = rpassword::prompt_password(prompt)
+        .context("Unable to ask for a passphrase; set MODCACHE_PASSPHRASE instead")?;
+    if passphrase.is_empty() {
+        anyhow::bail!("The passphrase can't be empty.");
+    }
+    if new && rpassword::prompt_password("Again, to be sure: ")? != passphrase {
+        anyhow::bail!("The passphrases don't match.");
+    }
+    Ok(passphrase)
+}
+
+/// Save a profile's api key, in the keyring if there is one, or else the encrypted file.
+/// Pass `to_file` to use the file even when there's a keyring.
+pub fn save(profile: &str, apikey: &str, to_file: bool) -> anyhow::Result<Stored> {
+    let path = credentials_path().context("There's no config directory to keep the key in.")?;
+    let mut sealed = read_file(&path)?;
+    if !to_file {
+        match entry(profile).and_then(|e| e.set_password(apikey)) {
+            Ok(()) => {
+                // Don't leave an old key behind to be found later.
+                if sealed.remove(&account(profile)).is_some() {
+                    write_file(&path, &sealed)?;
+                }
+                return Ok(Stored::Keyring);
+            }
+            Err(e) => log::info!("No keyring to keep the key in ({}); using a file.", e),
+        }
+    }
+
+    let passphrase = passphrase(&path, true)?;
+    // The keyring is looked in first, so an old key there would shadow this one.
+    if let Ok(entry) = entry(profile) {
+        let _ = entry.delete_credential();
+    }
+    sealed.insert(account(profile), Sealed::seal(apikey, &passphrase)?);
+    write_file(&path, &sealed)?;
+    Ok(Stored::File(path))
+}
+
+/// Look up a profile's saved api key, trying the keyring first.
+pub fn load(profile: &str) -> anyhow::Result<Option<String>> {
+    match entry(profile).and_then(|e| e.get_password()) {
+        Ok(apikey) => return Ok(Some(apikey)),
+        Err(keyring::Error::NoEntry) => {}
+        Err(e) => log::debug!("Unable to read the keyring: {}", e),
+    }
+
+    let Some(path) = credentials_path() else {
+        return Ok(None);
+    };
+    let Some(found) = read_file(&path)?.remove(&account(profile)) else {
+        return Ok(None);
+    };
+    let apikey = found
+        .open(&passphrase(&path, false)?)
+        .with_context(|| format!("Unable to unlock {}", path.display()))?;
+    Ok(Some(apikey))
+}
+
+/// Forget a profile's saved api key, wherever it is. Responds with where it was found.
+pub fn remove(profile: &str) -> anyhow::Result<Vec<Stored>> {
+    let mut removed = Vec::new();
+    match entry(profile).and_then(|e| e.delete_credential()) {
+        Ok(()) => removed.push(Stored::Keyring),
+        Err(keyring::Error::NoEntry) => {}
+        Err(e) => log::debug!("Unable to reach the keyring: {}", e),
+    }
+
+    if let Some(path) = credentials_path() {
+        let mut sealed = read_file(&path)?;
+        if sealed.remove(&account(profile)).is_some() {
+            write_file(&path, &sealed)?;
+            removed.push(Stored::File(path));
+        }
+    }
+    Ok(removed)
+}
+
+/// The saved api key for the profile in use, for the Nexus client to ask for when it first
+/// needs one. Failures are warnings, because they only matter if a request needs the key.
+pub fn stored_key() -> Option<String> {
+    match load(config::profile()) {
+        Ok(apikey) => apikey,
+        Err(e) => {
+            log::warn!("{:#}", e);
+            None
+        }
+    }
+}
@@ -0,0 +1,74 @@
+//! An archive of the mods we depend on, as they were while they were published. When an
+//! author hides or removes a mod, the Nexus stops telling us anything useful about it, and
+//! a refresh would leave us with a name and a status. The archive keeps the description,
+//! file list, and changelogs from the last time the mod was published, so the
+//! documentation for a load-order-critical mod outlives the mod's page.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::{local, Changelogs, CompoundKey, Files, ModInfoFull, ModStatus};
+use crate::{storage, Cacheable};
+
+/// The bucket archived mods are kept in, under the same keys as the mods.
+pub static ARCHIVE: &str = "archive";
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ArchivedMod {
+    pub archived: DateTime<Utc>,
+    pub modinfo: ModInfoFull,
+    pub files: Option<Files>,
+    pub changelogs: Option<Changelogs>,
+}
+
+impl ArchivedMod {
+    pub fn load(key: &CompoundKey) -> anyhow::Result<Option<Self>> {
+        match storage().get(ARCHIVE, &key.to_string())? {
+            Some(json) => Ok(Some(serde_json::from_str(&json)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Every archived mod, or every one for a game.
+    pub fn all(game: Option<&str>) -> anyhow::Result<Vec<Self>> {
+        let prefix = game.map(|g| format!("{}/", g)).unwrap_or_default();
+        let mut found = Vec::new();
+        for (key, json) in storage().scan(ARCHIVE, &prefix)? {
+            match serde_json::from_str::<Self>(&json) {
+                Ok(archived) => found.push(archived),
+                Err(e) => log::warn!("Can't make sense of the archived {}: {}", key, e),
+            }
+        }
+        Ok(found)
+    }
+
+    /// Archive what we have cached for a mod: its info, file list, and changelogs. Only a
+    /// published mod is archived, so an archive is never replaced by what's left of a mod
+    /// after it's gone. Responds with whether anything was archived.
+    pub fn archive_cached(key: &CompoundKey) -> anyhow::Result<bool> {
+        let Some(modinfo) = local::<ModInfoFull>(key) else {
+            return Ok(false);
+        };
+        if modinfo.status() != ModStatus::Published {
+            return Ok(false);
+        }
+        let archived = ArchivedMod {
+            archived: Utc::now(),
+            modinfo: *modinfo,
+            files: local::<Files>(key).map(|f| *f),
+            changelogs: local::<Changelogs>(key).map(|c| *c),
+        };
+        storage().set(
+            ARCHIVE,
+            &key.to_string(),
+            &serde_json::to_string(&archived)?,
+        )?;
+        Ok(true)
+    }
+
+    /// Read a stored archive entry, responding with the key it belongs under.
+    pub fn read_key(json: &str) -> anyhow::Result<String> {
+        let archived: ArchivedMod = serde_json::from_str(json)?;
+        Ok(archived.modinfo.key().to_string())
+    }
+}
@@ -3,10 +3,9 @@
 use std::collections::HashMap;
 use std::fmt::Display;
 
-use kv::Json;
 use serde::{Deserialize, Serialize};
 
-use crate::nexus::NexusClient;
+use crate::nexus::{NexusApi, NexusError};
 use crate::{Cacheable, CompoundKey};
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
@@ -49,6 +48,10 @@ impl Cacheable for Changelogs {
         "changelogs"
     }
 
+    fn max_age() -> chrono::Duration {
+        chrono::Duration::days(1)
+    }
+
     fn key(&self) -> CompoundKey {
         CompoundKey {
             domain_name: self.domain_name.clone(),
@@ -64,35 +67,34 @@ impl Cacheable for Changelogs {
         self.etag = etag.to_string()
     }
 
-    fn get(key: &CompoundKey, refresh: bool, nexus: &mut NexusClient) -> Option<Box<Self>> {
-        super::get::<Self>(key, refresh, nexus)
+    fn get<N: NexusApi>(
+        key: &CompoundKey,
+        refresh: bool,
+        nexus: &mut N,
+    ) -> Result<Box<Self>, NexusError> {
+        super::get::<Self, N>(key, refresh, nexus)
     }
 
-    fn fetch(
+    fn fetch<N: NexusApi>(
         key: &CompoundKey,
-        nexus: &mut NexusClient,
+        nexus: &mut N,
         etag: Option<String>,
-    ) -> Option<Box<Self>> {
+    ) -> Result<Option<Box<Self>>, NexusError> {
         // The game & modid are *not* included in the response data. This is okay, but I want it.
-        nexus
-            .changelogs(&key.domain_name, key.mod_id, etag)
-            .map(|mut v| {
-                v.domain_name = key.domain_name.clone();
-                v.mod_id = key.mod_id;
-                Box::new(v)
-            })
+        let found = nexus.changelogs(&key.domain_name, key.mod_id, etag)?;
+        Ok(found.map(|mut v| {
+            v.domain_name = key.domain_name.clone();
+            v.mod_id = key.mod_id;
+            Box::new(v)
+        }))
     }
 
     fn store(&self) -> anyhow::Result<usize> {
-        let bucket = super::bucket::<Self>().unwrap();
-        if bucket
-            .set(&&*self.key().to_string(), &Json(self.clone()))
-            .is_ok()
-        {
-            Ok(1)
-        } else {
-            Ok(0)
+        let stored = super::save(self).unwrap_or(0);
+        if let Err(e) = super::index::reindex(&self.key()) {
+            log::warn!("Couldn't index the changelogs for {}: {}", self.key(), e);
         }
+        Ok(stored)
     }
 
     fn update(&self, other: &Self) -> Self {
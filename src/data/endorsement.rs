@@ -1,15 +1,16 @@
 use std::collections::HashMap;
 use std::fmt::Display;
 
-use kv::Json;
 use owo_colors::OwoColorize;
 use serde::{Deserialize, Serialize};
 
+use super::profile_key;
+use crate::config;
 use crate::formatting::pluralize_mod;
-use crate::nexus::NexusClient;
+use crate::nexus::{NexusApi, NexusError};
 use crate::Cacheable;
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
 pub enum EndorsementStatus {
     Endorsed,
     Undecided,
@@ -78,6 +79,9 @@ impl Display for UserEndorsement {
 pub struct EndorsementList {
     pub mods: Vec<UserEndorsement>,
     pub etag: String,
+    /// The profile this list belongs to; empty for none.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub profile: String,
 }
 
 impl EndorsementList {
@@ -107,8 +111,9 @@ impl EndorsementList {
         result
     }
 
-    pub fn listkey() -> &'static str {
-        "endorsements"
+    /// The key for the endorsement list of the profile in use.
+    pub fn listkey() -> String {
+        profile_key("endorsements", config::profile())
     }
 }
 
@@ -125,26 +130,37 @@ impl Display for EndorsementList {
 }
 
 impl Cacheable for EndorsementList {
-    type K = &'static str;
+    type K = String;
 
     fn bucket_name() -> &'static str {
         "endorsements"
     }
 
-    fn get(key: &&'static str, refresh: bool, nexus: &mut NexusClient) -> Option<Box<Self>> {
-        super::get::<Self>(key, refresh, nexus)
+    fn max_age() -> chrono::Duration {
+        chrono::Duration::hours(1)
+    }
+
+    fn get<N: NexusApi>(
+        key: &String,
+        refresh: bool,
+        nexus: &mut N,
+    ) -> Result<Box<Self>, NexusError> {
+        super::get::<Self, N>(key, refresh, nexus)
     }
 
-    fn fetch(
-        _key: &&'static str,
-        nexus: &mut NexusClient,
+    fn fetch<N: NexusApi>(
+        _key: &String,
+        nexus: &mut N,
         etag: Option<String>,
-    ) -> Option<Box<Self>> {
-        nexus.endorsements(etag).map(Box::new)
+    ) -> Result<Option<Box<Self>>, NexusError> {
+        Ok(nexus.endorsements(etag)?.map(|mut list| {
+            list.profile = config::profile().to_string();
+            Box::new(list)
+        }))
     }
 
-    fn key(&self) -> &'static str {
-        EndorsementList::listkey()
+    fn key(&self) -> String {
+        profile_key("endorsements", &self.profile)
     }
 
     fn etag(&self) -> &str {
@@ -156,10 +172,7 @@ impl Cacheable for EndorsementList {
     }
 
     fn store(&self) -> anyhow::Result<usize> {
-        let bucket = super::bucket::<Self>().unwrap();
-        bucket.set(&self.key(), &Json(self.clone()))?;
-        bucket.flush()?;
-        Ok(1)
+        super::save(self)
     }
 
     fn update(&self, other: &Self) -> Self {
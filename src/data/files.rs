@@ -6,12 +6,11 @@ use std::time::{Duration, UNIX_EPOCH};
 use chrono::Utc;
 use humansize::format_size;
 use itertools::Itertools;
-use kv::Json;
 use owo_colors::OwoColorize;
 use serde::{Deserialize, Serialize};
 use terminal_size::*;
 
-use crate::nexus::NexusClient;
+use crate::nexus::{NexusApi, NexusError};
 use crate::{Cacheable, CompoundKey};
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
@@ -38,6 +37,14 @@ pub struct FileInfo {
 }
 
 impl FileInfo {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn description(&self) -> &str {
+        &self.description
+    }
+
     pub fn description_wrapped(&self) -> String {
         let width: usize = if let Some((Width(w), Height(_h))) = terminal_size() {
             w as usize - 2
@@ -191,23 +198,30 @@ impl Cacheable for Files {
         "files"
     }
 
-    fn get(key: &CompoundKey, refresh: bool, nexus: &mut NexusClient) -> Option<Box<Self>> {
-        super::get::<Self>(key, refresh, nexus)
+    fn max_age() -> chrono::Duration {
+        chrono::Duration::days(1)
     }
 
-    fn fetch(
+    fn get<N: NexusApi>(
         key: &CompoundKey,
-        nexus: &mut NexusClient,
+        refresh: bool,
+        nexus: &mut N,
+    ) -> Result<Box<Self>, NexusError> {
+        super::get::<Self, N>(key, refresh, nexus)
+    }
+
+    fn fetch<N: NexusApi>(
+        key: &CompoundKey,
+        nexus: &mut N,
         etag: Option<String>,
-    ) -> Option<Box<Self>> {
+    ) -> Result<Option<Box<Self>>, NexusError> {
         // The game & modid are *not* included in the response data. This is okay, but I want it.
-        nexus
-            .files(&key.domain_name, key.mod_id, etag)
-            .map(|mut v| {
-                v.domain_name = key.domain_name.clone();
-                v.mod_id = key.mod_id;
-                Box::new(v)
-            })
+        let found = nexus.files(&key.domain_name, key.mod_id, etag)?;
+        Ok(found.map(|mut v| {
+            v.domain_name = key.domain_name.clone();
+            v.mod_id = key.mod_id;
+            Box::new(v)
+        }))
     }
 
     fn key(&self) -> CompoundKey {
@@ -226,15 +240,11 @@ impl Cacheable for Files {
     }
 
     fn store(&self) -> anyhow::Result<usize> {
-        let bucket = super::bucket::<Self>().unwrap();
-        if bucket
-            .set(&&*self.key().to_string(), &Json(self.clone()))
-            .is_ok()
-        {
-            Ok(1)
-        } else {
-            Ok(0)
+        let stored = super::save(self).unwrap_or(0);
+        if let Err(e) = super::index::reindex(&self.key()) {
+            log::warn!("Couldn't index the files for {}: {}", self.key(), e);
         }
+        Ok(stored)
     }
 
     fn update(&self, other: &Self) -> Self {
@@ -0,0 +1,115 @@
+//! Loose matching for mod names, for when you only half remember what a mod is called.
+//! Names are compared word by word, forgiving a typo or two, words typed only partway, and
+//! words left out. They're also compared with the spaces taken out, so `skyui` finds
+//! "SkyUI - Fixed" and `sky ui` finds "SkyUI".
+
+/// How well a name has to score to count as a match, out of 1.
+pub static THRESHOLD: f64 = 0.7;
+
+/// Lowercased words, without the punctuation between them.
+fn words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| word.to_lowercase())
+        .collect()
+}
+
+/// How alike a typed word is to a word in a name: 1 for the same word, a bit less for the
+/// start of it, and less again for each typo. Short words get fewer typos before they stop
+/// counting at all.
+fn similarity(typed: &str, word: &str) -> f64 {
+    if typed == word {
+        return 1.0;
+    }
+    let length = typed.chars().count();
+    if length > 1 && word.starts_with(typed) {
+        return 0.9;
+    }
+    let allowed = match length {
+        0..=2 => 0,
+        3..=5 => 1,
+        _ => 2,
+    };
+    let distance = strsim::damerau_levenshtein(typed, word);
+    if distance > allowed {
+        return 0.0;
+    }
+    1.0 - distance as f64 / length.max(word.chars().count()) as f64
+}
+
+/// How well a name matches what was typed, from 0 for not at all to 1 for exactly. Names
+/// with fewer words beyond those typed score higher.
+pub fn score(typed: &str, name: &str) -> f64 {
+    let typed = words(typed);
+    let name = words(name);
+    if typed.is_empty() || name.is_empty() {
+        return 0.0;
+    }
+
+    // Each typed word against the word in the name it's closest to.
+    let by_word = typed
+        .iter()
+        .map(|t| name.iter().map(|w| similarity(t, w)).fold(0.0, f64::max))
+        .sum::<f64>()
+        / typed.len() as f64;
+
+    // Everything typed run together, found in the name run together from one of its words.
+    // This is exact: cut at an arbitrary letter, a name's words look like typos of anything.
+    let joined = typed.concat();
+    let length = joined.chars().count();
+    let spaced_differently = (0..name.len()).any(|start| {
+        let window: String = name[start..].concat().chars().take(length).collect();
+        window == joined
+    });
+    let by_letters = if spaced_differently { 1.0 } else { 0.0 };
+
+    let covered = (length as f64 / name.concat().chars().count() as f64).min(1.0);
+    by_word.max(by_letters) * (0.9 + 0.1 * covered)
+}
+
+/// Whether a name is a close enough match to show.
+pub fn matches(typed: &str, name: &str) -> bool {
+    score(typed, name) >= THRESHOLD
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_with_spacing_changed() {
+        assert!(matches("skyui", "SkyUI - Fixed"));
+        assert!(matches("sky ui", "SkyUI"));
+        assert!(matches("SkyUI", "Sky UI"));
+        assert!(score("skyui", "SkyUI") > score("skyui", "SkyUI - Fixed"));
+        assert!(!matches("sky", "Dragonborn"));
+        assert!(!matches("", "SkyUI"));
+    }
+
+    #[test]
+    fn matches_partial_and_mistyped_names() {
+        assert!(matches(
+            "unoff patch",
+            "Unofficial Skyrim Special Edition Patch"
+        ));
+        assert!(matches("skyiu", "SkyUI"));
+        assert!(matches("immersive armor", "Immersive Armors"));
+        assert!(!matches("immersive weapons", "Immersive Armors"));
+    }
+
+    #[test]
+    fn allows_fewer_typos_in_shorter_words() {
+        // Two letters or fewer: none.
+        assert_eq!(similarity("ui", "ui"), 1.0);
+        assert_eq!(similarity("ux", "ui"), 0.0);
+        // Three to five: one.
+        assert!(similarity("axe", "axa") > 0.0);
+        assert!(similarity("swrod", "sword") > 0.0);
+        assert_eq!(similarity("swxyd", "sword"), 0.0);
+        // Longer: two.
+        assert!(similarity("dargno", "dragon") > 0.0);
+        assert_eq!(similarity("drxxxn", "dragon"), 0.0);
+        // Typing only the start of a word isn't a typo.
+        assert_eq!(similarity("drag", "dragonborn"), 0.9);
+    }
+}
@@ -2,32 +2,56 @@ use std::collections::HashMap;
 use std::fmt::Display;
 
 use itertools::Itertools;
-use kv::Json;
 use num_format::{Locale, ToFormattedString};
 use owo_colors::OwoColorize;
-use regex::RegexBuilder;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 use unicase::UniCase;
 
-use super::{Cacheable, ModInfoFull, ModStatus};
-use crate::nexus::NexusClient;
+use super::query::{Query, Search};
+use super::{local, Cacheable, CompoundKey, ModInfoFull, ModStatus};
+use crate::nexus::{NexusApi, NexusError};
+use crate::storage::ModQuery;
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct ModCategory {
     category_id: u16,
     name: String,
-    // TODO custom deserialization
-    // this is either `false` for the top-level game category or an unsigned int that
-    // points to that top-level category_id for the game
-    parent_category: serde_json::Value,
+    // The Nexus sends `false` for the top-level game category, or the category_id of the
+    // top-level category this one belongs to.
+    #[serde(deserialize_with = "parent_category")]
+    parent_category: Option<u16>,
 }
 
 impl ModCategory {
+    pub fn id(&self) -> u16 {
+        self.category_id
+    }
+
     pub fn name(&self) -> &str {
         &self.name
     }
 }
 
+fn parent_category<'de, D>(deserializer: D) -> Result<Option<u16>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Parent {
+        Id(u16),
+        TopLevel(bool),
+    }
+
+    match Option::<Parent>::deserialize(deserializer)? {
+        Some(Parent::Id(id)) => Ok(Some(id)),
+        Some(Parent::TopLevel(false)) | None => Ok(None),
+        Some(Parent::TopLevel(true)) => Err(serde::de::Error::custom(
+            "expected a category id or false for parent_category",
+        )),
+    }
+}
+
 impl Display for ModCategory {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "({}: {})", self.name.yellow(), self.category_id)
@@ -127,96 +151,80 @@ impl GameMetadata {
         crate::formatting::print_in_grid(cats, 2);
     }
 
+    /// Ask the cache for this game's mods, warning instead of failing if it can't answer.
+    fn find_mods(&self, query: ModQuery) -> Vec<ModInfoFull> {
+        match ModInfoFull::find(&self.domain_name, &query) {
+            Ok(found) => found,
+            Err(e) => {
+                log::warn!(
+                    "Unable to search the mods cached for {}: {:#}",
+                    self.name,
+                    e
+                );
+                Vec::new()
+            }
+        }
+    }
+
     /// Get all mods cached for this game.
     pub fn mods(&self) -> Vec<ModInfoFull> {
-        let prefix = format!("{}/", &self.domain_name);
-        ModInfoFull::by_prefix(&prefix)
+        self.find_mods(ModQuery::All)
             .into_iter()
             .sorted_by(|left, right| UniCase::new(left.name()).cmp(&UniCase::new(right.name())))
             .collect()
     }
 
-    /// Get all mods for this game with names that match the given filter pattern.
-    /// Case-insensitive, but otherwise a very naive match.
-    pub fn mods_name_match(&self, filter: &str) -> Vec<ModInfoFull> {
-        let prefix = format!("{}/", &self.domain_name);
-        let candidates = ModInfoFull::by_prefix(&prefix);
-        let patt = RegexBuilder::new(filter)
-            .case_insensitive(true)
-            .build()
-            .unwrap();
-        candidates
-            .into_iter()
-            .filter(|modinfo| patt.is_match(&modinfo.name()))
-            .collect()
-    }
-
-    /// Get all mods for this game with an uploader string that matches the given filter pattern.
-    /// Case-insensitive, but otherwise a very naive match.
-    pub fn mods_author_match(&self, filter: &str) -> Vec<ModInfoFull> {
-        let prefix = format!("{}/", &self.domain_name);
-        let candidates = ModInfoFull::by_prefix(&prefix);
-        let patt = RegexBuilder::new(filter)
-            .case_insensitive(true)
-            .build()
-            .unwrap();
-        candidates
-            .into_iter()
-            .filter(|modinfo| {
-                patt.is_match(modinfo.uploaded_by()) || patt.is_match(modinfo.author())
-            })
-            .collect()
-    }
-
-    /// Get all mods for this game with names or summaries that match the given filter pattern.
-    /// Case-insensitive, but otherwise a very naive match.
-    // Note repetition with previous function. Searching needs some abstractions.
-    pub fn mods_match_text(&self, filter: &str) -> Vec<ModInfoFull> {
-        let prefix = format!("{}/", &self.domain_name);
-        let candidates = ModInfoFull::by_prefix(&prefix);
-        let patt = RegexBuilder::new(filter)
-            .case_insensitive(true)
-            .build()
-            .unwrap();
-        candidates
+    /// Get all mods cached for this game that match the query, best match first for any
+    /// text it searches for, and otherwise by id.
+    pub fn mods_matching(&self, query: &Query) -> anyhow::Result<Vec<ModInfoFull>> {
+        let categories = self
+            .categories
+            .iter()
+            .map(|category| (category.id(), category.name().to_string()))
+            .collect();
+        let search = Search::new(query, &self.domain_name, categories)?;
+        // Text searches can say which few mods are worth reading.
+        let candidates = match search.candidates() {
+            Some(ids) => ids
+                .into_iter()
+                .filter_map(|mod_id| {
+                    local::<ModInfoFull>(&CompoundKey::new(self.domain_name.clone(), mod_id))
+                })
+                .map(|modinfo| *modinfo)
+                .collect(),
+            None => ModInfoFull::find(&self.domain_name, &query.prefilter())?,
+        };
+        let mut found: Vec<(f64, ModInfoFull)> = candidates
             .into_iter()
-            .filter(|modinfo| {
-                patt.is_match(&modinfo.name())
-                    || patt.is_match(modinfo.summary())
-                    || patt.is_match(modinfo.uploaded_by())
-                    || patt.is_match(modinfo.author())
-            })
-            .collect()
+            .filter(|modinfo| search.matches(modinfo))
+            .map(|modinfo| (search.relevance(&modinfo), modinfo))
+            .collect();
+        found.sort_by(|left, right| {
+            right
+                .0
+                .total_cmp(&left.0)
+                .then(left.1.mod_id().cmp(&right.1.mod_id()))
+        });
+        Ok(found.into_iter().map(|(_score, modinfo)| modinfo).collect())
     }
 
-    // I learned a surprising thing about rust when I tried to make a single function
-    // to which I pass the enum variant I want to match against.
     pub fn mods_hidden(&self) -> Vec<ModInfoFull> {
-        let prefix = format!("{}/", &self.domain_name);
-        let candidates = ModInfoFull::by_prefix(&prefix);
-        candidates
+        self.find_mods(ModQuery::Status(ModStatus::Hidden))
             .into_iter()
-            .filter(|modinfo| matches!(modinfo.status(), ModStatus::Hidden))
             .sorted_by(|left, right| left.mod_id().cmp(&right.mod_id()))
             .collect()
     }
 
     pub fn mods_removed(&self) -> Vec<ModInfoFull> {
-        let prefix = format!("{}/", &self.domain_name);
-        let candidates = ModInfoFull::by_prefix(&prefix);
-        candidates
+        self.find_mods(ModQuery::Status(ModStatus::Removed))
             .into_iter()
-            .filter(|modinfo| matches!(modinfo.status(), ModStatus::Removed))
             .sorted_by(|left, right| left.mod_id().cmp(&right.mod_id()))
             .collect()
     }
 
     pub fn mods_wastebinned(&self) -> Vec<ModInfoFull> {
-        let prefix = format!("{}/", &self.domain_name);
-        let candidates = ModInfoFull::by_prefix(&prefix);
-        candidates
+        self.find_mods(ModQuery::Status(ModStatus::Wastebinned))
             .into_iter()
-            .filter(|modinfo| matches!(modinfo.status(), ModStatus::Wastebinned))
             .sorted_by(|left, right| left.mod_id().cmp(&right.mod_id()))
             .collect()
     }
@@ -229,12 +237,24 @@ impl Cacheable for GameMetadata {
         "games"
     }
 
-    fn get(key: &String, refresh: bool, nexus: &mut NexusClient) -> Option<Box<Self>> {
-        super::get::<Self>(key, refresh, nexus)
+    fn max_age() -> chrono::Duration {
+        chrono::Duration::weeks(1)
+    }
+
+    fn get<N: NexusApi>(
+        key: &String,
+        refresh: bool,
+        nexus: &mut N,
+    ) -> Result<Box<Self>, NexusError> {
+        super::get::<Self, N>(key, refresh, nexus)
     }
 
-    fn fetch(key: &String, nexus: &mut NexusClient, etag: Option<String>) -> Option<Box<Self>> {
-        nexus.gameinfo(key, etag).map(Box::new)
+    fn fetch<N: NexusApi>(
+        key: &String,
+        nexus: &mut N,
+        etag: Option<String>,
+    ) -> Result<Option<Box<Self>>, NexusError> {
+        Ok(nexus.gameinfo(key, etag)?.map(Box::new))
     }
 
     fn key(&self) -> String {
@@ -250,10 +270,7 @@ impl Cacheable for GameMetadata {
     }
 
     fn store(&self) -> anyhow::Result<usize> {
-        let bucket = super::bucket::<Self>().unwrap();
-        bucket.set(&&*self.domain_name, &Json(self.clone()))?;
-        bucket.flush()?;
-        Ok(1)
+        super::save(self)
     }
 
     fn update(&self, other: &Self) -> Self {
@@ -0,0 +1,156 @@
+//! Snapshots of a mod's metadata over time. Storing a refreshed mod overwrites the cached
+//! copy, so before it does, we note what changed. Each mod gets one history record, a list
+//! of snapshots oldest first, and a snapshot is only added when something we care about is
+//! different from the one before it. Descriptions are long and rarely edited, so a snapshot
+//! only keeps one when it changed.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::{fetched_at, local, CompoundKey, ModInfoFull, ModStatus};
+use crate::{storage, Cacheable};
+
+/// The bucket mod histories are kept in, under the same keys as the mods.
+pub static HISTORY: &str = "mod_history";
+
+/// The parts of a mod worth watching change.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ModSnapshot {
+    /// When we saw this version of the mod. Snapshots of mods cached before we kept
+    /// histories may not know.
+    pub seen: Option<DateTime<Utc>>,
+    pub name: String,
+    pub version: String,
+    pub summary: String,
+    /// The description, if it's different from the one in the snapshot before.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub author: String,
+    pub uploaded_by: String,
+    pub status: ModStatus,
+    pub endorsement_count: u32,
+    pub updated_timestamp: u64,
+}
+
+impl ModSnapshot {
+    pub fn of(modinfo: &ModInfoFull, seen: Option<DateTime<Utc>>) -> Self {
+        Self {
+            seen,
+            name: modinfo.name(),
+            version: modinfo.version().to_string(),
+            summary: modinfo.summary().to_string(),
+            description: Some(modinfo.description().to_string()),
+            author: modinfo.author().to_string(),
+            uploaded_by: modinfo.uploaded_by().to_string(),
+            status: modinfo.status(),
+            endorsement_count: modinfo.endorsement_count(),
+            updated_timestamp: modinfo.updated_timestamp(),
+        }
+    }
+
+    /// Whether two snapshots describe the same mod, whenever they were taken, apart from
+    /// the description.
+    fn same_as(&self, other: &Self) -> bool {
+        self.name == other.name
+            && self.version == other.version
+            && self.summary == other.summary
+            && self.author == other.author
+            && self.uploaded_by == other.uploaded_by
+            && self.status == other.status
+            && self.endorsement_count == other.endorsement_count
+            && self.updated_timestamp == other.updated_timestamp
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ModHistory {
+    pub domain_name: String,
+    pub mod_id: u32,
+    pub snapshots: Vec<ModSnapshot>,
+}
+
+impl ModHistory {
+    /// Look up the history for a mod. Mods we've never stored have none.
+    pub fn load(key: &CompoundKey) -> anyhow::Result<Option<Self>> {
+        match storage().get(HISTORY, &key.to_string())? {
+            Some(json) => Ok(Some(serde_json::from_str(&json)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Each snapshot, oldest first, with the description the mod had then.
+    pub fn described(&self) -> Vec<(&ModSnapshot, &str)> {
+        let mut description = "";
+        self.snapshots
+            .iter()
+            .map(|snapshot| {
+                if let Some(text) = &snapshot.description {
+                    description = text;
+                }
+                (snapshot, description)
+            })
+            .collect()
+    }
+
+    /// Drop descriptions that are the same as the one before. Histories recorded before
+    /// we did this have a copy in every snapshot.
+    fn compact(&mut self) {
+        let mut previous: Option<String> = None;
+        for snapshot in self.snapshots.iter_mut() {
+            if snapshot.description.is_none() {
+                continue;
+            }
+            if snapshot.description == previous {
+                snapshot.description = None;
+            } else {
+                previous = snapshot.description.clone();
+            }
+        }
+    }
+
+    /// Note a mod we're about to store, if it differs from the last snapshot. The first
+    /// time we see a mod that's already cached, the cached copy starts its history.
+    pub fn record(modinfo: &ModInfoFull) -> anyhow::Result<()> {
+        let key = modinfo.key();
+        let (mut history, started) = match Self::load(&key)? {
+            Some(history) => (history, false),
+            None => {
+                let mut history = ModHistory {
+                    domain_name: key.domain_name.clone(),
+                    mod_id: key.mod_id,
+                    snapshots: Vec::new(),
+                };
+                if let Some(cached) = local::<ModInfoFull>(&key) {
+                    let seen = fetched_at::<ModInfoFull>(&key);
+                    history.snapshots.push(ModSnapshot::of(&cached, seen));
+                }
+                (history, true)
+            }
+        };
+
+        let latest = ModSnapshot::of(modinfo, Some(Utc::now()));
+        let description = history
+            .described()
+            .last()
+            .map(|(_s, text)| text.to_string());
+        let unchanged = history
+            .snapshots
+            .last()
+            .is_some_and(|last| last.same_as(&latest))
+            && description == latest.description;
+        if unchanged && !started {
+            return Ok(());
+        }
+        if !unchanged {
+            history.snapshots.push(latest);
+        }
+        history.compact();
+        storage().set(HISTORY, &key.to_string(), &serde_json::to_string(&history)?)
+    }
+
+    /// Read a stored history, responding with the key it belongs under.
+    pub fn read_key(json: &str) -> anyhow::Result<String> {
+        let history: ModHistory = serde_json::from_str(json)?;
+        Ok(CompoundKey::new(history.domain_name, history.mod_id).to_string())
+    }
+}
@@ -0,0 +1,656 @@
+//! The full-text search index. Each cached mod's name, summary, description, credits,
+//! changelogs, and file descriptions are split into words, stemmed, and filed under each
+//! word, so a search reads one list per word instead of every mod. Results are ranked with
+//! BM25, which favors mods that use the words often over mods that merely mention them, and
+//! rare words over common ones.
+//!
+//! The index is kept up to date as mods are stored, each mod's words written under its own
+//! keys so several processes can store mods at once. A game's mods cached before the index
+//! existed are indexed all at once by `modcache cache reindex`. Until then, searches for that
+//! game read every mod instead, and rank them the same way.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::Mutex;
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+use rust_stemmers::{Algorithm, Stemmer};
+use serde::{Deserialize, Serialize};
+
+use super::{local, Changelogs, CompoundKey, Files, ModInfoFull};
+use crate::storage::{ModQuery, MODS};
+use crate::{storage, Cacheable};
+
+/// How often each mod uses each word, keyed by game, word, and mod id, so the mods using a
+/// word are one prefix scan.
+pub static SEARCH_TERMS: &str = "search_terms";
+/// How long each indexed mod's text is, keyed like the mods themselves. A search reads these
+/// only for the mods that match.
+pub static SEARCH_DOCS: &str = "search_docs";
+/// The words in each indexed mod, keyed like the mods themselves, so an update knows what
+/// to change.
+pub static SEARCH_WORDS: &str = "search_words";
+/// Whether each game's index covers every mod cached for it, and how many mods it holds and
+/// how long their texts are all told, which BM25 needs to judge every match.
+pub static SEARCH_STATS: &str = "search_stats";
+
+/// Bumped when the way the index is stored changes, so older indexes get rebuilt.
+static INDEX_VERSION: u32 = 2;
+
+/// How many records a rebuild writes at a time.
+static BATCH: usize = 10_000;
+
+/// How much more a word counts in a mod's name or summary than in the rest of its text.
+static NAME_WEIGHT: u32 = 3;
+static SUMMARY_WEIGHT: u32 = 2;
+
+/// BM25's usual tuning: how quickly repeating a word stops helping, and how much a long
+/// text is marked down for having more room to repeat it.
+static K1: f64 = 1.2;
+static B: f64 = 0.75;
+
+/// Words too common to tell mods apart.
+static STOP_WORDS: &[&str] = &[
+    "a", "an", "and", "are", "as", "at", "be", "by", "for", "from", "has", "have", "in", "is",
+    "it", "its", "of", "on", "or", "so", "that", "the", "this", "to", "was", "were", "will",
+    "with",
+];
+
+static STEMMER: Lazy<Stemmer> = Lazy::new(|| Stemmer::create(Algorithm::English));
+// bbcode and html tags, and links, which are noise as far as search is concerned.
+static MARKUP: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(\[[^\]]*\]|<[^>]*>|https?://\S+|&\w+;)").unwrap());
+
+/// An update reads what was indexed for a mod before writing the difference, so only one
+/// happens at a time here. Bulk fetches store mods from several threads.
+static INDEXING: Mutex<()> = Mutex::new(());
+
+/// What the index knows about one mod.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct IndexedMod {
+    domain_name: String,
+    mod_id: u32,
+    /// How many words the mod's text holds, counting the weights.
+    length: u32,
+    /// Each stemmed word and how often it appears, counting the weights.
+    terms: BTreeMap<String, u32>,
+}
+
+/// How long an indexed mod's text is, which is all a search needs to know about it.
+#[derive(Serialize, Deserialize, Debug)]
+struct IndexedLength {
+    length: u32,
+}
+
+/// Updates keep the totals here in step with the mods they index. Processes storing mods for
+/// the same game side by side can undo each other's changes to them, which only skews the
+/// ranking a little, and a rebuild counts them afresh.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+struct IndexStats {
+    /// Whether every mod cached for the game has been indexed, as opposed to only those
+    /// stored since the index existed.
+    #[serde(default)]
+    complete: bool,
+    /// How the index was stored; see `INDEX_VERSION`.
+    #[serde(default)]
+    version: u32,
+    /// `None` for an index written before these were kept; the next search counts them.
+    #[serde(default)]
+    totals: Option<Totals>,
+}
+
+/// How many of a game's mods are indexed, and how long their texts are all told.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+struct Totals {
+    docs: u64,
+    length: u64,
+}
+
+impl Totals {
+    fn of<'a>(lengths: impl IntoIterator<Item = &'a u32>) -> Self {
+        lengths
+            .into_iter()
+            .fold(Self::default(), |totals, length| Self {
+                docs: totals.docs + 1,
+                length: totals.length + *length as u64,
+            })
+    }
+
+    /// Account for a mod whose text was `before` long, if it was indexed, and is now
+    /// `after` long, if it still is.
+    fn replace(&mut self, before: Option<u32>, after: Option<u32>) {
+        if let Some(before) = before {
+            self.docs = self.docs.saturating_sub(1);
+            self.length = self.length.saturating_sub(before as u64);
+        }
+        if let Some(after) = after {
+            self.docs += 1;
+            self.length += after as u64;
+        }
+    }
+
+    /// The average mod's length, which is never less than one word.
+    fn average(&self) -> f64 {
+        (self.length as f64 / (self.docs as f64).max(1.0)).max(1.0)
+    }
+}
+
+impl IndexStats {
+    fn load(game: &str) -> anyhow::Result<Option<Self>> {
+        match storage().get(SEARCH_STATS, game)? {
+            Some(json) => Ok(Some(serde_json::from_str(&json)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn save(&self, game: &str) -> anyhow::Result<()> {
+        storage().set(SEARCH_STATS, game, &serde_json::to_string(self)?)
+    }
+
+    /// Whether every mod is indexed, the way this version stores them.
+    fn current(&self) -> bool {
+        self.complete && self.version == INDEX_VERSION
+    }
+
+    /// Change the totals for one mod, if they're being kept.
+    fn replace(game: &str, before: Option<u32>, after: Option<u32>) -> anyhow::Result<()> {
+        let Some(mut stats) = Self::load(game)? else {
+            // A new index for the game, holding only this mod so far.
+            let stats = IndexStats {
+                complete: false,
+                version: INDEX_VERSION,
+                totals: Some(Totals::of(after.as_ref())),
+            };
+            return stats.save(game);
+        };
+        let Some(totals) = stats.totals.as_mut() else {
+            return Ok(());
+        };
+        totals.replace(before, after);
+        stats.save(game)
+    }
+}
+
+/// Split text into stemmed, lowercased words, leaving out markup and stop words.
+pub fn words(text: &str) -> Vec<String> {
+    let text = MARKUP.replace_all(text, " ");
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| word.chars().count() > 1)
+        .map(|word| word.to_lowercase())
+        .filter(|word| !STOP_WORDS.contains(&word.as_str()))
+        .map(|word| STEMMER.stem(&word).into_owned())
+        .collect()
+}
+
+impl IndexedMod {
+    /// Gather the words for a mod, including its cached changelogs and file list.
+    fn of(modinfo: &ModInfoFull) -> Self {
+        let key = modinfo.key();
+        let mut terms: BTreeMap<String, u32> = BTreeMap::new();
+        let mut add = |text: &str, weight: u32| {
+            for word in words(text) {
+                *terms.entry(word).or_default() += weight;
+            }
+        };
+
+        add(&modinfo.name(), NAME_WEIGHT);
+        add(modinfo.summary(), SUMMARY_WEIGHT);
+        add(modinfo.description(), 1);
+        add(modinfo.author(), 1);
+        add(modinfo.uploaded_by(), 1);
+        if let Some(changelogs) = local::<Changelogs>(&key) {
+            for entry in changelogs.versions().values().flatten() {
+                add(entry, 1);
+            }
+        }
+        if let Some(files) = local::<Files>(&key) {
+            for file in files.files() {
+                add(file.name(), 1);
+                add(file.description(), 1);
+            }
+        }
+
+        Self {
+            domain_name: key.domain_name.clone(),
+            mod_id: key.mod_id(),
+            length: terms.values().sum(),
+            terms,
+        }
+    }
+
+    fn load(key: &str) -> anyhow::Result<Option<Self>> {
+        match storage().get(SEARCH_WORDS, key)? {
+            Some(json) => Ok(Some(serde_json::from_str(&json)?)),
+            None => Ok(None),
+        }
+    }
+}
+
+fn term_prefix(game: &str, term: &str) -> String {
+    format!("{}/{}/", game, term)
+}
+
+fn posting_key(game: &str, term: &str, mod_id: u32) -> String {
+    format!("{}{}", term_prefix(game, term), mod_id)
+}
+
+/// Each mod using a word, and how often.
+fn postings(game: &str, term: &str) -> anyhow::Result<Vec<(u32, u32)>> {
+    let prefix = term_prefix(game, term);
+    let mut found = Vec::new();
+    for (key, json) in storage().scan(SEARCH_TERMS, &prefix)? {
+        let Ok(mod_id) = key[prefix.len()..].parse() else {
+            continue;
+        };
+        found.push((mod_id, serde_json::from_str(&json)?));
+    }
+    Ok(found)
+}
+
+/// Bring the index up to date with a mod that was just stored. Only the words whose counts
+/// changed are written, so storing a mod that hasn't changed costs one read.
+pub fn update(modinfo: &ModInfoFull) -> anyhow::Result<()> {
+    let _lock = INDEXING.lock().unwrap();
+    let indexed = IndexedMod::of(modinfo);
+    let key = modinfo.key().to_string();
+    let previous = IndexedMod::load(&key)?;
+    if previous.as_ref() == Some(&indexed) {
+        return Ok(());
+    }
+    let game = indexed.domain_name.as_str();
+
+    let old_terms = previous.as_ref().map(|p| &p.terms);
+    let changed: Vec<(String, String)> = indexed
+        .terms
+        .iter()
+        .filter(|(term, count)| old_terms.and_then(|t| t.get(*term)) != Some(*count))
+        .map(|(term, count)| (posting_key(game, term, indexed.mod_id), count.to_string()))
+        .collect();
+    let dropped: Vec<String> = old_terms
+        .into_iter()
+        .flat_map(|t| t.keys())
+        .filter(|term| !indexed.terms.contains_key(*term))
+        .map(|term| posting_key(game, term, indexed.mod_id))
+        .collect();
+    storage().set_many(SEARCH_TERMS, &changed)?;
+    storage().remove_many(SEARCH_TERMS, &dropped)?;
+    storage().set(SEARCH_WORDS, &key, &serde_json::to_string(&indexed)?)?;
+    let length = IndexedLength {
+        length: indexed.length,
+    };
+    storage().set(SEARCH_DOCS, &key, &serde_json::to_string(&length)?)?;
+    IndexStats::replace(game, previous.map(|p| p.length), Some(indexed.length))
+}
+
+/// Take up-to-date changelogs or files into account for a mod that's already indexed.
+pub fn reindex(key: &CompoundKey) -> anyhow::Result<()> {
+    match local::<ModInfoFull>(key) {
+        Some(modinfo) => update(&modinfo),
+        None => Ok(()),
+    }
+}
+
+/// Drop a mod from the index, by its key in the mods bucket.
+pub fn remove(key: &str) -> anyhow::Result<()> {
+    let _lock = INDEXING.lock().unwrap();
+    let Some(previous) = IndexedMod::load(key)? else {
+        return Ok(());
+    };
+    let game = previous.domain_name.as_str();
+    let postings: Vec<String> = previous
+        .terms
+        .keys()
+        .map(|term| posting_key(game, term, previous.mod_id))
+        .collect();
+    storage().remove_many(SEARCH_TERMS, &postings)?;
+    storage().remove(SEARCH_DOCS, key)?;
+    storage().remove(SEARCH_WORDS, key)?;
+    IndexStats::replace(game, Some(previous.length), None)
+}
+
+/// Index every mod cached for a game from scratch, responding with how many there were.
+/// Records are written in batches rather than one at a time.
+pub fn rebuild(game: &str) -> anyhow::Result<usize> {
+    let _lock = INDEXING.lock().unwrap();
+    let prefix = format!("{}/", game);
+    for bucket in [SEARCH_TERMS, SEARCH_DOCS, SEARCH_WORDS] {
+        let keys: Vec<String> = storage()
+            .scan(bucket, &prefix)?
+            .into_iter()
+            .map(|(key, _json)| key)
+            .collect();
+        for batch in keys.chunks(BATCH) {
+            storage().remove_many(bucket, batch)?;
+        }
+    }
+
+    let mods = ModInfoFull::find(game, &ModQuery::All)?;
+    let mut postings = Vec::new();
+    let mut docs = Vec::new();
+    let mut words = Vec::new();
+    let mut totals = Totals::default();
+    for modinfo in mods.iter() {
+        let indexed = IndexedMod::of(modinfo);
+        totals.replace(None, Some(indexed.length));
+        let key = modinfo.key().to_string();
+        for (term, count) in indexed.terms.iter() {
+            postings.push((posting_key(game, term, indexed.mod_id), count.to_string()));
+        }
+        let length = IndexedLength {
+            length: indexed.length,
+        };
+        docs.push((key.clone(), serde_json::to_string(&length)?));
+        words.push((key, serde_json::to_string(&indexed)?));
+        if postings.len() >= BATCH {
+            storage().set_many(SEARCH_TERMS, &postings)?;
+            postings.clear();
+        }
+        if docs.len() >= BATCH {
+            storage().set_many(SEARCH_DOCS, &docs)?;
+            storage().set_many(SEARCH_WORDS, &words)?;
+            docs.clear();
+            words.clear();
+        }
+    }
+    storage().set_many(SEARCH_TERMS, &postings)?;
+    storage().set_many(SEARCH_DOCS, &docs)?;
+    storage().set_many(SEARCH_WORDS, &words)?;
+    let stats = IndexStats {
+        complete: true,
+        version: INDEX_VERSION,
+        totals: Some(totals),
+    };
+    stats.save(game)?;
+    Ok(mods.len())
+}
+
+/// How long each indexed mod's text is for a game, by mod id. This reads every indexed mod,
+/// so searches only do it while the index isn't known to be complete.
+fn lengths(game: &str) -> anyhow::Result<HashMap<u32, u32>> {
+    let prefix = format!("{}/", game);
+    let mut found = HashMap::new();
+    for (key, json) in storage().scan(SEARCH_DOCS, &prefix)? {
+        let Ok(mod_id) = key[prefix.len()..].parse() else {
+            continue;
+        };
+        found.insert(mod_id, serde_json::from_str::<IndexedLength>(&json)?.length);
+    }
+    Ok(found)
+}
+
+/// How long the text of each of these indexed mods is, by mod id.
+fn lengths_of(game: &str, mod_ids: &BTreeSet<u32>) -> anyhow::Result<HashMap<u32, u32>> {
+    let mut found = HashMap::new();
+    for mod_id in mod_ids.iter() {
+        if let Some(json) = storage().get(SEARCH_DOCS, &format!("{}/{}", game, mod_id))? {
+            found.insert(
+                *mod_id,
+                serde_json::from_str::<IndexedLength>(&json)?.length,
+            );
+        }
+    }
+    Ok(found)
+}
+
+/// The totals for the game's index, if it holds every mod cached for the game. An index this
+/// version wrote can have every mod even if it was never rebuilt, if they were all cached
+/// since it existed; the first search to find that remembers it, and counts the totals if
+/// they weren't being kept.
+fn complete(game: &str) -> anyhow::Result<Option<Totals>> {
+    let stats = IndexStats::load(game)?;
+    if stats
+        .as_ref()
+        .is_some_and(|stats| stats.version != INDEX_VERSION)
+    {
+        return Ok(None);
+    }
+    let stats = stats.unwrap_or_default();
+    if let (true, Some(totals)) = (stats.current(), stats.totals) {
+        return Ok(Some(totals));
+    }
+    let lengths = lengths(game)?;
+    if !stats.complete {
+        let prefix = format!("{}/", game);
+        let unindexed = storage()
+            .scan(MODS, &prefix)?
+            .into_iter()
+            .any(|(key, _json)| {
+                key[prefix.len()..]
+                    .parse()
+                    .map_or(true, |id| !lengths.contains_key(&id))
+            });
+        if unindexed {
+            return Ok(None);
+        }
+    }
+    let totals = Totals::of(lengths.values());
+    let stats = IndexStats {
+        complete: true,
+        version: INDEX_VERSION,
+        totals: Some(totals),
+    };
+    stats.save(game)?;
+    Ok(Some(totals))
+}
+
+/// Find the mods for a game that use every word in the text, best match first, with their
+/// scores. If some of the game's mods were cached before the index existed and haven't been
+/// indexed since, every mod is read and scored here instead, rather than rebuilding the
+/// index from under anything else using the cache.
+pub fn search(game: &str, text: &str) -> anyhow::Result<Vec<(u32, f64)>> {
+    let query: BTreeSet<String> = words(text).into_iter().collect();
+    if query.is_empty() {
+        return Ok(Vec::new());
+    }
+    if let Some(totals) = complete(game)? {
+        let found = query
+            .iter()
+            .map(|term| Ok(postings(game, term)?.into_iter().collect()))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let lengths = lengths_of(game, &matching(&found))?;
+        return Ok(rank(totals, &lengths, &found));
+    }
+
+    log::warn!(
+        "Some mods cached for {} aren't in the search index yet, so searching reads every mod. Run `modcache cache reindex` to index them.",
+        game
+    );
+    let mut lengths = HashMap::new();
+    let mut found: Vec<HashMap<u32, u32>> = vec![HashMap::new(); query.len()];
+    for modinfo in ModInfoFull::find(game, &ModQuery::All)?.iter() {
+        let indexed = IndexedMod::of(modinfo);
+        for (term, postings) in query.iter().zip(found.iter_mut()) {
+            if let Some(count) = indexed.terms.get(term) {
+                postings.insert(indexed.mod_id, *count);
+            }
+        }
+        lengths.insert(indexed.mod_id, indexed.length);
+    }
+    Ok(rank(Totals::of(lengths.values()), &lengths, &found))
+}
+
+/// The mods that use every word, given each word's postings.
+fn matching(found: &[HashMap<u32, u32>]) -> BTreeSet<u32> {
+    let mut matched: Option<BTreeSet<u32>> = None;
+    for postings in found.iter() {
+        let ids = postings.keys().copied();
+        matched = Some(match matched {
+            Some(matched) => ids.filter(|id| matched.contains(id)).collect(),
+            None => ids.collect(),
+        });
+    }
+    matched.unwrap_or_default()
+}
+
+/// Score the mods that use every query word with BM25, best first. Takes the totals for the
+/// game's index, the length of at least each matching mod by id, and each word's postings:
+/// how often each mod using it does.
+fn rank(
+    totals: Totals,
+    lengths: &HashMap<u32, u32>,
+    found: &[HashMap<u32, u32>],
+) -> Vec<(u32, f64)> {
+    let mods = totals.docs as f64;
+    let average = totals.average();
+
+    // Each word's weight.
+    let weighted: Vec<(f64, &HashMap<u32, u32>)> = found
+        .iter()
+        .map(|postings| {
+            let using = postings.len() as f64;
+            let idf = (1.0 + (mods - using + 0.5).max(0.0) / (using + 0.5)).ln();
+            (idf, postings)
+        })
+        .collect();
+
+    let mut ranked = Vec::new();
+    for mod_id in matching(found) {
+        let Some(length) = lengths.get(&mod_id) else {
+            continue;
+        };
+        let norm = K1 * (1.0 - B + B * *length as f64 / average);
+        let score: f64 = weighted
+            .iter()
+            .map(|(idf, postings)| {
+                let count = postings[&mod_id] as f64;
+                idf * count * (K1 + 1.0) / (count + norm)
+            })
+            .sum();
+        ranked.push((mod_id, score));
+    }
+    ranked.sort_by(|left, right| right.1.total_cmp(&left.1).then(left.0.cmp(&right.0)));
+    ranked
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn words_are_stemmed_and_lowercased_without_markup_or_stop_words() {
+        assert_eq!(
+            words("[b]Better[/b] Textures for the <i>Dragons</i> of Skyrim"),
+            vec!["better", "textur", "dragon", "skyrim"]
+        );
+        assert_eq!(words("Retextured textures"), words("retexture TEXTURE"));
+        assert_eq!(
+            words("See https://example.com/mod?id=1 &amp; a 4K x2 patch"),
+            vec!["see", "4k", "x2", "patch"]
+        );
+        assert!(words("the a of I").is_empty());
+    }
+
+    #[test]
+    fn postings_for_a_word_stop_at_that_word() {
+        let prefix = term_prefix("skyrim", "dragon");
+        assert_eq!(posting_key("skyrim", "dragon", 10), "skyrim/dragon/10");
+        assert!(posting_key("skyrim", "dragon", 1).starts_with(&prefix));
+        assert!(!posting_key("skyrim", "dragonborn", 1).starts_with(&prefix));
+        assert!(!posting_key("skyrimse", "dragon", 1).starts_with(&prefix));
+    }
+
+    fn postings(counts: &[(u32, u32)]) -> HashMap<u32, u32> {
+        counts.iter().copied().collect()
+    }
+
+    #[test]
+    fn ranking_favors_repeated_words_in_short_texts() {
+        let lengths: HashMap<u32, u32> = [(1, 10), (2, 10), (3, 100), (4, 10)].into();
+        let found = [postings(&[(1, 1), (2, 3), (3, 3)])];
+        let ranked = rank(Totals::of(lengths.values()), &lengths, &found);
+        let order: Vec<u32> = ranked.iter().map(|(id, _)| *id).collect();
+        // Saying it three times in ten words beats once, and beats three times in a hundred,
+        // which is marked down for having so much room to say it.
+        assert_eq!(order, vec![2, 1, 3]);
+        assert!(ranked.iter().all(|(_, score)| *score > 0.0));
+    }
+
+    #[test]
+    fn ranking_needs_every_word_and_favors_rare_ones() {
+        let lengths: HashMap<u32, u32> = (1..=10).map(|id| (id, 20)).collect();
+        let totals = Totals::of(lengths.values());
+        // Every mod says "mod"; only two say "dragon", and only one says "sword".
+        let common = postings(&(1..=10).map(|id| (id, 1)).collect::<Vec<_>>());
+        let dragon = postings(&[(3, 1), (7, 1)]);
+        let sword = postings(&[(7, 1)]);
+
+        let ranked = rank(totals, &lengths, &[common.clone(), dragon.clone()]);
+        let order: Vec<u32> = ranked.iter().map(|(id, _)| *id).collect();
+        assert_eq!(order, vec![3, 7]);
+        assert_eq!(ranked[0].1, ranked[1].1);
+
+        let ranked = rank(totals, &lengths, &[dragon.clone(), sword]);
+        assert_eq!(ranked.len(), 1);
+        assert_eq!(ranked[0].0, 7);
+
+        // A rare word outweighs a common one used as often.
+        let rare = rank(totals, &lengths, &[dragon])[0].1;
+        let everywhere = rank(totals, &lengths, &[common])[0].1;
+        assert!(rare > everywhere, "{} <= {}", rare, everywhere);
+    }
+
+    #[test]
+    fn ranking_nothing_finds_nothing() {
+        assert!(rank(Totals::default(), &HashMap::new(), &[]).is_empty());
+        let lengths: HashMap<u32, u32> = [(1, 10)].into();
+        let totals = Totals::of(lengths.values());
+        assert!(rank(totals, &lengths, &[postings(&[])]).is_empty());
+    }
+
+    #[test]
+    fn ranking_needs_lengths_only_for_the_matches() {
+        let lengths: HashMap<u32, u32> = [(1, 10), (2, 10), (3, 100), (4, 10)].into();
+        let totals = Totals::of(lengths.values());
+        let found = [postings(&[(1, 1), (2, 3)])];
+        let matched: HashMap<u32, u32> = [(1, 10), (2, 10)].into();
+        assert_eq!(
+            rank(totals, &matched, &found),
+            rank(totals, &lengths, &found)
+        );
+    }
+
+    #[test]
+    fn totals_follow_mods_in_and_out() {
+        let mut totals = Totals::of(&[10, 30]);
+        assert_eq!(
+            totals,
+            Totals {
+                docs: 2,
+                length: 40
+            }
+        );
+        assert_eq!(totals.average(), 20.0);
+
+        totals.replace(None, Some(20));
+        assert_eq!(
+            totals,
+            Totals {
+                docs: 3,
+                length: 60
+            }
+        );
+        totals.replace(Some(20), Some(50));
+        assert_eq!(
+            totals,
+            Totals {
+                docs: 3,
+                length: 90
+            }
+        );
+        totals.replace(Some(50), None);
+        assert_eq!(
+            totals,
+            Totals {
+                docs: 2,
+                length: 40
+            }
+        );
+
+        // Never below nothing, even if another process's changes were lost.
+        totals.replace(Some(100), None);
+        totals.replace(Some(100), None);
+        totals.replace(Some(100), None);
+        assert_eq!(totals, Totals::default());
+        assert_eq!(totals.average(), 1.0);
+    }
+}
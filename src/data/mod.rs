@@ -2,38 +2,84 @@
 //! More complex structures are broken out into separate files.
 
 use std::fmt::{Debug, Display};
+use std::str::FromStr;
 
-use kv::{Codec, Json};
+use chrono::{DateTime, Duration, Utc};
+use once_cell::sync::OnceCell;
 use serde::{Deserialize, Serialize};
 
+pub mod archive;
 pub mod changelogs;
 pub mod endorsement;
 pub mod files;
+pub mod fuzzy;
 pub mod game;
+pub mod history;
+pub mod index;
 pub mod modinfo;
+pub mod query;
 pub mod tracked;
 pub mod user;
 
+pub use archive::*;
 pub use changelogs::*;
 pub use endorsement::*;
 pub use files::*;
 pub use game::*;
+pub use history::*;
 pub use modinfo::*;
 pub use tracked::*;
 pub use user::*;
 
-use crate::kvstore;
-use crate::nexus::NexusClient;
+use crate::nexus::{NexusApi, NexusError};
+use crate::storage;
+
+/// Where we note when each record was last fetched from the Nexus, or confirmed current by
+/// it. Keys are the record's bucket and key.
+pub static FETCHED: &str = "fetched";
+
+/// Where we note when each item was last shown to someone, keyed like `FETCHED`. This is how
+/// `cache gc` tells the mods you look at from the ones you don't.
+pub static VIEWED: &str = "viewed";
+
+/// The key for a profile's own copy of a personal record, like its tracked list. Records
+/// for no profile in particular keep the plain key.
+pub fn profile_key(base: &str, profile: &str) -> String {
+    if profile.is_empty() {
+        base.to_string()
+    } else {
+        format!("{}@{}", base, profile)
+    }
+}
+
+/// Set by `--max-age` to override every type's own max age.
+static MAX_AGE: OnceCell<Duration> = OnceCell::new();
 
 /// Get the item, looking in local cache first then calling to the Nexus if not found.
-/// Set refresh to true if you want to check the Nexus even if you have a cache hit.
-pub fn get<T>(key: &<T as Cacheable>::K, refresh: bool, nexus: &mut NexusClient) -> Option<Box<T>>
+/// A cached copy older than its type's max age is refreshed automatically. Set refresh to
+/// true if you want to check the Nexus even if you have a fresh cache hit. A failed refresh
+/// falls back to the cached copy; a failed first fetch is an error. Offline, the cached
+/// copy is all there is, however old.
+pub fn get<T, N: NexusApi>(
+    key: &<T as Cacheable>::K,
+    refresh: bool,
+    nexus: &mut N,
+) -> Result<Box<T>, NexusError>
 where
     T: Cacheable + Debug,
 {
+    if nexus.is_offline() {
+        return local::<T>(key).ok_or_else(|| NexusError::Offline(format!("{} isn't cached", key)));
+    }
     if let Some(found) = local::<T>(key) {
-        if refresh {
-            if let Some(fetched) = T::fetch(key, nexus, Some(found.etag().to_string())) {
+        if !refresh {
+            if !is_stale::<T>(key) {
+                return Ok(found);
+            }
+            log::info!("    ⧗ cached copy is stale");
+        }
+        match T::fetch(key, nexus, Some(found.etag().to_string())) {
+            Ok(Some(fetched)) => {
                 log::info!("    ↪ refreshed nexus data");
                 let merged = found.update(&fetched);
                 match merged.store() {
@@ -44,50 +90,163 @@ where
                         log::warn!("Failed to store refreshed object! {e:?}");
                     }
                 }
-                Some(Box::new(merged))
-            } else {
+                Ok(Box::new(merged))
+            }
+            Ok(None) => {
                 log::info!("    ↩ no update; responding with cached");
-                Some(found)
+                if let Err(e) = note_fetched::<T>(key) {
+                    log::warn!("Failed to note when {key} was checked! {e:?}");
+                }
+                Ok(found)
+            }
+            Err(e) => {
+                log::warn!("Couldn't refresh {key}; responding with cached. {e}");
+                Ok(found)
             }
-        } else {
-            Some(found)
-        }
-    } else if let Some(fetched) = T::fetch(key, nexus, None) {
-        log::info!("    ﹢ first fetch of nexus data");
-        if fetched.store().is_ok() {
-            log::info!("    ✓ cached new nexus data");
         }
-        Some(fetched)
     } else {
-        log::info!("    ␀nexus gave us nothing");
-        None
+        match T::fetch(key, nexus, None)? {
+            Some(fetched) => {
+                log::info!("    ﹢ first fetch of nexus data");
+                if fetched.store().is_ok() {
+                    log::info!("    ✓ cached new nexus data");
+                }
+                Ok(fetched)
+            }
+            None => {
+                // Only a conditional fetch should ever come back empty.
+                log::info!("    ␀nexus gave us nothing");
+                Err(NexusError::NotFound {
+                    uri: key.to_string(),
+                    message: "the Nexus responded with nothing".to_string(),
+                })
+            }
+        }
     }
 }
 
-/// Given a bucket name and appropriate types, return a kv bucket for the data.
-pub fn bucket<T>() -> Option<kv::Bucket<'static, &'static str, Json<T>>>
+/// Look for an item locally, in the cache, by type and key.
+pub fn local<T>(key: &<T as Cacheable>::K) -> Option<Box<T>>
 where
     T: Cacheable,
 {
-    let db = kvstore();
-
-    match db.bucket::<&str, Json<T>>(Some(T::bucket_name())) {
+    let found = match storage().get(T::bucket_name(), &key.to_string()) {
+        Ok(v) => v?,
         Err(e) => {
-            log::info!("Can't open bucket {}! {:?}", T::bucket_name(), e);
+            log::warn!("Can't read {} from {}! {:?}", key, T::bucket_name(), e);
+            return None;
+        }
+    };
+    match serde_json::from_str::<T>(&found) {
+        Ok(v) => Some(Box::new(v)),
+        Err(e) => {
+            log::warn!(
+                "Can't make sense of cached {} in {}: {}",
+                key,
+                T::bucket_name(),
+                e
+            );
             None
         }
-        Ok(v) => Some(v),
     }
 }
 
-/// Look for an item locally, in the kv store, by type and key.
-pub fn local<T>(key: &<T as Cacheable>::K) -> Option<Box<T>>
+/// Write an item to the cache, in its type's bucket under its own key. Everything we
+/// store came from the Nexus, so this also notes when it was fetched.
+pub fn save<T>(item: &T) -> anyhow::Result<usize>
 where
     T: Cacheable,
 {
-    let bucket = bucket::<T>().unwrap();
-    let found: Option<Json<T>> = bucket.get(&&*key.to_string()).ok()?;
-    found.map(|x| Box::new(x.into_inner()))
+    let json = serde_json::to_string(item)?;
+    storage().set(T::bucket_name(), &item.key().to_string(), &json)?;
+    note_fetched::<T>(&item.key())?;
+    Ok(1)
+}
+
+/// Note that a cached item was just checked with the Nexus, as when it answers that our copy
+/// is current.
+pub fn note_fetched<T: Cacheable>(key: &<T as Cacheable>::K) -> anyhow::Result<()> {
+    set_record_fetched(T::bucket_name(), &key.to_string(), Some(Utc::now()))
+}
+
+/// When a cached item was last fetched from the Nexus. Items cached before we kept track
+/// have no fetch time.
+pub fn fetched_at<T: Cacheable>(key: &<T as Cacheable>::K) -> Option<DateTime<Utc>> {
+    record_fetched_at(T::bucket_name(), &key.to_string())
+}
+
+/// When the record under this bucket and key was last fetched from the Nexus, for callers
+/// that don't know its type.
+pub fn record_fetched_at(bucket: &str, key: &str) -> Option<DateTime<Utc>> {
+    let json = storage()
+        .get(FETCHED, &format!("{}/{}", bucket, key))
+        .ok()??;
+    serde_json::from_str(&json).ok()
+}
+
+/// Note when the record under this bucket and key was fetched, or forget, if we don't know.
+pub fn set_record_fetched(
+    bucket: &str,
+    key: &str,
+    when: Option<DateTime<Utc>>,
+) -> anyhow::Result<()> {
+    let key = format!("{}/{}", bucket, key);
+    match when {
+        Some(when) => storage().set(FETCHED, &key, &serde_json::to_string(&when)?),
+        None => storage().remove(FETCHED, &key),
+    }
+}
+
+/// Note that a cached item was just shown to someone.
+pub fn note_viewed<T: Cacheable>(key: &<T as Cacheable>::K) -> anyhow::Result<()> {
+    let json = serde_json::to_string(&Utc::now())?;
+    storage().set(VIEWED, &format!("{}/{}", T::bucket_name(), key), &json)
+}
+
+/// Note that cached items were just shown to someone in a listing, all in one write.
+pub fn note_all_viewed<T: Cacheable>(
+    keys: impl IntoIterator<Item = <T as Cacheable>::K>,
+) -> anyhow::Result<()> {
+    let keys: Vec<String> = keys.into_iter().map(|key| key.to_string()).collect();
+    set_records_viewed(T::bucket_name(), &keys, Utc::now())
+}
+
+/// Note when the records under this bucket and these keys were viewed, all in one write.
+pub fn set_records_viewed(
+    bucket: &str,
+    keys: &[String],
+    when: DateTime<Utc>,
+) -> anyhow::Result<()> {
+    let json = serde_json::to_string(&when)?;
+    let records: Vec<(String, String)> = keys
+        .iter()
+        .map(|key| (format!("{}/{}", bucket, key), json.clone()))
+        .collect();
+    storage().set_many(VIEWED, &records)
+}
+
+/// When the record under this bucket and key was last shown to someone, if ever.
+pub fn record_viewed_at(bucket: &str, key: &str) -> Option<DateTime<Utc>> {
+    let json = storage()
+        .get(VIEWED, &format!("{}/{}", bucket, key))
+        .ok()??;
+    serde_json::from_str(&json).ok()
+}
+
+/// Override the max age of every type of cached item.
+pub fn override_max_age(age: Duration) {
+    let _ = MAX_AGE.set(age);
+}
+
+/// Whether a cached item is older than we're willing to trust. An item with no fetch time
+/// was cached before we kept track, so its age is unknown. Calling it stale by default would
+/// refresh every old record on its next read and spend the quota doing it, so it's trusted,
+/// unless a max age was asked for explicitly, which no unknown age can be shown to meet.
+pub fn is_stale<T: Cacheable>(key: &<T as Cacheable>::K) -> bool {
+    let Some(when) = fetched_at::<T>(key) else {
+        return MAX_AGE.get().is_some();
+    };
+    Utc::now() - when > MAX_AGE.get().copied().unwrap_or_else(T::max_age)
 }
 
 /// The main trait for objects we store.
@@ -99,12 +258,23 @@ where
 
     /// Get the name of the bucket where these items are stored.
     fn bucket_name() -> &'static str;
+    /// How long a cached copy is good for before `get` checks the Nexus for a newer one.
+    fn max_age() -> Duration;
     /// Get an item of this type, looking in local storage first then fetching from the Nexus if it
     /// isn't found locally. Set `refresh` to true to do a conditional GET to the Nexus for updated
     /// data even if we have a local hit. All implementations just call the parametrized get.
-    fn get(key: &Self::K, refresh: bool, nexus: &mut NexusClient) -> Option<Box<Self>>;
-    /// Fetch an item from the Nexus by key. Each data type has a unique endpoint.
-    fn fetch(key: &Self::K, nexus: &mut NexusClient, etag: Option<String>) -> Option<Box<Self>>;
+    fn get<N: NexusApi>(
+        key: &Self::K,
+        refresh: bool,
+        nexus: &mut N,
+    ) -> Result<Box<Self>, NexusError>;
+    /// Fetch an item from the Nexus by key. Each data type has a unique endpoint. Responds
+    /// with `None` if the etag shows our copy is current.
+    fn fetch<N: NexusApi>(
+        key: &Self::K,
+        nexus: &mut N,
+        etag: Option<String>,
+    ) -> Result<Option<Box<Self>>, NexusError>;
     /// Get this item's key
     fn key(&self) -> Self::K;
     /// Get an etag for this data.
@@ -118,6 +288,49 @@ where
     fn update(&self, other: &Self) -> Self;
 }
 
+/// One kind of record we cache: the bucket it's kept in, and a way to read one back
+/// without knowing its type, responding with the key it belongs under.
+#[derive(Debug, Clone, Copy)]
+pub struct CachedType {
+    pub bucket: &'static str,
+    pub read: fn(&str) -> anyhow::Result<String>,
+}
+
+impl CachedType {
+    fn of<T: Cacheable>() -> Self {
+        Self {
+            bucket: T::bucket_name(),
+            read: read_key::<T>,
+        }
+    }
+}
+
+fn read_key<T: Cacheable>(json: &str) -> anyhow::Result<String> {
+    let item: T = serde_json::from_str(json)?;
+    Ok(item.key().to_string())
+}
+
+/// Every type of record kept in the cache.
+pub fn cached_types() -> Vec<CachedType> {
+    vec![
+        CachedType::of::<AuthenticatedUser>(),
+        CachedType::of::<Changelogs>(),
+        CachedType::of::<EndorsementList>(),
+        CachedType::of::<Files>(),
+        CachedType::of::<GameMetadata>(),
+        CachedType::of::<ModInfoFull>(),
+        CachedType::of::<Tracked>(),
+        CachedType {
+            bucket: HISTORY,
+            read: ModHistory::read_key,
+        },
+        CachedType {
+            bucket: ARCHIVE,
+            read: ArchivedMod::read_key,
+        },
+    ]
+}
+
 /// A commonly-used key type that composes the game's name and a mod id.
 #[derive(Debug, Clone)]
 pub struct CompoundKey {
@@ -132,6 +345,10 @@ impl CompoundKey {
             mod_id,
         }
     }
+
+    pub fn mod_id(&self) -> u32 {
+        self.mod_id
+    }
 }
 
 impl Display for CompoundKey {
@@ -161,6 +378,93 @@ pub struct ModInfoList {
     pub mods: Vec<ModInfoFull>,
 }
 
+/// How far back to ask the Nexus for recently-changed mods. These are the only periods it
+/// accepts.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub enum UpdatePeriod {
+    Day,
+    Week,
+    Month,
+}
+
+impl FromStr for UpdatePeriod {
+    type Err = anyhow::Error;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input.to_lowercase().as_str() {
+            "1d" | "day" => Ok(UpdatePeriod::Day),
+            "1w" | "week" => Ok(UpdatePeriod::Week),
+            "1m" | "month" => Ok(UpdatePeriod::Month),
+            _ => anyhow::bail!("period must be one of 1d, 1w, or 1m"),
+        }
+    }
+}
+
+impl Display for UpdatePeriod {
+    // This is the form the Nexus wants in the query string.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UpdatePeriod::Day => write!(f, "1d"),
+            UpdatePeriod::Week => write!(f, "1w"),
+            UpdatePeriod::Month => write!(f, "1m"),
+        }
+    }
+}
+
+/// How old cached data may be before it's refreshed, as a count and a unit: 30m, 12h, 1d,
+/// 2w. Zero means every cached item is stale.
+#[derive(Debug, Clone, Copy)]
+pub struct MaxAge(pub Duration);
+
+impl FromStr for MaxAge {
+    type Err = anyhow::Error;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let input = input.trim().to_lowercase();
+        if input == "0" {
+            return Ok(MaxAge(Duration::zero()));
+        }
+        let usage = "max age must be a number followed by m, h, d, or w, like 12h";
+        let Some(unit) = input.chars().last() else {
+            anyhow::bail!(usage);
+        };
+        let count = &input[..input.len() - unit.len_utf8()];
+        let Ok(count) = count.parse::<i64>() else {
+            anyhow::bail!(usage);
+        };
+        if count < 0 {
+            anyhow::bail!("max age can't be negative");
+        }
+        let seconds: u64 = match unit {
+            'm' => 60,
+            'h' => 60 * 60,
+            'd' => 24 * 60 * 60,
+            'w' => 7 * 24 * 60 * 60,
+            _ => anyhow::bail!(usage),
+        };
+        (count as u64)
+            .checked_mul(seconds)
+            .and_then(|secs| Duration::from_std(std::time::Duration::from_secs(secs)).ok())
+            .map(MaxAge)
+            .ok_or_else(|| anyhow::anyhow!("max age {} is longer than we can count", input))
+    }
+}
+
+/// One entry in a game's list of recently-changed mods.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ModUpdate {
+    pub mod_id: u32,
+    pub latest_file_update: u64,
+    pub latest_mod_activity: u64,
+}
+
+impl ModUpdate {
+    /// The most recent change of any kind, as a unix timestamp.
+    pub fn latest(&self) -> u64 {
+        self.latest_file_update.max(self.latest_mod_activity)
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct EndorseResponse {
     pub message: String,
@@ -171,3 +475,33 @@ pub struct EndorseResponse {
 pub struct TrackingResponse {
     pub message: String,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn max_age_parses_counts_and_units() {
+        assert_eq!("0".parse::<MaxAge>().unwrap().0, Duration::zero());
+        assert_eq!("30m".parse::<MaxAge>().unwrap().0, Duration::minutes(30));
+        assert_eq!("12H".parse::<MaxAge>().unwrap().0, Duration::hours(12));
+        assert_eq!("2w".parse::<MaxAge>().unwrap().0, Duration::weeks(2));
+    }
+
+    #[test]
+    fn max_age_rejects_nonsense_without_panicking() {
+        let inputs = [
+            "",
+            "h",
+            "12",
+            "12é",
+            "é",
+            "-1d",
+            "12y",
+            "9223372036854775807w",
+        ];
+        for input in inputs {
+            assert!(input.parse::<MaxAge>().is_err(), "{:?} parsed", input);
+        }
+    }
+}
@@ -5,15 +5,15 @@ use std::io::Write;
 use std::process::{Command, Stdio};
 
 use chrono::{DateTime, Utc};
-use kv::{Codec, Json};
 use once_cell::sync::Lazy;
 use owo_colors::OwoColorize;
 use regex::Regex;
 use serde::{Deserialize, Serialize};
 use terminal_size::*;
 
-use crate::nexus::NexusClient;
-use crate::{Cacheable, CompoundKey, EndorsementStatus};
+use crate::nexus::{NexusApi, NexusError};
+use crate::storage::ModQuery;
+use crate::{storage, ArchivedMod, Cacheable, CompoundKey, EndorsementStatus, ModHistory};
 
 // We do solemnly swear or affirm that these regexes are valid.
 // This is a terrifyingly stupid bbcode -> markdown converter.
@@ -77,7 +77,7 @@ impl Default for ModEndorsement {
     }
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
 #[serde(rename_all = "snake_case")]
 pub enum ModStatus {
     Hidden,
@@ -141,18 +141,20 @@ pub struct ModInfoFull {
 }
 
 impl ModInfoFull {
-    pub fn by_prefix(prefix: &str) -> Vec<Self> {
-        let bucket = super::bucket::<Self>().unwrap();
-
-        let mut result: Vec<Self> = Vec::new();
-        if let Ok(prefixes) = bucket.iter_prefix(&prefix) {
-            for item in prefixes.flatten() {
-                if let Ok(modinfo) = item.value::<Json<Self>>() {
-                    result.push(modinfo.into_inner());
+    /// Find the cached mods for a game that match the query.
+    pub fn find(game: &str, query: &ModQuery) -> anyhow::Result<Vec<Self>> {
+        let found = storage()
+            .find_mods(game, query)?
+            .into_iter()
+            .filter_map(|json| match serde_json::from_str::<Self>(&json) {
+                Ok(modinfo) => Some(modinfo),
+                Err(e) => {
+                    log::warn!("Can't make sense of a cached mod for {}: {}", game, e);
+                    None
                 }
-            }
-        }
-        result
+            })
+            .collect();
+        Ok(found)
     }
 
     pub fn available(&self) -> bool {
@@ -215,6 +217,26 @@ impl ModInfoFull {
         self.updated_timestamp
     }
 
+    pub fn version(&self) -> &str {
+        &self.version
+    }
+
+    pub fn endorsement_count(&self) -> u32 {
+        self.endorsement_count
+    }
+
+    /// Whether you've endorsed this mod, as of when it was fetched.
+    pub fn endorsement_status(&self) -> EndorsementStatus {
+        self.endorsement
+            .as_ref()
+            .map(|e| e.endorse_status.clone())
+            .unwrap_or(EndorsementStatus::Undecided)
+    }
+
+    pub fn contains_adult_content(&self) -> bool {
+        self.contains_adult_content
+    }
+
     pub fn url(&self) -> String {
         format!(
             "https://www.nexusmods.com/{}/mods/{}",
@@ -387,18 +409,25 @@ impl Cacheable for ModInfoFull {
         "mods"
     }
 
-    fn get(key: &CompoundKey, refresh: bool, nexus: &mut NexusClient) -> Option<Box<Self>> {
-        super::get::<Self>(key, refresh, nexus)
+    fn max_age() -> chrono::Duration {
+        chrono::Duration::days(1)
+    }
+
+    fn get<N: NexusApi>(
+        key: &CompoundKey,
+        refresh: bool,
+        nexus: &mut N,
+    ) -> Result<Box<Self>, NexusError> {
+        super::get::<Self, N>(key, refresh, nexus)
     }
 
-    fn fetch(
+    fn fetch<N: NexusApi>(
         key: &CompoundKey,
-        nexus: &mut NexusClient,
+        nexus: &mut N,
         etag: Option<String>,
-    ) -> Option<Box<Self>> {
-        nexus
-            .mod_by_id(&key.domain_name, key.mod_id, etag)
-            .map(Box::new)
+    ) -> Result<Option<Box<Self>>, NexusError> {
+        let found = nexus.mod_by_id(&key.domain_name, key.mod_id, etag)?;
+        Ok(found.map(Box::new))
     }
 
     fn key(&self) -> CompoundKey {
@@ -417,10 +446,22 @@ impl Cacheable for ModInfoFull {
     }
 
     fn store(&self) -> anyhow::Result<usize> {
-        let bucket = super::bucket::<Self>().unwrap();
-        bucket.set(&&*self.key().to_string(), &Json(self.clone()))?;
-        bucket.flush()?;
-        Ok(1)
+        // Note what's changing before the cached copy is overwritten.
+        if let Err(e) = ModHistory::record(self) {
+            log::warn!("Couldn't update the history of {}: {}", self.key(), e);
+        }
+        // A mod that's no longer published is about to lose its description. Archive the
+        // cached copy if it's still the published one.
+        if self.status != ModStatus::Published {
+            if let Err(e) = ArchivedMod::archive_cached(&self.key()) {
+                log::warn!("Couldn't archive {}: {}", self.key(), e);
+            }
+        }
+        let stored = super::save(self)?;
+        if let Err(e) = super::index::update(self) {
+            log::warn!("Couldn't index {} for searching: {}", self.key(), e);
+        }
+        Ok(stored)
     }
 
     fn update(&self, other: &Self) -> Self {
@@ -0,0 +1,665 @@
+//! The search language. A query is a list of conditions a mod must meet:
+//!
+//! ```text
+//! author:foo category:"Animation" status:published endorsed:no updated:>2023-01-01 "dragon"
+//! ```
+//!
+//! Plain words and quoted text are looked up in the full-text index. `field:value` tests one
+//! field of the mod. Conditions side by side must all hold; join them with `OR` for either,
+//! put `NOT` in front of one to exclude it, and group them with parentheses.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt::Display;
+use std::str::FromStr;
+
+use chrono::{DateTime, NaiveDate};
+use regex::Regex;
+use serde_json::Value;
+
+use super::{fuzzy, index, EndorsementStatus, ModInfoFull, ModStatus};
+use crate::storage::{case_insensitive, ModQuery};
+
+static FIELDS: &[&str] = &[
+    "name",
+    "author",
+    "category",
+    "status",
+    "endorsed",
+    "adult",
+    "updated",
+    "endorsements",
+];
+
+/// How a field compares to the value in the query.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compare {
+    Less,
+    AtMost,
+    Equal,
+    AtLeast,
+    More,
+}
+
+impl Compare {
+    /// Split a leading comparison off a value, like the `>` in `>2023-01-01`.
+    fn split(value: &str) -> (Self, &str) {
+        for (prefix, compare) in [
+            (">=", Compare::AtLeast),
+            ("<=", Compare::AtMost),
+            (">", Compare::More),
+            ("<", Compare::Less),
+            ("=", Compare::Equal),
+        ] {
+            if let Some(rest) = value.strip_prefix(prefix) {
+                return (compare, rest);
+            }
+        }
+        (Compare::Equal, value)
+    }
+
+    fn test<T: PartialOrd>(&self, left: T, right: T) -> bool {
+        match self {
+            Compare::Less => left < right,
+            Compare::AtMost => left <= right,
+            Compare::Equal => left == right,
+            Compare::AtLeast => left >= right,
+            Compare::More => left > right,
+        }
+    }
+}
+
+/// A category, by its Nexus id or its name.
+#[derive(Debug, Clone)]
+pub enum Category {
+    Id(u16),
+    Name(String),
+}
+
+#[derive(Debug, Clone)]
+pub enum Query {
+    And(Box<Query>, Box<Query>),
+    Or(Box<Query>, Box<Query>),
+    Not(Box<Query>),
+    /// Mods that use every word in the text, anywhere.
+    Text(String),
+    /// Mods with names matching the pattern.
+    Name(Regex),
+    /// Mods with names like this one, give or take typos, spacing, and missing words.
+    Fuzzy(String),
+    /// Mods with uploader or author credits matching the pattern.
+    Author(Regex),
+    Category(Category),
+    Status(ModStatus),
+    /// Mods you've given any of these endorsement statuses.
+    Endorsed(Vec<EndorsementStatus>),
+    Adult(bool),
+    /// Compared by day.
+    Updated(Compare, NaiveDate),
+    Endorsements(Compare, u32),
+}
+
+impl Query {
+    /// Mods with names matching a case-insensitive regex.
+    pub fn name(pattern: &str) -> anyhow::Result<Self> {
+        Ok(Query::Name(case_insensitive(pattern)?))
+    }
+
+    /// Mods with author credits or uploaders matching a case-insensitive regex.
+    pub fn author(pattern: &str) -> anyhow::Result<Self> {
+        Ok(Query::Author(case_insensitive(pattern)?))
+    }
+
+    /// The query as if words the index leaves out, like "the" or "a", had never been typed,
+    /// since searching for them finds nothing. `None` if that leaves nothing to search for.
+    fn without_unindexed(self) -> Option<Query> {
+        fn join(
+            left: Option<Query>,
+            right: Option<Query>,
+            both: fn(Box<Query>, Box<Query>) -> Query,
+        ) -> Option<Query> {
+            match (left, right) {
+                (Some(left), Some(right)) => Some(both(Box::new(left), Box::new(right))),
+                (Some(one), None) | (None, Some(one)) => Some(one),
+                (None, None) => None,
+            }
+        }
+        match self {
+            Query::And(left, right) => join(
+                left.without_unindexed(),
+                right.without_unindexed(),
+                Query::And,
+            ),
+            Query::Or(left, right) => join(
+                left.without_unindexed(),
+                right.without_unindexed(),
+                Query::Or,
+            ),
+            Query::Not(inner) => inner.without_unindexed().map(|q| Query::Not(Box::new(q))),
+            Query::Text(text) if index::words(&text).is_empty() => None,
+            other => Some(other),
+        }
+    }
+
+    /// The conditions the whole query needs to hold, not counting any under an `OR` or a
+    /// `NOT`.
+    fn required(&self) -> Vec<&Query> {
+        match self {
+            Query::And(left, right) => {
+                let mut found = left.required();
+                found.extend(right.required());
+                found
+            }
+            other => vec![other],
+        }
+    }
+
+    /// The narrowest question the cache can answer for us before the rest of the query is
+    /// checked here.
+    pub fn prefilter(&self) -> ModQuery {
+        self.required()
+            .into_iter()
+            .find_map(|query| match query {
+                Query::Status(status) => Some(ModQuery::Status(status.clone())),
+                Query::Name(pattern) => Some(ModQuery::Name(pattern.as_str().to_string())),
+                Query::Author(pattern) => Some(ModQuery::Author(pattern.as_str().to_string())),
+                _ => None,
+            })
+            .unwrap_or(ModQuery::All)
+    }
+
+    /// Every text search in the query.
+    fn texts<'a>(&'a self, found: &mut Vec<&'a str>) {
+        match self {
+            Query::And(left, right) | Query::Or(left, right) => {
+                left.texts(found);
+                right.texts(found);
+            }
+            Query::Not(inner) => inner.texts(found),
+            Query::Text(text) => found.push(text),
+            _ => {}
+        }
+    }
+
+    /// The only mods that could match, if the text searches say.
+    fn candidates(&self, hits: &HashMap<String, HashMap<u32, f64>>) -> Option<HashSet<u32>> {
+        match self {
+            Query::And(left, right) => match (left.candidates(hits), right.candidates(hits)) {
+                (Some(left), Some(right)) => Some(left.intersection(&right).copied().collect()),
+                (Some(found), None) | (None, Some(found)) => Some(found),
+                (None, None) => None,
+            },
+            Query::Or(left, right) => {
+                let mut found = left.candidates(hits)?;
+                found.extend(right.candidates(hits)?);
+                Some(found)
+            }
+            Query::Text(text) => hits.get(text).map(|h| h.keys().copied().collect()),
+            _ => None,
+        }
+    }
+
+    fn matches(&self, modinfo: &ModInfoFull, search: &Search<'_>) -> bool {
+        match self {
+            Query::And(left, right) => {
+                left.matches(modinfo, search) && right.matches(modinfo, search)
+            }
+            Query::Or(left, right) => {
+                left.matches(modinfo, search) || right.matches(modinfo, search)
+            }
+            Query::Not(inner) => !inner.matches(modinfo, search),
+            Query::Text(text) => search
+                .hits
+                .get(text)
+                .is_some_and(|hits| hits.contains_key(&modinfo.mod_id())),
+            Query::Name(pattern) => pattern.is_match(&modinfo.name()),
+            Query::Fuzzy(name) => fuzzy::matches(name, &modinfo.name()),
+            Query::Author(pattern) => {
+                pattern.is_match(modinfo.author()) || pattern.is_match(modinfo.uploaded_by())
+            }
+            Query::Category(Category::Id(id)) => modinfo.category_id() == *id,
+            Query::Category(Category::Name(name)) => search
+                .categories
+                .get(&modinfo.category_id())
+                .is_some_and(|found| found.eq_ignore_ascii_case(name)),
+            Query::Status(status) => modinfo.status() == *status,
+            Query::Endorsed(statuses) => statuses.contains(&modinfo.endorsement_status()),
+            Query::Adult(adult) => modinfo.contains_adult_content() == *adult,
+            Query::Updated(compare, date) => {
+                DateTime::from_timestamp(modinfo.updated_timestamp() as i64, 0)
+                    .is_some_and(|updated| compare.test(updated.date_naive(), *date))
+            }
+            Query::Endorsements(compare, count) => {
+                compare.test(modinfo.endorsement_count(), *count)
+            }
+        }
+    }
+
+    /// How well a mod fits the query's text searches and loose name matches. What's under a
+    /// `NOT` doesn't count.
+    fn relevance(&self, modinfo: &ModInfoFull, search: &Search<'_>) -> f64 {
+        match self {
+            Query::And(left, right) | Query::Or(left, right) => {
+                left.relevance(modinfo, search) + right.relevance(modinfo, search)
+            }
+            Query::Text(text) => search
+                .hits
+                .get(text)
+                .and_then(|hits| hits.get(&modinfo.mod_id()))
+                .copied()
+                .unwrap_or_default(),
+            Query::Fuzzy(name) => fuzzy::score(name, &modinfo.name()),
+            _ => 0.0,
+        }
+    }
+}
+
+/// A query ready to run against one game's mods: its text searches done, and the game's
+/// category names at hand.
+#[derive(Debug)]
+pub struct Search<'a> {
+    query: &'a Query,
+    hits: HashMap<String, HashMap<u32, f64>>,
+    categories: HashMap<u16, String>,
+}
+
+impl<'a> Search<'a> {
+    pub fn new(
+        query: &'a Query,
+        game: &str,
+        categories: HashMap<u16, String>,
+    ) -> anyhow::Result<Self> {
+        let mut texts = Vec::new();
+        query.texts(&mut texts);
+        let mut hits = HashMap::new();
+        for text in texts {
+            if !hits.contains_key(text) {
+                let found: HashMap<u32, f64> = index::search(game, text)?.into_iter().collect();
+                hits.insert(text.to_string(), found);
+            }
+        }
+        Ok(Self {
+            query,
+            hits,
+            categories,
+        })
+    }
+
+    /// The only mods worth looking at, if the text searches narrow it down.
+    pub fn candidates(&self) -> Option<HashSet<u32>> {
+        self.query.candidates(&self.hits)
+    }
+
+    pub fn matches(&self, modinfo: &ModInfoFull) -> bool {
+        self.query.matches(modinfo, self)
+    }
+
+    /// How well a matching mod fits the query; 0 if nothing in it is scored.
+    pub fn relevance(&self, modinfo: &ModInfoFull) -> f64 {
+        self.query.relevance(modinfo, self)
+    }
+}
+
+impl FromStr for Query {
+    type Err = anyhow::Error;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let tokens = tokenize(input)?;
+        let mut parser = Parser { tokens, next: 0 };
+        let Some(query) = parser.any()? else {
+            anyhow::bail!("There's nothing to search for.");
+        };
+        if let Some(token) = parser.peek() {
+            anyhow::bail!("Unexpected {} in the query.", token);
+        }
+        query.without_unindexed().ok_or_else(|| {
+            anyhow::anyhow!(
+                "There's nothing to search for: its words are too common or too short to look up."
+            )
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Open,
+    Close,
+    And,
+    Or,
+    Not,
+    Text(String),
+    Field(String, String),
+}
+
+impl Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Open => write!(f, "`(`"),
+            Token::Close => write!(f, "`)`"),
+            Token::And => write!(f, "AND"),
+            Token::Or => write!(f, "OR"),
+            Token::Not => write!(f, "NOT"),
+            Token::Text(text) => write!(f, "`{}`", text),
+            Token::Field(field, value) => write!(f, "`{}:{}`", field, value),
+        }
+    }
+}
+
+/// Read the text up to a closing quote, the opening one already consumed.
+fn quoted(chars: &mut std::iter::Peekable<std::str::Chars<'_>>) -> anyhow::Result<String> {
+    let mut text = String::new();
+    for c in chars.by_ref() {
+        if c == '"' {
+            return Ok(text);
+        }
+        text.push(c);
+    }
+    anyhow::bail!("A quote in the query is never closed.")
+}
+
+fn tokenize(input: &str) -> anyhow::Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = input.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+        match c {
+            '(' => {
+                chars.next();
+                tokens.push(Token::Open);
+            }
+            ')' => {
+                chars.next();
+                tokens.push(Token::Close);
+            }
+            '"' => {
+                chars.next();
+                tokens.push(Token::Text(quoted(&mut chars)?));
+            }
+            _ => {
+                let mut word = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || c == '(' || c == ')' || c == '"' {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+                tokens.push(word_token(word, &mut chars)?);
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+fn word_token(
+    word: String,
+    chars: &mut std::iter::Peekable<std::str::Chars<'_>>,
+) -> anyhow::Result<Token> {
+    match word.as_str() {
+        "AND" => return Ok(Token::And),
+        "OR" => return Ok(Token::Or),
+        "NOT" => return Ok(Token::Not),
+        _ => {}
+    }
+    let Some((field, value)) = word.split_once(':') else {
+        return Ok(Token::Text(word));
+    };
+    let field = field.to_lowercase();
+    if !FIELDS.contains(&field.as_str()) {
+        anyhow::bail!(
+            "There's no `{}:` to search by. Try one of: {}.",
+            field,
+            FIELDS.join(", ")
+        );
+    }
+    // A quoted value, as in `category:"Armour and Clothing"`.
+    let value = if value.is_empty() && chars.peek() == Some(&'"') {
+        chars.next();
+        quoted(chars)?
+    } else {
+        value.to_string()
+    };
+    if value.is_empty() {
+        anyhow::bail!("`{}:` needs something to look for.", field);
+    }
+    Ok(Token::Field(field, value))
+}
+
+/// Parses, loosest first: OR, then AND, whether it's written or not, then NOT.
+struct Parser {
+    tokens: Vec<Token>,
+    next: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.next)
+    }
+
+    fn advance(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.next).cloned();
+        self.next += 1;
+        token
+    }
+
+    fn any(&mut self) -> anyhow::Result<Option<Query>> {
+        let Some(mut query) = self.all()? else {
+            return Ok(None);
+        };
+        while self.peek() == Some(&Token::Or) {
+            self.advance();
+            let Some(right) = self.all()? else {
+                anyhow::bail!("OR needs something after it.");
+            };
+            query = Query::Or(Box::new(query), Box::new(right));
+        }
+        Ok(Some(query))
+    }
+
+    fn all(&mut self) -> anyhow::Result<Option<Query>> {
+        let Some(mut query) = self.negated()? else {
+            return Ok(None);
+        };
+        loop {
+            match self.peek() {
+                Some(Token::And) => {
+                    self.advance();
+                    let Some(right) = self.negated()? else {
+                        anyhow::bail!("AND needs something after it.");
+                    };
+                    query = Query::And(Box::new(query), Box::new(right));
+                }
+                Some(Token::Or) | Some(Token::Close) | None => return Ok(Some(query)),
+                Some(_) => {
+                    let Some(right) = self.negated()? else {
+                        return Ok(Some(query));
+                    };
+                    query = Query::And(Box::new(query), Box::new(right));
+                }
+            }
+        }
+    }
+
+    fn negated(&mut self) -> anyhow::Result<Option<Query>> {
+        if self.peek() == Some(&Token::Not) {
+            self.advance();
+            let Some(inner) = self.negated()? else {
+                anyhow::bail!("NOT needs something after it.");
+            };
+            return Ok(Some(Query::Not(Box::new(inner))));
+        }
+        self.single()
+    }
+
+    fn single(&mut self) -> anyhow::Result<Option<Query>> {
+        let query = match self.peek().cloned() {
+            None | Some(Token::Close) => return Ok(None),
+            Some(Token::Not) => return self.negated(),
+            Some(token @ (Token::And | Token::Or)) => {
+                anyhow::bail!("{} needs something before it.", token)
+            }
+            Some(Token::Open) => {
+                self.advance();
+                let inner = self.any()?;
+                if self.advance() != Some(Token::Close) {
+                    anyhow::bail!("A `(` in the query is never closed.");
+                }
+                inner.ok_or_else(|| anyhow::anyhow!("There's nothing between `(` and `)`."))?
+            }
+            Some(Token::Text(text)) => {
+                self.advance();
+                Query::Text(text)
+            }
+            Some(Token::Field(field, value)) => {
+                self.advance();
+                field_query(&field, &value)?
+            }
+        };
+        Ok(Some(query))
+    }
+}
+
+fn yes_or_no(field: &str, value: &str) -> anyhow::Result<bool> {
+    match value.to_lowercase().as_str() {
+        "yes" | "true" => Ok(true),
+        "no" | "false" => Ok(false),
+        _ => anyhow::bail!("`{}:` takes yes or no, not `{}`.", field, value),
+    }
+}
+
+fn field_query(field: &str, value: &str) -> anyhow::Result<Query> {
+    let query = match field {
+        "name" => Query::name(value)?,
+        "author" => Query::author(value)?,
+        "category" => match value.parse::<u16>() {
+            Ok(id) => Query::Category(Category::Id(id)),
+            Err(_) => Query::Category(Category::Name(value.to_string())),
+        },
+        "status" => {
+            let spelled = value.to_lowercase().replace([' ', '-'], "_");
+            let status = serde_json::from_value(Value::String(spelled)).map_err(|_| {
+                anyhow::anyhow!(
+                    "`{}` isn't a mod status. Try published, hidden, removed, wastebinned, not_published, or under_moderation.",
+                    value
+                )
+            })?;
+            Query::Status(status)
+        }
+        "endorsed" => Query::Endorsed(match value.to_lowercase().as_str() {
+            "abstained" => vec![EndorsementStatus::Abstained],
+            "undecided" => vec![EndorsementStatus::Undecided],
+            _ => {
+                if yes_or_no(field, value)? {
+                    vec![EndorsementStatus::Endorsed]
+                } else {
+                    vec![EndorsementStatus::Undecided, EndorsementStatus::Abstained]
+                }
+            }
+        }),
+        "adult" => Query::Adult(yes_or_no(field, value)?),
+        "updated" => {
+            let (compare, date) = Compare::split(value);
+            let date = NaiveDate::parse_from_str(date, "%Y-%m-%d").map_err(|_| {
+                anyhow::anyhow!("`updated:` takes a date like 2023-01-01, not `{}`.", date)
+            })?;
+            Query::Updated(compare, date)
+        }
+        "endorsements" => {
+            let (compare, count) = Compare::split(value);
+            let count = count
+                .parse::<u32>()
+                .map_err(|_| anyhow::anyhow!("`endorsements:` takes a number, not `{}`.", count))?;
+            Query::Endorsements(compare, count)
+        }
+        _ => unreachable!("the tokenizer only lets through fields we know"),
+    };
+    Ok(query)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The shape of a query, for comparing against what it should have parsed to.
+    fn shape(query: &Query) -> String {
+        match query {
+            Query::And(left, right) => format!("(and {} {})", shape(left), shape(right)),
+            Query::Or(left, right) => format!("(or {} {})", shape(left), shape(right)),
+            Query::Not(inner) => format!("(not {})", shape(inner)),
+            Query::Text(text) => text.clone(),
+            other => format!("{:?}", other),
+        }
+    }
+
+    fn parsed(input: &str) -> String {
+        shape(&input.parse().unwrap())
+    }
+
+    #[test]
+    fn tokenizes() {
+        let text = |t: &str| Token::Text(t.to_string());
+        let field = |f: &str, v: &str| Token::Field(f.to_string(), v.to_string());
+        assert_eq!(
+            tokenize(r#"Author:foo category:"Armour and Clothing" (big OR "two words") NOT x"#)
+                .unwrap(),
+            vec![
+                field("author", "foo"),
+                field("category", "Armour and Clothing"),
+                Token::Open,
+                text("big"),
+                Token::Or,
+                text("two words"),
+                Token::Close,
+                Token::Not,
+                text("x"),
+            ]
+        );
+        assert_eq!(
+            tokenize("or and not").unwrap(),
+            vec![text("or"), text("and"), text("not")]
+        );
+        assert!(tokenize(r#"dragon "unclosed"#).is_err());
+        assert!(tokenize("colour:red").is_err());
+        assert!(tokenize("author:").is_err());
+    }
+
+    #[test]
+    fn parses_by_precedence() {
+        assert_eq!(parsed("dragon OR big sword"), "(or dragon (and big sword))");
+        assert_eq!(parsed("dragon big OR sword"), "(or (and dragon big) sword)");
+        assert_eq!(
+            parsed("dragon AND big OR sword"),
+            "(or (and dragon big) sword)"
+        );
+        assert_eq!(parsed("NOT dragon sword"), "(and (not dragon) sword)");
+        assert_eq!(parsed("NOT NOT dragon"), "(not (not dragon))");
+        assert_eq!(
+            parsed("dragon (big OR sword)"),
+            "(and dragon (or big sword))"
+        );
+        for bad in [
+            "",
+            "dragon OR",
+            "AND dragon",
+            "NOT",
+            "(dragon",
+            "dragon)",
+            "()",
+        ] {
+            assert!(bad.parse::<Query>().is_err(), "{:?} should not parse", bad);
+        }
+    }
+
+    #[test]
+    fn leaves_out_unindexed_words() {
+        assert_eq!(parsed("the dragon"), "dragon");
+        assert_eq!(parsed("4 dragon a sword"), "(and dragon sword)");
+        assert_eq!(parsed("dragon OR the"), "dragon");
+        assert_eq!(parsed("NOT the dragon"), "dragon");
+        assert_eq!(parsed("\"the dragon\""), "the dragon");
+        assert!("the a".parse::<Query>().is_err());
+        assert!("(the) OR and".parse::<Query>().is_err());
+    }
+}
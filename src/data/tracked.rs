@@ -1,14 +1,14 @@
 use std::collections::HashMap;
 use std::fmt::Display;
 
-use kv::Json;
 use owo_colors::OwoColorize;
 use prettytable::{row, Table};
 use serde::{Deserialize, Serialize};
 
-use super::Cacheable;
+use super::{profile_key, Cacheable};
+use crate::config;
 use crate::formatting::pluralize_mod;
-use crate::nexus::NexusClient;
+use crate::nexus::{NexusApi, NexusError};
 
 // Store and retrieve the tracked mods list.
 
@@ -22,6 +22,9 @@ pub struct ModReference {
 pub struct Tracked {
     pub mods: Vec<ModReference>,
     pub etag: String,
+    /// The profile this list belongs to; empty for none.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub profile: String,
 }
 
 impl Display for ModReference {
@@ -55,8 +58,9 @@ impl Tracked {
         result
     }
 
-    pub fn listkey() -> &'static str {
-        "tracked"
+    /// The key for the tracked list of the profile in use.
+    pub fn listkey() -> String {
+        profile_key("tracked", config::profile())
     }
 }
 
@@ -80,22 +84,37 @@ impl Display for Tracked {
 }
 
 impl Cacheable for Tracked {
-    type K = &'static str;
+    type K = String;
 
     fn bucket_name() -> &'static str {
         "mod_ref_lists"
     }
 
-    fn get(_key: &&str, refresh: bool, nexus: &mut NexusClient) -> Option<Box<Self>> {
-        super::get::<Self>(&"tracked", refresh, nexus)
+    fn max_age() -> chrono::Duration {
+        chrono::Duration::hours(1)
     }
 
-    fn fetch(_key: &&str, nexus: &mut NexusClient, etag: Option<String>) -> Option<Box<Self>> {
-        nexus.tracked(etag).map(Box::new)
+    fn get<N: NexusApi>(
+        _key: &String,
+        refresh: bool,
+        nexus: &mut N,
+    ) -> Result<Box<Self>, NexusError> {
+        super::get::<Self, N>(&Self::listkey(), refresh, nexus)
     }
 
-    fn key(&self) -> &'static str {
-        "tracked"
+    fn fetch<N: NexusApi>(
+        _key: &String,
+        nexus: &mut N,
+        etag: Option<String>,
+    ) -> Result<Option<Box<Self>>, NexusError> {
+        Ok(nexus.tracked(etag)?.map(|mut tracked| {
+            tracked.profile = config::profile().to_string();
+            Box::new(tracked)
+        }))
+    }
+
+    fn key(&self) -> String {
+        profile_key("tracked", &self.profile)
     }
 
     fn etag(&self) -> &str {
@@ -107,10 +126,7 @@ impl Cacheable for Tracked {
     }
 
     fn store(&self) -> anyhow::Result<usize> {
-        let bucket = super::bucket::<Self>().unwrap();
-        bucket.set(&self.key(), &Json(self.clone()))?;
-        bucket.flush()?;
-        Ok(1)
+        super::save(self)
     }
 
     fn update(&self, other: &Self) -> Self {
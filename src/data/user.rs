@@ -1,12 +1,13 @@
 use std::collections::HashMap;
 use std::fmt::Display;
 
-use kv::Json;
 use log::{info, warn};
 use owo_colors::OwoColorize;
 use serde::{Deserialize, Serialize};
 
-use crate::nexus::NexusClient;
+use super::profile_key;
+use crate::config;
+use crate::nexus::{NexusApi, NexusError};
 use crate::Cacheable;
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
@@ -19,6 +20,13 @@ pub struct AuthenticatedUser {
     profile_url: String,
     user_id: u32,
     etag: String,
+    /// The modcache profile this account belongs to; empty for none.
+    #[serde(skip_serializing_if = "String::is_empty")]
+    profile: String,
+    /// The api key the Nexus echoes back. Read so it isn't kept with the fields we ignore,
+    /// and never written out.
+    #[serde(rename = "key", skip_serializing)]
+    api_key: String,
     #[serde(flatten)]
     ignored: Option<HashMap<String, serde_json::Value>>,
 }
@@ -34,40 +42,50 @@ impl Default for AuthenticatedUser {
             profile_url: "".to_string(),
             ignored: None,
             etag: "".to_string(),
+            profile: "".to_string(),
+            api_key: "".to_string(),
         }
     }
 }
 
 impl Cacheable for AuthenticatedUser {
-    type K = &'static str;
+    type K = String;
 
     fn bucket_name() -> &'static str {
         "authed_users"
     }
 
-    fn get(_key: &&str, _refresh: bool, nexus: &mut NexusClient) -> Option<Box<Self>> {
+    fn max_age() -> chrono::Duration {
+        chrono::Duration::days(1)
+    }
+
+    fn get<N: NexusApi>(
+        _key: &String,
+        _refresh: bool,
+        nexus: &mut N,
+    ) -> Result<Box<Self>, NexusError> {
         // We do not ever rely on cache for this.
-        if let Ok(user) = nexus.validate() {
-            match user.store() {
-                Ok(_) => info!("stored authed user"),
-                Err(e) => warn!("failed to store authed user! {:?}", e),
-            }
-            Some(Box::new(user))
-        } else {
-            None
+        let mut user = nexus.validate()?;
+        user.profile = config::profile().to_string();
+        match user.store() {
+            Ok(_) => info!("stored authed user"),
+            Err(e) => warn!("failed to store authed user! {:?}", e),
         }
+        Ok(Box::new(user))
     }
 
-    fn fetch(_key: &&str, nexus: &mut NexusClient, _etag: Option<String>) -> Option<Box<Self>> {
-        if let Ok(user) = nexus.validate() {
-            Some(Box::new(user))
-        } else {
-            None
-        }
+    fn fetch<N: NexusApi>(
+        _key: &String,
+        nexus: &mut N,
+        _etag: Option<String>,
+    ) -> Result<Option<Box<Self>>, NexusError> {
+        let mut user = nexus.validate()?;
+        user.profile = config::profile().to_string();
+        Ok(Some(Box::new(user)))
     }
 
-    fn key(&self) -> &'static str {
-        "authed_user"
+    fn key(&self) -> String {
+        profile_key("authed_user", &self.profile)
     }
 
     fn etag(&self) -> &str {
@@ -79,10 +97,7 @@ impl Cacheable for AuthenticatedUser {
     }
 
     fn store(&self) -> anyhow::Result<usize> {
-        let bucket = super::bucket::<Self>().unwrap();
-        bucket.set(&self.key(), &Json(self.clone()))?;
-        bucket.flush()?;
-        Ok(1)
+        super::save(self)
     }
 
     fn update(&self, other: &Self) -> Self {
@@ -102,3 +117,17 @@ impl Display for AuthenticatedUser {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn never_writes_out_the_api_key() {
+        let user: AuthenticatedUser =
+            serde_json::from_str(include_str!("../../test/fixtures/validate.json")).unwrap();
+        let json = serde_json::to_string(&user).unwrap();
+        assert!(!json.contains("deadbeefcafed00d"), "{}", json);
+        assert!(json.contains("notreal@example.com"));
+    }
+}
@@ -1,9 +1,12 @@
+use chrono::{Duration, Utc};
+use chrono_humanize::HumanTime;
 use num_format::{Locale, ToFormattedString};
 use owo_colors::OwoColorize;
 use term_grid::{Cell, Direction, Filling, Grid, GridOptions};
 use terminal_size::*;
 
 use crate::data::modinfo::ModInfoFull;
+use crate::data::{fetched_at, Cacheable};
 
 pub fn print_in_grid(items: Vec<impl ToString>, column_hint: usize) {
     let width = if let Some((Width(w), Height(_h))) = terminal_size() {
@@ -47,3 +50,15 @@ pub fn emit_modlist_with_caption(modlist: Vec<ModInfoFull>, caption: &str) {
         print_in_grid(modlist.iter().map(|xs| xs.mod_id()).collect(), 10);
     }
 }
+
+/// Say how long ago a cached item came from the Nexus, or that nobody knows.
+pub fn emit_fetched_age<T: Cacheable>(key: &<T as Cacheable>::K) {
+    let note = match fetched_at::<T>(key) {
+        Some(when) if Utc::now() - when < Duration::minutes(1) => {
+            "fetched from the Nexus just now".to_string()
+        }
+        Some(when) => format!("fetched from the Nexus {}", HumanTime::from(when)),
+        None => "age unknown: cached before modcache noted when".to_string(),
+    };
+    println!("{}", note.dimmed());
+}
@@ -0,0 +1,53 @@
+//! Ctrl-C while modcache shares its cache with other processes asks it to stop rather than
+//! killing it outright, which could cut a write or a checkpoint short and leave the server
+//! file behind. Requests to the Nexus and bulk fetches stop at the next safe point, and the
+//! error unwinds to `main`, which cleans up. A second Ctrl-C stops it at once.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Once;
+use std::time::{Duration, Instant};
+
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+static WATCHING: Once = Once::new();
+
+/// Catch Ctrl-C from now on, instead of letting it kill the process.
+pub fn watch() {
+    WATCHING.call_once(|| {
+        std::thread::spawn(|| {
+            let runtime = match tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+            {
+                Ok(runtime) => runtime,
+                Err(e) => {
+                    log::debug!("Unable to watch for interrupts: {}", e);
+                    return;
+                }
+            };
+            while runtime.block_on(tokio::signal::ctrl_c()).is_ok() {
+                if REQUESTED.swap(true, Ordering::SeqCst) {
+                    crate::storage::remote::stop_serving();
+                    std::process::exit(130);
+                }
+                log::warn!("Stopping once the work underway is saved. Ctrl-C again to stop now.");
+            }
+        });
+    });
+}
+
+/// Whether we've been asked to stop.
+pub fn requested() -> bool {
+    REQUESTED.load(Ordering::SeqCst)
+}
+
+/// Sleep, waking early if asked to stop.
+pub fn sleep(duration: Duration) {
+    let until = Instant::now() + duration;
+    while !requested() {
+        let left = until.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return;
+        }
+        std::thread::sleep(left.min(Duration::from_millis(250)));
+    }
+}
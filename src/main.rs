@@ -7,10 +7,11 @@
     unused_qualifications
 )]
 
+use std::path::Path;
 use std::str::FromStr;
 use std::sync::Mutex;
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use clap::{Parser, Subcommand};
 use clap_complete::{generate, Shell};
 use dotenvy::dotenv;
@@ -19,13 +20,25 @@ use owo_colors::OwoColorize;
 use serde::{Deserialize, Serialize};
 
 pub mod commands;
+pub mod config;
+pub mod credentials;
 pub mod data;
 pub mod formatting;
+pub mod interrupt;
 pub mod nexus;
+pub mod storage;
 
+use commands::archive::ArchiveCommand;
+use commands::cache::CacheCommand;
+use commands::files::{file_by_id, mod_files, primary_file};
 use commands::mods::{show_game_mods, show_single_mod};
+use commands::populate::Budget;
 use commands::*;
 use data::*;
+use nexus::cassette::Cassette;
+use nexus::retry::RetryPolicy;
+use nexus::NexusApi;
+use storage::Storage;
 use unicase::UniCase;
 
 static REQ_LIMIT: u16 = 50;
@@ -51,6 +64,13 @@ pub struct Flags {
         global = true
     )]
     json: bool,
+    #[arg(
+        long,
+        conflicts_with = "json",
+        help = "Emit text or json, whatever your config file says",
+        global = true
+    )]
+    output: Option<config::Output>,
     #[arg(
         short,
         long,
@@ -58,6 +78,41 @@ pub struct Flags {
         global = true
     )]
     refresh: bool,
+    #[arg(
+        long,
+        help = "When out of api calls, sleep until the quota resets instead of stopping",
+        global = true
+    )]
+    wait_for_reset: bool,
+    #[arg(
+        long,
+        default_value_t = 8,
+        value_parser = clap::value_parser!(u8).range(1..=30),
+        help = "How many requests to make at once when fetching in bulk",
+        global = true
+    )]
+    jobs: u8,
+    #[arg(
+        long,
+        help = "Refresh cached data older than this, like 30m, 12h, or 2d, whatever its type",
+        global = true
+    )]
+    max_age: Option<MaxAge>,
+    #[arg(
+        long,
+        env = "NEXUS_OFFLINE",
+        value_parser = clap::builder::BoolishValueParser::new(),
+        help = "Use only what's cached and never talk to the Nexus",
+        global = true
+    )]
+    offline: bool,
+    #[arg(
+        long,
+        env = "MODCACHE_PROFILE",
+        help = "Act as this profile from your config, with its own api key and tracked mods",
+        global = true
+    )]
+    profile: Option<String>,
 }
 
 #[derive(Clone, Debug, Subcommand)]
@@ -65,9 +120,23 @@ enum Command {
     /// Test your Nexus API key; whoami
     #[clap(alias = "whoami")]
     Validate,
+    /// Check a Nexus API key and save it in your system keyring, or an encrypted file.
+    ///
+    /// The key is read from stdin if it isn't a terminal. Saved keys are used when
+    /// NEXUS_API_KEY isn't set and the profile has no api_key of its own.
+    Login {
+        /// Use an encrypted file even if there's a keyring.
+        #[clap(long)]
+        file: bool,
+    },
+    /// Forget the API key saved by login.
+    Logout,
+    /// Show your remaining Nexus api calls and when they reset, without spending one.
+    Quota,
     /// Fetch your list of tracked mods and show a by-game summary.
     Tracked {
         /// Optionally, display a detailed list of tracked mods for a specific game.
+        #[clap(value_parser = config::game_name)]
         game: Option<String>,
     },
     /// Populate the local cache with mods tracked for a specific game.
@@ -75,8 +144,29 @@ enum Command {
         /// The number of API calls allowed before stopping.
         #[clap(short, long, default_value_t = REQ_LIMIT)]
         limit: u16,
-        /// The game to populate.
-        #[clap(default_value = "skyrimspecialedition")]
+        /// Set to `auto` to spend whatever your quota allows instead of a fixed limit.
+        #[clap(short, long, conflicts_with = "limit")]
+        budget: Option<Budget>,
+        /// With `--budget auto`, how many api calls to leave for interactive use.
+        #[clap(long, default_value_t = config::get().reserve)]
+        reserve: u16,
+        /// Keep going, sleeping through quota resets, until every tracked mod is cached.
+        /// Progress is saved, so a stopped daemon resumes where it left off.
+        #[clap(long, conflicts_with_all = ["limit", "budget"])]
+        daemon: bool,
+        /// The games to populate.
+        #[clap(default_value = config::get().game.as_str(), value_parser = config::game_name)]
+        games: Vec<String>,
+    },
+    /// Refresh only the cached and tracked mods the Nexus says changed recently.
+    ///
+    /// Costs a few api calls for a whole game, where `--refresh` costs one per mod.
+    Sync {
+        /// How far back to look for changes: 1d, 1w, or 1m.
+        #[clap(short, long, default_value = "1w")]
+        period: UpdatePeriod,
+        /// The game to sync.
+        #[clap(default_value = config::get().game.as_str(), value_parser = config::game_name)]
         game: String,
     },
     /// Refresh your tracked mods and pull new ones to cache.
@@ -84,44 +174,58 @@ enum Command {
     /// Executes `tracked` then `populate` for the given game.
     Update {
         /// The game to update.
-        #[clap(default_value = "skyrimspecialedition")]
+        #[clap(default_value = config::get().game.as_str(), value_parser = config::game_name)]
         game: String,
     },
-    /// Find mods that mention this string in their names or text summaries.
+    /// Find mods that mention these words anywhere in their text, best match first.
+    ///
+    /// Searches names, summaries, descriptions, credits, and the changelogs and file
+    /// descriptions of mods whose files you've looked at. Words match in any form, so
+    /// "texture" finds "textures".
+    ///
+    /// Narrow the search with name:, author:, category:, status:, endorsed:, adult:,
+    /// updated:, and endorsements:, as in
+    /// `author:foo category:"Animation" endorsed:no updated:>2023-01-01 dragon`.
+    /// Everything must match; join conditions with OR for either, put NOT in front of one
+    /// to exclude it, and group them with parentheses.
     ///
     /// Pass --refresh to update cached data from the Nexus for each result.
     Search {
-        /// Optional sort for the matches: name, author, date
-        #[clap(short, long, default_value = "id")]
+        /// Optional sort for the matches: relevance, name, author, date, id
+        #[clap(short, long, default_value = config::get().sort.as_deref().unwrap_or("relevance"))]
         sort: SortKey,
-        /// Look for mods that mention this string
+        /// What to look for: words, conditions, or both
         text: String,
         /// The slug for the game to filter by.
-        #[clap(default_value = "skyrimspecialedition")]
+        #[clap(default_value = config::get().game.as_str(), value_parser = config::game_name)]
         game: String,
     },
     /// Find mods with names matching the given string, for the named game.
     ///
     /// Pass --refresh to update cached data from the Nexus for each result.
     ByName {
-        /// Optional sort for the matches: name, author, date
-        #[clap(short, long, default_value = "id")]
+        /// Optional sort for the matches: relevance, name, author, date, id
+        #[clap(short, long, default_value = config::get().sort.as_deref().unwrap_or("relevance"))]
         sort: SortKey,
+        /// Match names loosely, forgiving typos, spacing, and missing words, best match first.
+        /// Without this the name is a case-insensitive regular expression.
+        #[clap(long)]
+        fuzzy: bool,
         /// Look for mods with names similar to this
         name: String,
         /// The slug for the game to filter by.
-        #[clap(default_value = "skyrimspecialedition")]
+        #[clap(default_value = config::get().game.as_str(), value_parser = config::game_name)]
         game: String,
     },
     /// Find mods by the given author, for the named game.
     ByAuthor {
         /// Optional sort for the matches: name, author, date, id
-        #[clap(short, long, default_value = "id")]
+        #[clap(short, long, default_value = config::get().sort.as_deref().unwrap_or("id"))]
         sort: SortKey,
         /// Look for mods with authors similar to this
         author: String,
         /// The slug for the game to filter by.
-        #[clap(default_value = "skyrimspecialedition")]
+        #[clap(default_value = config::get().game.as_str(), value_parser = config::game_name)]
         game: String,
     },
     /// Track a specific mod
@@ -129,13 +233,13 @@ enum Command {
         /// The id of the mod to track
         mod_id: u32,
         /// Which game the mods belong to; Nexus short name
-        #[clap(default_value = "skyrimspecialedition")]
+        #[clap(default_value = config::get().game.as_str(), value_parser = config::game_name)]
         game: String,
     },
     /// Stop tracking a mod or list of mods, by id.
     Untrack {
         /// Which game the mods belong to; Nexus short name
-        #[clap(short, long, default_value = "skyrimspecialedition")]
+        #[clap(short, long, default_value = config::get().game.as_str(), value_parser = config::game_name)]
         game: String,
         /// The ids of the mods to stop tracking
         ids: Vec<u32>,
@@ -143,6 +247,7 @@ enum Command {
     /// Stop tracking all removed mods for a specific game
     UntrackRemoved {
         /// Which game to clean up your tracking list for; Nexus short name
+        #[clap(value_parser = config::game_name)]
         game: String,
     },
     /// Get changelogs for a specific mod.
@@ -150,7 +255,7 @@ enum Command {
         /// The id of the mod to fetch changelogs for
         mod_id: u32,
         /// Which game the mods belong to; Nexus short name
-        #[clap(default_value = "skyrimspecialedition")]
+        #[clap(default_value = config::get().game.as_str(), value_parser = config::game_name)]
         game: String,
     },
     /// Get the list of files for a specific mod.
@@ -158,7 +263,7 @@ enum Command {
         /// The id of the mod to fetch files for
         mod_id: u32,
         /// Which game the mods belong to; Nexus short name
-        #[clap(default_value = "skyrimspecialedition")]
+        #[clap(default_value = config::get().game.as_str(), value_parser = config::game_name)]
         game: String,
     },
     /// Get information about the mod's primary file, usefully formatted.
@@ -166,7 +271,7 @@ enum Command {
         /// The id of the mod to fetch files for
         mod_id: u32,
         /// Which game the mods belong to; Nexus short name
-        #[clap(default_value = "skyrimspecialedition")]
+        #[clap(default_value = config::get().game.as_str(), value_parser = config::game_name)]
         game: String,
     },
     /// Get information about a specific mod file.
@@ -174,7 +279,7 @@ enum Command {
         /// The id of the mod to fetch files for
         mod_id: u32,
         /// Which game the mods belong to; Nexus short name
-        #[clap(default_value = "skyrimspecialedition")]
+        #[clap(default_value = config::get().game.as_str(), value_parser = config::game_name)]
         game: String,
         /// the id of the file to get detailed info on
         file_id: usize,
@@ -182,11 +287,13 @@ enum Command {
     /// Fetch the list of mods you have endorsed
     Endorsements {
         /// Optionally filter displayed endorsements by this game name.
+        #[clap(value_parser = config::game_name)]
         game: Option<String>,
     },
     /// Endorse a mod or list of mods
     Endorse {
         /// Which game the mods belong to; Nexus short name
+        #[clap(value_parser = config::game_name)]
         game: String,
         /// The ids of the mods to endorse
         ids: Vec<u32>,
@@ -194,51 +301,52 @@ enum Command {
     /// Abstain from endorsing a mod.
     Abstain {
         /// Which game the mods belong to; Nexus short name
+        #[clap(value_parser = config::game_name)]
         game: String,
         /// The id of the mod to refuse to endorse
         mod_id: u32,
     },
     /// Get Nexus metadata about a game by slug
     Game {
-        #[clap(default_value = "skyrimspecialedition")]
+        #[clap(default_value = config::get().game.as_str(), value_parser = config::game_name)]
         game: String,
     },
     /// Get all mods locally cached for this game by slug
     Mods {
-        #[clap(default_value = "skyrimspecialedition")]
+        #[clap(default_value = config::get().game.as_str(), value_parser = config::game_name)]
         game: String,
     },
     /// Find mods for this game that are hidden, probably so you can untrack them.
     Hidden {
         /// The slug for the game to consider.
-        #[clap(default_value = "skyrimspecialedition")]
+        #[clap(default_value = config::get().game.as_str(), value_parser = config::game_name)]
         game: String,
     },
     /// Find mods for this game that are removed, probably so you can untrack them.
     Removed {
         /// The slug for the game to consider.
-        #[clap(default_value = "skyrimspecialedition")]
+        #[clap(default_value = config::get().game.as_str(), value_parser = config::game_name)]
         game: String,
     },
     /// Find mods for this game that were wastebinned by their authors.
     Wastebinned {
         /// The slug for the game to consider.
-        #[clap(default_value = "skyrimspecialedition")]
+        #[clap(default_value = config::get().game.as_str(), value_parser = config::game_name)]
         game: String,
     },
     /// Show the 10 top all-time trending mods for a game
     Trending {
-        #[clap(default_value = "skyrimspecialedition")]
+        #[clap(default_value = config::get().game.as_str(), value_parser = config::game_name)]
         game: String,
     },
     /// Show 10 mods most recently added for a game
     Latest {
-        #[clap(default_value = "skyrimspecialedition")]
+        #[clap(default_value = config::get().game.as_str(), value_parser = config::game_name)]
         game: String,
     },
     /// Show the 10 mods most recently updated for a game
     Updated {
-        #[clap(default_value = "skyrimspecialedition")]
+        #[clap(default_value = config::get().game.as_str(), value_parser = config::game_name)]
         game: String,
     },
     /// Display detailed info for a single mod
@@ -246,13 +354,69 @@ enum Command {
         /// The id of the mod to show
         mod_id: u32,
         /// Which game the mods belong to; Nexus short name
-        #[clap(default_value = "skyrimspecialedition")]
+        #[clap(default_value = config::get().game.as_str(), value_parser = config::game_name)]
+        game: String,
+    },
+    /// Show how a mod's name, version, status, credits, summary, and description have
+    /// changed over the times it's been fetched.
+    History {
+        /// The id of the mod to show
+        mod_id: u32,
+        /// Which game the mod belongs to; Nexus short name
+        #[clap(default_value = config::get().game.as_str(), value_parser = config::game_name)]
         game: String,
     },
+    /// Keep the descriptions, file lists, and changelogs of tracked mods in case their
+    /// authors pull them, and browse the ones that are gone.
+    Archive {
+        #[clap(subcommand)]
+        cmd: ArchiveCommand,
+    },
+    /// Look after the cache: show what's in it, check it, clean it up, and compact it.
+    Cache {
+        #[clap(subcommand)]
+        cmd: CacheCommand,
+    },
+    /// Write the cache to a file you can share or move to another machine. Name it with a
+    /// .gz extension to compress it.
+    Export {
+        /// Where to write the dump
+        path: String,
+        /// Include your tracked and endorsement lists, for moving your own cache rather
+        /// than sharing it
+        #[clap(long)]
+        personal: bool,
+    },
+    /// Load a dump made by export. Where you already have a record, the newer one wins.
+    Import {
+        /// The dump to load
+        path: String,
+    },
     Completions {
         #[clap(value_enum)]
         shell: Shell,
     },
+    /// Run a local stand-in for the Nexus API that serves the json test fixtures.
+    ///
+    /// Point modcache at it with `NEXUS_BASE_URL=http://localhost:<port>`.
+    #[clap(hide = true)]
+    MockServer {
+        /// The port to listen on.
+        #[clap(short, long, default_value_t = 8080)]
+        port: u16,
+        /// The directory holding fixture json.
+        #[clap(short, long, default_value = "test/fixtures")]
+        fixtures: String,
+        /// The hourly api call limit to advertise and enforce.
+        #[clap(long, default_value_t = 100)]
+        hourly_limit: u16,
+        /// The daily api call limit to advertise and enforce.
+        #[clap(long, default_value_t = 2500)]
+        daily_limit: u16,
+        /// The fraction of requests, from 0.0 to 1.0, to fail with a 503.
+        #[clap(long, default_value_t = 0.0)]
+        flaky: f64,
+    },
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
@@ -261,6 +425,8 @@ pub enum SortKey {
     Name,
     Date,
     Author,
+    /// The order the search found them in: best match first, for full-text searches.
+    Relevance,
 }
 
 impl FromStr for SortKey {
@@ -272,6 +438,7 @@ impl FromStr for SortKey {
             "name" => Ok(SortKey::Name),
             "date" => Ok(SortKey::Date),
             "author" => Ok(SortKey::Author),
+            "relevance" => Ok(SortKey::Relevance),
             _ => Ok(SortKey::Id),
         }
     }
@@ -288,22 +455,29 @@ impl SortByKey for Vec<ModInfoFull> {
             SortKey::Name => self.sort_by_key(|xs| UniCase::new(xs.name())),
             SortKey::Date => self.sort_by_key(|xs| xs.updated_timestamp()),
             SortKey::Author => self.sort_by_key(|xs| UniCase::new(xs.uploaded_by().to_string())),
+            SortKey::Relevance => {}
         }
     }
 }
 
-/// A shared reference to our kv store on disk.
-static STORE: OnceCell<kv::Store> = OnceCell::new();
+/// A shared reference to our cache on disk.
+static STORE: OnceCell<Box<dyn Storage>> = OnceCell::new();
 
-/// Fetch our kv store instance
-pub fn kvstore() -> &'static kv::Store {
-    STORE.get_or_init(|| {
-        let dbpath = std::env::var("NEXUS_CACHE_PATH")
-            .unwrap_or_else(|_| "./db/nexus_cache.sled".to_string());
-        log::debug!("Storing data in {}", dbpath.bold());
-        let cfg = kv::Config::new(dbpath);
-        kv::Store::new(cfg).expect("unable to create k/v store!")
-    })
+/// Fetch our cache. `NEXUS_CACHE_BACKEND` picks the kv store, the default, or sqlite;
+/// `NEXUS_CACHE_PATH` says where it lives. Either falls back to the config file.
+pub fn storage() -> &'static dyn Storage {
+    STORE
+        .get_or_init(|| {
+            let config = config::get();
+            let backend = config.backend;
+            let dbpath = std::env::var("NEXUS_CACHE_PATH")
+                .ok()
+                .or_else(|| config.cache_path.clone())
+                .unwrap_or_else(|| backend.default_path().to_string());
+            log::debug!("Storing data in {}", dbpath.bold());
+            backend.open(&dbpath).expect("unable to open the cache!")
+        })
+        .as_ref()
 }
 
 /// A shared reference to our nexus client. This is persistent so we can manage
@@ -313,17 +487,55 @@ static NEXUS: OnceCell<Mutex<nexus::NexusClient>> = OnceCell::new();
 /// Fetch our nexus client instance
 pub fn nexus_client() -> &'static Mutex<nexus::NexusClient> {
     NEXUS.get_or_init(|| {
-        let nexuskey = std::env::var("NEXUS_API_KEY")
-            .expect("You must provide your personal Nexus API key in the env var NEXUS_API_KEY.");
+        // Without a key, the client refuses to send requests, but everything that only
+        // reads the cache still works. Replaying a cassette needs no key at all. A key saved
+        // by `login` is looked up only once a request needs it.
+        let nexuskey = config::get().api_key().unwrap_or_default();
 
-        let nexus = nexus::NexusClient::new(nexuskey);
+        let base_url = match std::env::var("NEXUS_BASE_URL") {
+            Ok(base_url) => {
+                log::debug!("Talking to the Nexus at {}", base_url.bold());
+                base_url
+            }
+            Err(_) => nexus::NEXUS_BASE.to_string(),
+        };
+        let mut nexus = nexus::NexusClient::for_profile(nexuskey, base_url, config::profile());
+        nexus.set_key_source(credentials::stored_key);
         Mutex::new(nexus)
     })
 }
 
+/// The cassette `NEXUS_REPLAY` says to play back, or `NEXUS_RECORD` says to record to.
+fn cassette_from_env() -> Result<Option<Cassette>> {
+    if let Ok(path) = std::env::var("NEXUS_REPLAY") {
+        log::info!("Replaying Nexus responses from {}", path.bold());
+        let cassette = Cassette::replay(Path::new(&path))
+            .context("Unable to replay the cassette NEXUS_REPLAY names")?;
+        return Ok(Some(cassette));
+    }
+    if let Ok(path) = std::env::var("NEXUS_RECORD") {
+        log::info!("Recording Nexus responses to {}", path.bold());
+        let cassette = Cassette::record(Path::new(&path))
+            .context("Unable to record to the cassette NEXUS_RECORD names")?;
+        return Ok(Some(cassette));
+    }
+    Ok(None)
+}
+
 fn main() -> Result<()> {
+    let result = run();
+    // Other modcache processes shouldn't go looking for a cache we're no longer sharing.
+    storage::remote::stop_serving();
+    result
+}
+
+fn run() -> Result<()> {
     dotenv().ok();
-    let flags = Flags::parse();
+    config::init()?;
+    let mut flags = Flags::parse();
+    if flags.output.unwrap_or(config::get().output) == config::Output::Json {
+        flags.json = true;
+    }
 
     loggerv::Logger::new()
         .verbosity(flags.verbose as u64)
@@ -332,81 +544,150 @@ fn main() -> Result<()> {
         .colors(true)
         .init()
         .unwrap();
+    if let Some(profile) = &flags.profile {
+        log::info!("Using the profile {}", profile.bold());
+    }
 
-    let mut nexus = nexus_client().lock().unwrap();
+    // The mock server is the one command that must never talk to the real Nexus.
+    if let Command::MockServer {
+        port,
+        ref fixtures,
+        hourly_limit,
+        daily_limit,
+        flaky,
+    } = flags.cmd
+    {
+        return nexus::mock::serve(port, fixtures, hourly_limit, daily_limit, flaky);
+    }
+
+    if let Some(MaxAge(age)) = flags.max_age {
+        override_max_age(age);
+    }
+
+    let cassette = cassette_from_env()?;
+    let mut guard = nexus_client().lock().unwrap();
+    let nexus: &mut nexus::NexusClient = &mut guard;
+    if let Some(cassette) = cassette {
+        nexus.set_cassette(cassette);
+    }
+    nexus.set_retry_policy(RetryPolicy {
+        wait_for_reset: flags.wait_for_reset,
+        ..Default::default()
+    });
+    nexus.set_offline(flags.offline);
 
     match flags.cmd {
         Command::Validate => {
-            handle_validate(&flags, &mut nexus)?;
+            handle_validate(&flags, nexus)?;
+        }
+        Command::Login { file } => {
+            login::login(&flags, file, nexus)?;
+        }
+        Command::Logout => {
+            login::logout(&flags)?;
+        }
+        Command::Quota => {
+            handle_quota(&flags, nexus)?;
         }
         Command::Tracked { ref game } => {
-            handle_tracked(&flags, game, &mut nexus)?;
+            handle_tracked(&flags, game, nexus)?;
         }
-        Command::Populate { ref game, limit } => {
-            handle_populate(&flags, game, limit, &mut nexus)?;
+        Command::Populate {
+            ref games,
+            limit,
+            budget,
+            reserve,
+            daemon,
+        } => {
+            if daemon {
+                populate::daemon(&flags, games, reserve, nexus)?;
+            } else {
+                let budget = budget.unwrap_or(Budget::Fixed(limit));
+                handle_populate(&flags, games, budget, reserve, nexus)?;
+            }
+        }
+        Command::Sync { ref game, period } => {
+            handle_sync(&flags, game, period, nexus)?;
         }
         Command::Update { ref game } => {
             let mut force_refresh = flags.clone();
             force_refresh.refresh = true;
-            handle_tracked(&force_refresh, &None, &mut nexus)?;
-            handle_populate(&force_refresh, game, REQ_LIMIT, &mut nexus)?;
+            handle_tracked(&force_refresh, &None, nexus)?;
+            let games = std::slice::from_ref(game);
+            handle_populate(&force_refresh, games, Budget::Fixed(REQ_LIMIT), 0, nexus)?;
         }
         Command::Search {
             ref text,
             ref game,
             ref sort,
         } => {
-            search::full_text(&flags, game, text, sort, &mut nexus)?;
+            search::full_text(&flags, game, text, sort, nexus)?;
         }
         Command::ByAuthor {
             ref author,
             ref game,
             ref sort,
         } => {
-            search::by_author(&flags, game, author, sort, &mut nexus)?;
+            search::by_author(&flags, game, author, sort, nexus)?;
         }
         Command::ByName {
             ref name,
             ref game,
+            fuzzy,
             ref sort,
         } => {
-            search::by_name(&flags, game, name, sort, &mut nexus)?;
+            search::by_name(&flags, game, name, fuzzy, sort, nexus)?;
         }
         Command::Game { ref game } => {
-            handle_game(&flags, game, &mut nexus)?;
+            handle_game(&flags, game, nexus)?;
         }
         Command::Mods { ref game } => {
-            show_game_mods(&flags, game, &mut nexus)?;
+            show_game_mods(&flags, game, nexus)?;
         }
         Command::Hidden { ref game } => {
-            cleanup::hidden(&flags, game, &mut nexus)?;
+            cleanup::hidden(&flags, game, nexus)?;
         }
         Command::Removed { ref game } => {
-            cleanup::removed(&flags, game, &mut nexus)?;
+            cleanup::removed(&flags, game, nexus)?;
         }
         Command::Wastebinned { ref game } => {
-            cleanup::wastebinned(&flags, game, &mut nexus)?;
+            cleanup::wastebinned(&flags, game, nexus)?;
         }
         Command::Mod { ref game, mod_id } => {
-            show_single_mod(&flags, game, mod_id, &mut nexus)?;
+            show_single_mod(&flags, game, mod_id, nexus)?;
+        }
+        Command::History { ref game, mod_id } => {
+            handle_history(&flags, game, mod_id, nexus)?;
+        }
+        Command::Archive { ref cmd } => {
+            handle_archive(&flags, cmd, nexus)?;
+        }
+        Command::Cache { ref cmd } => {
+            handle_cache(&flags, cmd)?;
+        }
+        Command::Export { ref path, personal } => {
+            dump::export(&flags, Path::new(path), personal)?;
+        }
+        Command::Import { ref path } => {
+            dump::import(&flags, Path::new(path))?;
         }
         Command::Endorsements { ref game } => {
-            handle_endorsements(&flags, game, &mut nexus)?;
+            handle_endorsements(&flags, game, nexus)?;
         }
         Command::Endorse { ref game, ref ids } => {
-            mod_actions::endorse(&flags, game, ids, &mut nexus)?;
+            mod_actions::endorse(&flags, game, ids, nexus)?;
         }
         Command::Abstain { ref game, mod_id } => {
-            mod_actions::abstain(&flags, game, mod_id, &mut nexus)?;
+            mod_actions::abstain(&flags, game, mod_id, nexus)?;
         }
         Command::Track { ref game, mod_id } => {
-            mod_actions::track(&flags, game, mod_id, &mut nexus)?;
+            mod_actions::track(&flags, game, mod_id, nexus)?;
         }
         Command::Untrack { ref game, ref ids } => {
-            mod_actions::untrack(&flags, game, ids, &mut nexus)?;
+            mod_actions::untrack(&flags, game, ids, nexus)?;
         }
         Command::UntrackRemoved { ref game } => {
-            cleanup::untrack_removed(&flags, game, &mut nexus)?;
+            cleanup::untrack_removed(&flags, game, nexus)?;
         }
         Command::Trending { game } => {
             let res = nexus.trending(&game)?;
@@ -423,14 +704,21 @@ fn main() -> Result<()> {
         // TODO: move these into the browser ui once it exists.
         Command::Changelogs { game, mod_id } => {
             let key = CompoundKey::new(game.clone(), mod_id);
-            let maybe = Changelogs::get(&key, flags.refresh, &mut nexus);
+            let maybe = match Changelogs::get(&key, flags.refresh, nexus) {
+                Ok(v) => Some(v),
+                Err(e) if e.is_not_found() => {
+                    println!("No changelogs found for {} #{}.", game, mod_id);
+                    None
+                }
+                Err(e) => return Err(e.into()),
+            };
             if let Some(changelogs) = maybe {
                 if flags.json {
                     let pretty = serde_json::to_string_pretty(&changelogs)?;
                     println!("{}", pretty);
                     return Ok(());
                 }
-                if let Some(mod_info) = ModInfoFull::get(&key, false, &mut nexus) {
+                if let Ok(mod_info) = ModInfoFull::get(&key, false, nexus) {
                     println!(
                         "\nchangelogs for \x1b]8;;{}\x1b\\{}\x1b]8;;\x1b\\",
                         mod_info.url(),
@@ -448,23 +736,24 @@ fn main() -> Result<()> {
             }
         }
         Command::Files { ref game, mod_id } => {
-            return commands::files::mod_files(game.as_str(), mod_id, &flags, &mut nexus)
+            return mod_files(game.as_str(), mod_id, &flags, nexus)
         }
         Command::PrimaryFile { ref game, mod_id } => {
-            return commands::files::primary_file(game.as_str(), mod_id, &flags, &mut nexus);
+            return primary_file(game.as_str(), mod_id, &flags, nexus);
         }
         Command::FileInfo {
             ref game,
             mod_id,
             file_id,
         } => {
-            return commands::files::file_by_id(game.as_str(), mod_id, file_id, &flags, &mut nexus);
+            return file_by_id(game.as_str(), mod_id, file_id, &flags, nexus);
         }
         Command::Completions { shell } => {
             use clap::CommandFactory;
             let mut app = Flags::command();
             generate(shell, &mut app, "modcache", &mut std::io::stdout())
         }
+        Command::MockServer { .. } => unreachable!("handled before the client is created"),
     }
 
     Ok(())
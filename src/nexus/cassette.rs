@@ -0,0 +1,211 @@
+//! Record-and-replay for Nexus traffic. A recording client appends every request and
+//! response it exchanges with the Nexus to a cassette file, one json object per line. A
+//! replaying client serves responses from a cassette and never touches the network, so a
+//! session captured once can be rerun deterministically. Request headers are recorded too,
+//! and a conditional request only replays a response to the same condition. Your api key is
+//! never recorded, but response bodies are, so check the cassette before you share it.
+
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::NexusResponse;
+
+/// Request headers that change what the Nexus says back, so a recording only answers a
+/// request that sent the same ones.
+static MATCHED_HEADERS: &[&str] = &["if-none-match"];
+/// Never written to a cassette.
+static SECRET_HEADERS: &[&str] = &["apikey"];
+
+/// A single request made to the Nexus and what it said back.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Interaction {
+    pub method: String,
+    /// The request path and query, relative to the api's base url.
+    pub path: String,
+    /// The request headers sent, minus the api key. Cassettes recorded before headers were
+    /// kept have none, and match whatever was sent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub headers: Option<Vec<(String, String)>>,
+    pub form: Vec<(String, String)>,
+    pub response: NexusResponse,
+    pub recorded_at: DateTime<Utc>,
+}
+
+impl Interaction {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .as_ref()?
+            .iter()
+            .find(|(k, _v)| k.eq_ignore_ascii_case(name))
+            .map(|(_k, v)| v.as_str())
+    }
+
+    fn matches(
+        &self,
+        method: &str,
+        path: &str,
+        headers: &[(&str, &str)],
+        form: &[(&str, &str)],
+    ) -> bool {
+        let same_headers = self.headers.is_none()
+            || MATCHED_HEADERS.iter().all(|name| {
+                let sent = headers
+                    .iter()
+                    .find(|(k, _v)| k.eq_ignore_ascii_case(name))
+                    .map(|(_k, v)| *v);
+                self.header(name) == sent
+            });
+        self.method == method
+            && self.path == path
+            && same_headers
+            && self.form.len() == form.len()
+            && self
+                .form
+                .iter()
+                .zip(form.iter())
+                .all(|((k1, v1), (k2, v2))| k1 == k2 && v1 == v2)
+    }
+}
+
+#[derive(Debug)]
+pub enum Cassette {
+    Record(Recorder),
+    Replay(Player),
+}
+
+impl Cassette {
+    /// Start recording to the given file, appending if it already exists.
+    pub fn record(path: &Path) -> anyhow::Result<Self> {
+        Ok(Cassette::Record(Recorder::new(path)?))
+    }
+
+    /// Load a cassette for playback.
+    pub fn replay(path: &Path) -> anyhow::Result<Self> {
+        Ok(Cassette::Replay(Player::new(path)?))
+    }
+}
+
+#[derive(Debug)]
+pub struct Recorder {
+    path: PathBuf,
+    file: File,
+}
+
+impl Recorder {
+    fn new(path: &Path) -> anyhow::Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("opening cassette {} for recording", path.display()))?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            file,
+        })
+    }
+
+    pub fn record(
+        &mut self,
+        method: &str,
+        path: &str,
+        headers: &[(&str, &str)],
+        form: &[(&str, &str)],
+        response: &NexusResponse,
+    ) -> anyhow::Result<()> {
+        let interaction = Interaction {
+            method: method.to_string(),
+            path: path.to_string(),
+            headers: Some(
+                headers
+                    .iter()
+                    .filter(|(k, _v)| !SECRET_HEADERS.iter().any(|s| k.eq_ignore_ascii_case(s)))
+                    .map(|(k, v)| (k.to_string(), v.to_string()))
+                    .collect(),
+            ),
+            form: form
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            response: response.clone(),
+            recorded_at: Utc::now(),
+        };
+        let line = serde_json::to_string(&interaction)?;
+        writeln!(self.file, "{}", line)?;
+        self.file.flush()?;
+        log::debug!("recorded {} {} to {}", method, path, self.path.display());
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct Player {
+    path: PathBuf,
+    interactions: Vec<Interaction>,
+    played: Vec<bool>,
+}
+
+impl Player {
+    fn new(path: &Path) -> anyhow::Result<Self> {
+        let file = File::open(path)
+            .with_context(|| format!("opening cassette {} for replay", path.display()))?;
+        let mut interactions = Vec::new();
+        for (lineno, line) in BufReader::new(file).lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let interaction: Interaction = serde_json::from_str(&line).with_context(|| {
+                format!(
+                    "cassette {} line {} is malformed",
+                    path.display(),
+                    lineno + 1
+                )
+            })?;
+            interactions.push(interaction);
+        }
+        let played = vec![false; interactions.len()];
+        Ok(Self {
+            path: path.to_path_buf(),
+            interactions,
+            played,
+        })
+    }
+
+    /// Find the response to this request. Matching recordings are served in the order
+    /// they were made; once all have been played, the last one is repeated.
+    pub fn play(
+        &mut self,
+        method: &str,
+        path: &str,
+        headers: &[(&str, &str)],
+        form: &[(&str, &str)],
+    ) -> anyhow::Result<NexusResponse> {
+        let mut last = None;
+        for (idx, interaction) in self.interactions.iter().enumerate() {
+            if !interaction.matches(method, path, headers, form) {
+                continue;
+            }
+            if !self.played[idx] {
+                self.played[idx] = true;
+                log::debug!("replaying {} {}", method, path);
+                return Ok(interaction.response.clone());
+            }
+            last = Some(idx);
+        }
+
+        match last {
+            Some(idx) => Ok(self.interactions[idx].response.clone()),
+            None => anyhow::bail!(
+                "no recorded response for {} {} in cassette {}",
+                method,
+                path,
+                self.path.display()
+            ),
+        }
+    }
+}
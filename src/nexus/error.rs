@@ -0,0 +1,161 @@
+//! Everything that can go wrong when talking to the Nexus, sorted into the cases a caller
+//! might want to handle differently.
+
+use chrono::{DateTime, Local, Utc};
+
+#[derive(Debug, thiserror::Error)]
+pub enum NexusError {
+    /// The Nexus has never heard of the thing we asked for.
+    #[error("not found on the Nexus: {message} ({uri})")]
+    NotFound { uri: String, message: String },
+    /// The api key is missing, wrong, or revoked.
+    #[error("the Nexus rejected your api key with {status}: {message} ({uri})")]
+    Unauthorized {
+        uri: String,
+        status: u16,
+        message: String,
+    },
+    /// Out of api calls, either by our own count or because the Nexus said so.
+    #[error(
+        "out of Nexus api calls until {}: {message} ({uri})",
+        reset.with_timezone(&Local).format("%H:%M:%S")
+    )]
+    RateLimited {
+        uri: String,
+        message: String,
+        reset: DateTime<Utc>,
+    },
+    /// Any other error status.
+    #[error("the Nexus responded with {status}: {message} ({uri})")]
+    Status {
+        uri: String,
+        status: u16,
+        message: String,
+    },
+    /// The Nexus said something we don't understand.
+    #[error("couldn't make sense of the Nexus response from {uri}: {source}")]
+    Deserialize {
+        uri: String,
+        #[source]
+        source: serde_json::Error,
+    },
+    /// A rate-limit header we couldn't parse.
+    #[error("the Nexus sent an unparseable {name} header: {value:?}")]
+    BadHeader { name: String, value: String },
+    /// We never got a response: dns, tls, timeouts, a dropped connection.
+    #[error("couldn't reach the Nexus at {uri}: {message}")]
+    Transport { uri: String, message: String },
+    /// A replaying client was asked for something its cassette doesn't have.
+    #[error("{0}")]
+    Cassette(String),
+    /// We were told not to talk to the Nexus, and this needs it.
+    #[error("modcache is offline, and {0}")]
+    Offline(String),
+    /// There's no api key to send.
+    #[error("you must provide your personal Nexus API key: run `modcache login`, set the env var NEXUS_API_KEY, or set the api_key of your profile")]
+    MissingApiKey,
+    /// Ctrl-C, while we were waiting on the Nexus or about to ask it something.
+    #[error("stopped by Ctrl-C")]
+    Interrupted,
+}
+
+impl NexusError {
+    /// Sort an error response from the Nexus into the right variant.
+    pub fn from_status(uri: &str, status: u16, body: &str, reset: DateTime<Utc>) -> Self {
+        let uri = uri.to_string();
+        let message = nexus_message(body);
+        match status {
+            401 | 403 => NexusError::Unauthorized {
+                uri,
+                status,
+                message,
+            },
+            404 => NexusError::NotFound { uri, message },
+            429 => NexusError::RateLimited {
+                uri,
+                message,
+                reset,
+            },
+            _ => NexusError::Status {
+                uri,
+                status,
+                message,
+            },
+        }
+    }
+
+    /// The http status the Nexus responded with, if it responded at all.
+    pub fn status(&self) -> Option<u16> {
+        match self {
+            NexusError::NotFound { .. } => Some(404),
+            NexusError::Unauthorized { status, .. } => Some(*status),
+            NexusError::RateLimited { .. } => Some(429),
+            NexusError::Status { status, .. } => Some(*status),
+            _ => None,
+        }
+    }
+
+    pub fn is_not_found(&self) -> bool {
+        matches!(self, NexusError::NotFound { .. })
+    }
+
+    /// True for errors that will happen again no matter what we ask for next: no quota,
+    /// a bad key or none, no network. Loops over many items should stop on these.
+    pub fn is_fatal(&self) -> bool {
+        matches!(
+            self,
+            NexusError::Unauthorized { .. }
+                | NexusError::RateLimited { .. }
+                | NexusError::Transport { .. }
+                | NexusError::Cassette(_)
+                | NexusError::Offline(_)
+                | NexusError::MissingApiKey
+                | NexusError::Interrupted
+        )
+    }
+}
+
+/// Error bodies are usually json with a `message` or `msg` field, but not always.
+fn nexus_message(body: &str) -> String {
+    serde_json::from_str::<serde_json::Value>(body)
+        .ok()
+        .and_then(|v| {
+            v.get("message")
+                .or_else(|| v.get("msg"))
+                .and_then(|m| m.as_str())
+                .map(|m| m.to_string())
+        })
+        .unwrap_or_else(|| body.trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static URI: &str = "https://api.nexusmods.com/v1/games/skyrim/mods/1.json";
+
+    #[test]
+    fn errors_keep_the_status_and_message() {
+        let reset = Utc::now();
+        let forbidden = NexusError::from_status(URI, 403, r#"{"message":"Forbidden"}"#, reset);
+        assert!(matches!(forbidden, NexusError::Unauthorized { .. }));
+        assert_eq!(forbidden.status(), Some(403));
+        assert!(
+            forbidden.to_string().contains("403: Forbidden"),
+            "{}",
+            forbidden
+        );
+        assert!(forbidden.to_string().contains(URI), "{}", forbidden);
+
+        let unauthorized = NexusError::from_status(URI, 401, r#"{"msg":"Bad key"}"#, reset);
+        assert_eq!(unauthorized.status(), Some(401));
+
+        let limited = NexusError::from_status(URI, 429, "Slow down\n", reset);
+        assert_eq!(limited.status(), Some(429));
+        assert!(limited.to_string().contains("Slow down"), "{}", limited);
+        assert!(limited.to_string().contains(URI), "{}", limited);
+
+        let broken = NexusError::from_status(URI, 502, "Bad gateway", reset);
+        assert_eq!(broken.status(), Some(502));
+    }
+}
@@ -0,0 +1,92 @@
+//! The Nexus's api rate limits, as last reported by its response headers. These are kept in
+//! the kv store between runs, so we know whether we can afford a request before making it.
+
+use chrono::{DateTime, Duration, DurationRound, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::storage;
+
+static BUCKET: &str = "rate_limits";
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RateLimits {
+    pub hourly_limit: u16,
+    pub hourly_remaining: u16,
+    pub hourly_reset: DateTime<Utc>,
+    pub daily_limit: u16,
+    pub daily_remaining: u16,
+    pub daily_reset: DateTime<Utc>,
+    /// When the Nexus last told us these numbers. `None` if it never has.
+    #[serde(default)]
+    pub as_of: Option<DateTime<Utc>>,
+}
+
+impl Default for RateLimits {
+    /// The Nexus's published limits for ordinary accounts. Hourly quotas reset on the hour,
+    /// daily quotas at midnight UTC.
+    fn default() -> Self {
+        let now = Utc::now();
+        Self {
+            hourly_limit: 100,
+            hourly_remaining: 100,
+            hourly_reset: next_boundary(now, Duration::hours(1)),
+            daily_limit: 2500,
+            daily_remaining: 2500,
+            daily_reset: next_boundary(now, Duration::days(1)),
+            as_of: None,
+        }
+    }
+}
+
+impl RateLimits {
+    /// Load the limits last seen for the api at this base url, or for one account there,
+    /// falling back to the defaults.
+    pub fn load(key: &str) -> Self {
+        let found = match storage().get(BUCKET, key) {
+            Ok(v) => v.and_then(|json| serde_json::from_str::<RateLimits>(&json).ok()),
+            Err(e) => {
+                log::info!("Can't read the rate limits! {:?}", e);
+                None
+            }
+        };
+        let mut limits = found.unwrap_or_default();
+        limits.roll_over();
+        limits
+    }
+
+    /// Remember these limits for the next run.
+    pub fn save(&self, key: &str) -> anyhow::Result<()> {
+        storage().set(BUCKET, key, &serde_json::to_string(self)?)
+    }
+
+    pub fn exhausted(&self) -> bool {
+        self.hourly_remaining < 1 || self.daily_remaining < 1
+    }
+
+    /// Restore the quota for any bucket whose reset time has passed. This is a guess;
+    /// the headers on the next response will correct it.
+    pub fn roll_over(&mut self) {
+        let now = Utc::now();
+        if now >= self.hourly_reset {
+            self.hourly_remaining = self.hourly_limit;
+            self.hourly_reset = next_boundary(now, Duration::hours(1));
+        }
+        if now >= self.daily_reset {
+            self.daily_remaining = self.daily_limit;
+            self.daily_reset = next_boundary(now, Duration::days(1));
+        }
+    }
+
+    /// When the bucket that's holding us up next resets.
+    pub fn next_reset(&self) -> DateTime<Utc> {
+        if self.daily_remaining < 1 {
+            self.daily_reset
+        } else {
+            self.hourly_reset
+        }
+    }
+}
+
+fn next_boundary(now: DateTime<Utc>, period: Duration) -> DateTime<Utc> {
+    now.duration_trunc(period).unwrap_or(now) + period
+}
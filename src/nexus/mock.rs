@@ -0,0 +1,406 @@
+//! A small stand-in for the Nexus API, for exercising the client without spending real
+//! API quota. It serves the json in `test/fixtures/`, answers conditional requests with
+//! weak etags and 304s, and sends the same `x-rl-*` rate-limit headers the Nexus does.
+//! Point modcache at it by setting `NEXUS_BASE_URL=http://localhost:<port>`.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use chrono::{DateTime, Duration, DurationRound, Utc};
+use owo_colors::OwoColorize;
+use serde_json::{json, Value};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+/// Rate-limit bookkeeping that mimics the Nexus's hourly and daily buckets.
+#[derive(Debug)]
+struct MockLimits {
+    hourly_limit: u16,
+    hourly_remaining: u16,
+    hourly_reset: DateTime<Utc>,
+    daily_limit: u16,
+    daily_remaining: u16,
+    daily_reset: DateTime<Utc>,
+}
+
+impl MockLimits {
+    fn new(hourly_limit: u16, daily_limit: u16) -> Self {
+        let now = Utc::now();
+        Self {
+            hourly_limit,
+            hourly_remaining: hourly_limit,
+            hourly_reset: next_boundary(now, Duration::hours(1)),
+            daily_limit,
+            daily_remaining: daily_limit,
+            daily_reset: next_boundary(now, Duration::days(1)),
+        }
+    }
+
+    /// Roll over any buckets whose reset time has passed.
+    fn tick(&mut self) {
+        let now = Utc::now();
+        if now >= self.hourly_reset {
+            self.hourly_remaining = self.hourly_limit;
+            self.hourly_reset = next_boundary(now, Duration::hours(1));
+        }
+        if now >= self.daily_reset {
+            self.daily_remaining = self.daily_limit;
+            self.daily_reset = next_boundary(now, Duration::days(1));
+        }
+    }
+
+    fn exhausted(&self) -> bool {
+        self.hourly_remaining == 0 || self.daily_remaining == 0
+    }
+
+    /// Spend one request from both buckets.
+    fn spend(&mut self) {
+        self.hourly_remaining = self.hourly_remaining.saturating_sub(1);
+        self.daily_remaining = self.daily_remaining.saturating_sub(1);
+    }
+
+    fn headers(&self) -> Vec<Header> {
+        vec![
+            header("x-rl-hourly-limit", &self.hourly_limit.to_string()),
+            header("x-rl-hourly-remaining", &self.hourly_remaining.to_string()),
+            header("x-rl-hourly-reset", &self.hourly_reset.to_rfc3339()),
+            header("x-rl-daily-limit", &self.daily_limit.to_string()),
+            header("x-rl-daily-remaining", &self.daily_remaining.to_string()),
+            header("x-rl-daily-reset", &self.daily_reset.to_rfc3339()),
+        ]
+    }
+}
+
+fn next_boundary(now: DateTime<Utc>, period: Duration) -> DateTime<Utc> {
+    now.duration_trunc(period).unwrap_or(now) + period
+}
+
+fn header(name: &str, value: &str) -> Header {
+    Header::from_bytes(name.as_bytes(), value.as_bytes()).expect("header names are ascii")
+}
+
+/// The data the mock serves, loaded from the fixtures directory and mutated by the
+/// track/untrack/endorse endpoints.
+#[derive(Debug)]
+pub struct MockNexus {
+    user: Value,
+    game: Value,
+    files: Value,
+    trending: Value,
+    latest_added: Value,
+    latest_updated: Value,
+    mods: HashMap<(String, u32), Value>,
+    tracked: Vec<(String, u32)>,
+    endorsements: Vec<Value>,
+    limits: MockLimits,
+    flaky: f64,
+}
+
+impl MockNexus {
+    /// Load fixtures from the given directory. Every mod found in any fixture can be
+    /// fetched by id, and the initial tracked list is all of those mods.
+    pub fn from_fixtures(dir: &Path, hourly_limit: u16, daily_limit: u16) -> anyhow::Result<Self> {
+        let load = |name: &str| -> anyhow::Result<Value> {
+            let path: PathBuf = dir.join(name);
+            let text = fs::read_to_string(&path)
+                .with_context(|| format!("reading fixture {}", path.display()))?;
+            let value = serde_json::from_str(&text)
+                .with_context(|| format!("parsing fixture {}", path.display()))?;
+            Ok(value)
+        };
+
+        let trending = load("trending.json")?;
+        let latest_added = load("newest.json")?;
+        let latest_updated = load("updated.json")?;
+
+        let mut mods: HashMap<(String, u32), Value> = HashMap::new();
+        let singles = [load("mod.json")?, load("mod2.json")?];
+        let lists = [&trending, &latest_added, &latest_updated];
+        let all = lists
+            .iter()
+            .filter_map(|xs| xs.as_array())
+            .flatten()
+            .chain(singles.iter());
+        for item in all {
+            if let (Some(game), Some(id)) = (item["domain_name"].as_str(), item["mod_id"].as_u64())
+            {
+                mods.insert((game.to_string(), id as u32), item.clone());
+            }
+        }
+        let mut tracked: Vec<(String, u32)> = mods.keys().cloned().collect();
+        tracked.sort();
+
+        let endorsements = load("endorsements.json")?
+            .as_array()
+            .cloned()
+            .unwrap_or_default();
+
+        Ok(Self {
+            user: load("validate.json")?,
+            game: load("game.json")?,
+            files: load("files.json")?,
+            trending,
+            latest_added,
+            latest_updated,
+            mods,
+            tracked,
+            endorsements,
+            limits: MockLimits::new(hourly_limit, daily_limit),
+            flaky: 0.0,
+        })
+    }
+
+    /// Answer a single request, returning the status code and json body.
+    fn route(&mut self, method: &Method, path: &str, query: &str, form: &str) -> (u16, Value) {
+        let segments: Vec<&str> = path
+            .trim_start_matches('/')
+            .trim_end_matches(".json")
+            .split('/')
+            .collect();
+
+        match (method, segments.as_slice()) {
+            (Method::Get, ["v1", "users", "validate"]) => (200, self.user.clone()),
+            (Method::Get, ["v1", "user", "tracked_mods"]) => {
+                let list: Vec<Value> = self
+                    .tracked
+                    .iter()
+                    .map(|(game, id)| json!({ "domain_name": game, "mod_id": id }))
+                    .collect();
+                (200, Value::Array(list))
+            }
+            (Method::Post, ["v1", "user", "tracked_mods"]) => {
+                let Some((game, mod_id)) = tracking_params(query, form) else {
+                    return not_found("Missing domain_name or mod_id");
+                };
+                let message = if self.tracked.contains(&(game.clone(), mod_id)) {
+                    format!("User is already Tracking Mod: {}", mod_id)
+                } else {
+                    self.tracked.push((game, mod_id));
+                    format!(
+                        "User {} is now Tracking Mod: {}",
+                        self.user["user_id"], mod_id
+                    )
+                };
+                (201, json!({ "message": message }))
+            }
+            (Method::Delete, ["v1", "user", "tracked_mods"]) => {
+                let Some((game, mod_id)) = tracking_params(query, form) else {
+                    return not_found("Missing domain_name or mod_id");
+                };
+                let before = self.tracked.len();
+                self.tracked.retain(|xs| *xs != (game.clone(), mod_id));
+                if self.tracked.len() == before {
+                    return not_found(&format!("User is not tracking mod {}", mod_id));
+                }
+                let message = format!(
+                    "User {} is no longer tracking {}",
+                    self.user["user_id"], mod_id
+                );
+                (200, json!({ "message": message }))
+            }
+            (Method::Get, ["v1", "user", "endorsements"]) => {
+                (200, Value::Array(self.endorsements.clone()))
+            }
+            (Method::Get, ["v1", "games", game]) => {
+                if self.game["domain_name"] == *game {
+                    (200, self.game.clone())
+                } else {
+                    not_found("Game not found")
+                }
+            }
+            (Method::Get, ["v1", "games", _game, "mods", "trending"]) => {
+                (200, self.trending.clone())
+            }
+            (Method::Get, ["v1", "games", _game, "mods", "latest_added"]) => {
+                (200, self.latest_added.clone())
+            }
+            (Method::Get, ["v1", "games", _game, "mods", "latest_updated"]) => {
+                (200, self.latest_updated.clone())
+            }
+            (Method::Get, ["v1", "games", game, "mods", "updated"]) => {
+                // Every fixture is years old, so the period is ignored and every mod we
+                // know for the game counts as recently changed. Endorsements and comments
+                // count as activity without touching the mod, so activity comes a day later.
+                let mut list: Vec<(u32, u64)> = self
+                    .mods
+                    .iter()
+                    .filter(|((domain, _id), _modinfo)| domain == game)
+                    .map(|((_domain, id), modinfo)| {
+                        (*id, modinfo["updated_timestamp"].as_u64().unwrap_or(0))
+                    })
+                    .collect();
+                list.sort();
+                let list: Vec<Value> = list
+                    .into_iter()
+                    .map(|(id, when)| {
+                        json!({
+                            "mod_id": id,
+                            "latest_file_update": when,
+                            "latest_mod_activity": when + 86_400,
+                        })
+                    })
+                    .collect();
+                (200, Value::Array(list))
+            }
+            (Method::Get, ["v1", "games", game, "mods", id]) => match self.find_mod(game, id) {
+                Some(modinfo) => (200, modinfo.clone()),
+                None => not_found("Mod not found"),
+            },
+            (Method::Get, ["v1", "games", game, "mods", id, "files"]) => {
+                match self.find_mod(game, id) {
+                    Some(_) => (200, self.files.clone()),
+                    None => not_found("Mod not found"),
+                }
+            }
+            (Method::Get, ["v1", "games", game, "mods", id, "changelogs"]) => {
+                match self.find_mod(game, id) {
+                    Some(modinfo) => {
+                        let version = modinfo["version"].as_str().unwrap_or("1.0").to_string();
+                        let mut versions = serde_json::Map::new();
+                        versions.insert(version, json!(["Mock changelog entry."]));
+                        (200, Value::Object(versions))
+                    }
+                    None => not_found("Mod not found"),
+                }
+            }
+            (Method::Post, ["v1", "games", game, "mods", id, verb @ ("endorse" | "abstain")]) => {
+                let Some(mod_id) = id.parse::<u32>().ok() else {
+                    return not_found("Mod not found");
+                };
+                let status = if *verb == "endorse" {
+                    "Endorsed"
+                } else {
+                    "Abstained"
+                };
+                self.endorsements
+                    .retain(|xs| !(xs["domain_name"] == *game && xs["mod_id"] == mod_id));
+                self.endorsements.push(json!({
+                    "date": Utc::now().timestamp(),
+                    "domain_name": game,
+                    "mod_id": mod_id,
+                    "status": status,
+                    "version": "*",
+                }));
+                (200, json!({ "message": "SUCCESS", "status": status }))
+            }
+            _ => not_found("No route matches"),
+        }
+    }
+
+    fn find_mod(&self, game: &str, id: &str) -> Option<&Value> {
+        let mod_id = id.parse::<u32>().ok()?;
+        self.mods.get(&(game.to_string(), mod_id))
+    }
+
+    /// Handle one incoming http request, including etags and rate limiting.
+    fn respond(&mut self, mut request: Request) -> anyhow::Result<()> {
+        let method = request.method().clone();
+        let url = request.url().to_string();
+        let (path, query) = url.split_once('?').unwrap_or((&url, ""));
+
+        let authed = request
+            .headers()
+            .iter()
+            .any(|h| h.field.equiv("apikey") && !h.value.as_str().is_empty());
+        let if_none_match = request
+            .headers()
+            .iter()
+            .find(|h| h.field.equiv("if-none-match"))
+            .map(|h| h.value.to_string());
+        let mut form = String::new();
+        request.as_reader().read_to_string(&mut form)?;
+
+        self.limits.tick();
+        let (status, body) = if !authed {
+            (401, json!({ "message": "Please provide a valid API Key" }))
+        } else if fastrand::f64() < self.flaky {
+            // Flaky failures are free, like a real outage.
+            (503, json!({ "message": "Service Unavailable" }))
+        } else if self.limits.exhausted() {
+            (429, json!({ "msg": "Rate limit exceeded" }))
+        } else {
+            self.limits.spend();
+            self.route(&method, path, query, &form)
+        };
+
+        let payload = body.to_string();
+        let etag = weak_etag(&payload);
+        let not_modified = status == 200 && if_none_match.as_deref() == Some(etag.as_str());
+        let status = if not_modified { 304 } else { status };
+        log::info!("{} {} {}", method, url, status.blue());
+
+        let mut response = if not_modified {
+            Response::from_data(Vec::new())
+        } else {
+            Response::from_data(payload.into_bytes())
+                .with_header(header("content-type", "application/json; charset=utf-8"))
+        }
+        .with_status_code(status)
+        .with_header(header("etag", &etag));
+        for h in self.limits.headers() {
+            response.add_header(h);
+        }
+        request.respond(response)?;
+        Ok(())
+    }
+}
+
+fn not_found(message: &str) -> (u16, Value) {
+    (404, json!({ "code": 404, "message": message }))
+}
+
+fn weak_etag(payload: &str) -> String {
+    let mut hasher = DefaultHasher::new();
+    payload.hash(&mut hasher);
+    format!("W/\"{:016x}\"", hasher.finish())
+}
+
+/// Pull a value out of an urlencoded string. Our values are all plain words and numbers.
+fn param<'a>(encoded: &'a str, name: &str) -> Option<&'a str> {
+    encoded
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(k, _v)| *k == name)
+        .map(|(_k, v)| v)
+}
+
+fn tracking_params(query: &str, form: &str) -> Option<(String, u32)> {
+    let game = param(query, "domain_name")?;
+    let mod_id = param(form, "mod_id").or_else(|| param(query, "mod_id"))?;
+    Some((game.to_string(), mod_id.parse().ok()?))
+}
+
+/// Run the mock Nexus until killed.
+pub fn serve(
+    port: u16,
+    fixtures: &str,
+    hourly_limit: u16,
+    daily_limit: u16,
+    flaky: f64,
+) -> anyhow::Result<()> {
+    let mut mock = MockNexus::from_fixtures(Path::new(fixtures), hourly_limit, daily_limit)?;
+    mock.flaky = flaky;
+    let address = format!("127.0.0.1:{}", port);
+    let server = Server::http(&address).map_err(|e| anyhow::anyhow!(e))?;
+    // Port 0 asks the OS for any free port, so report the one we got.
+    let address = server
+        .server_addr()
+        .to_ip()
+        .map_or(address, |bound| bound.to_string());
+    println!(
+        "Mock Nexus serving {} mods from {} at {}",
+        mock.mods.len().blue(),
+        fixtures.yellow(),
+        format!("http://{}", address).bold()
+    );
+
+    for request in server.incoming_requests() {
+        if let Err(e) = mock.respond(request) {
+            log::warn!("mock nexus failed to respond: {:?}", e);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,919 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use once_cell::sync::OnceCell;
+use owo_colors::OwoColorize;
+use serde::{Deserialize, Serialize};
+
+use crate::data::*;
+use crate::interrupt;
+
+pub mod cassette;
+pub mod error;
+pub mod limits;
+pub mod mock;
+pub mod pool;
+pub mod retry;
+pub mod throttle;
+
+use cassette::Cassette;
+pub use error::NexusError;
+use limits::RateLimits;
+use retry::RetryPolicy;
+use throttle::Throttle;
+
+/// The default location of the Nexus API.
+pub static NEXUS_BASE: &str = "https://api.nexusmods.com";
+/// The most requests a second the Nexus tolerates before answering with 429s.
+static BURST_LIMIT: usize = 30;
+
+/// Everything modcache asks of the Nexus. `NexusClient` implements this against the real
+/// API; commands and the caching layer are generic over it, so a fake or a proxy can stand in.
+/// Fetches that take an etag respond with `None` when the Nexus says our copy is current.
+pub trait NexusApi {
+    /// True if we must not talk to the Nexus at all, and should make do with the cache.
+    fn is_offline(&self) -> bool;
+    /// Api calls remaining this hour, as of the most recent response.
+    fn remaining_hour(&self) -> u16;
+    /// Api calls remaining today, as of the most recent response.
+    fn remaining_day(&self) -> u16;
+    /// Everything we know about the api limits and when they reset. Costs no request.
+    fn rate_limits(&self) -> RateLimits;
+    /// Validate your Nexus API token.
+    fn validate(&mut self) -> Result<AuthenticatedUser, NexusError>;
+    /// Fetch info about a specific game by its Nexus `domain name`.
+    fn gameinfo(
+        &mut self,
+        game: &str,
+        etag: Option<String>,
+    ) -> Result<Option<GameMetadata>, NexusError>;
+    /// Fetch full information about a mod by game domain name & mod id number.
+    /// These are the same two pieces of information in the url for a mod on the Nexus's site.
+    fn mod_by_id(
+        &mut self,
+        game: &str,
+        modid: u32,
+        etag: Option<String>,
+    ) -> Result<Option<ModInfoFull>, NexusError>;
+    /// Fetch the changelogs for a mod, keyed by version.
+    fn changelogs(
+        &mut self,
+        game: &str,
+        modid: u32,
+        etag: Option<String>,
+    ) -> Result<Option<Changelogs>, NexusError>;
+    /// Fetch the list of files for a mod.
+    fn files(
+        &mut self,
+        game: &str,
+        modid: u32,
+        etag: Option<String>,
+    ) -> Result<Option<Files>, NexusError>;
+    /// Get detailed info about a file.
+    fn mod_file_info(
+        &mut self,
+        game: &str,
+        mod_id: u32,
+        file: &str,
+    ) -> Result<FileInfo, NexusError>;
+    /// Fetch the list of mods tracked for all games.
+    fn tracked(&mut self, etag: Option<String>) -> Result<Option<Tracked>, NexusError>;
+    /// Begin tracking a specific mod, identified by game domain name and id.
+    fn track(&mut self, game: &str, mod_id: u32) -> Result<TrackingResponse, NexusError>;
+    /// Stop tracking a specific mod, identified by game domain name and id.
+    fn untrack(&mut self, game: &str, mod_id: u32) -> Result<TrackingResponse, NexusError>;
+    /// Get the list of all endorsement decisions made by the authed user.
+    fn endorsements(&mut self, etag: Option<String>)
+        -> Result<Option<EndorsementList>, NexusError>;
+    /// Endorse a mod.
+    fn endorse(&mut self, game: &str, mod_id: u32) -> Result<EndorseResponse, NexusError>;
+    /// Abstain from endorsing a mod.
+    fn abstain(&mut self, game: &str, mod_id: u32) -> Result<EndorseResponse, NexusError>;
+    /// Get a list of trending mods for a specific game. This list is capped at 10.
+    fn trending(&mut self, game: &str) -> Result<ModInfoList, NexusError>;
+    /// Get a list of the ten most-recently-added mods for a game. This list can include
+    /// mods that have not yet been published, or contain incomplete information.
+    fn latest_added(&mut self, game: &str) -> Result<ModInfoList, NexusError>;
+    /// Get a list of the ten most-recently updated mods for a game.
+    fn latest_updated(&mut self, game: &str) -> Result<ModInfoList, NexusError>;
+    /// Get the ids of every mod for a game that changed in the given period.
+    fn updated_mods(
+        &mut self,
+        game: &str,
+        period: UpdatePeriod,
+    ) -> Result<Vec<ModUpdate>, NexusError>;
+}
+
+/// The parts of a Nexus response we care about, detached from the http client so they
+/// can be recorded to and replayed from a cassette.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NexusResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl NexusResponse {
+    fn from_ureq(response: ureq::Response) -> std::io::Result<Self> {
+        let status = response.status();
+        let headers = response
+            .headers_names()
+            .into_iter()
+            .filter_map(|name| {
+                let value = response.header(&name)?.to_string();
+                Some((name, value))
+            })
+            .collect();
+        let body = response.into_string()?;
+        Ok(Self {
+            status,
+            headers,
+            body,
+        })
+    }
+
+    /// Look up a header by case-insensitive name.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _v)| k.eq_ignore_ascii_case(name))
+            .map(|(_k, v)| v.as_str())
+    }
+
+    fn json<T: for<'de> Deserialize<'de>>(&self, uri: &str) -> Result<T, NexusError> {
+        serde_json::from_str(&self.body).map_err(|source| NexusError::Deserialize {
+            uri: uri.to_string(),
+            source,
+        })
+    }
+}
+
+/// What we know of the quota, plus how many requests are underway whose cost the Nexus
+/// hasn't told us about yet.
+#[derive(Debug)]
+struct Quota {
+    limits: RateLimits,
+    in_flight: u16,
+}
+
+impl Quota {
+    /// The limits less whatever requests underway will spend.
+    fn available(&mut self) -> RateLimits {
+        self.limits.roll_over();
+        let mut limits = self.limits.clone();
+        limits.hourly_remaining = limits.hourly_remaining.saturating_sub(self.in_flight);
+        limits.daily_remaining = limits.daily_remaining.saturating_sub(self.in_flight);
+        limits
+    }
+
+    /// Set aside one request's worth of quota, or respond with the limits that say we can't
+    /// afford it.
+    fn claim(&mut self) -> Result<(), RateLimits> {
+        let limits = self.available();
+        if limits.exhausted() {
+            return Err(limits);
+        }
+        self.in_flight += 1;
+        Ok(())
+    }
+
+    /// Give back a claim once its request is done, noting what the response said about the
+    /// quota. Responds with whether it said anything.
+    fn release(&mut self, response: Option<&NexusResponse>) -> Result<bool, NexusError> {
+        self.in_flight = self.in_flight.saturating_sub(1);
+        match response {
+            Some(response) => note_limits(&mut self.limits, response),
+            None => Ok(false),
+        }
+    }
+}
+
+// Now a wrapper for the client so nobody else has to think about rate limiting.
+// Clones are cheap and share the quota, the throttle, and the cassette, so a clone can be
+// handed to each worker thread in a bulk fetch.
+#[derive(Debug, Clone)]
+pub struct NexusClient {
+    pub agent: ureq::Agent,
+    /// Found the first time a request needs it, and shared by clones.
+    apikey: Arc<OnceCell<String>>,
+    /// Where to look for a key if we weren't given one.
+    key_source: Option<fn() -> Option<String>>,
+    base_url: String,
+    /// Where this account's rate limits are remembered.
+    limits_key: String,
+    quota: Arc<Mutex<Quota>>,
+    throttle: Arc<Throttle>,
+    retry: RetryPolicy,
+    cassette: Option<Arc<Mutex<Cassette>>>,
+    replaying: bool,
+    offline: bool,
+}
+
+impl NexusClient {
+    pub fn new(apikey: String) -> Self {
+        Self::with_base_url(apikey, NEXUS_BASE.to_string())
+    }
+
+    /// Create a client that talks to a Nexus API at some other location, such as
+    /// the local mock server. The url should not have a trailing slash.
+    pub fn with_base_url(apikey: String, base_url: String) -> Self {
+        Self::for_profile(apikey, base_url, "")
+    }
+
+    /// Create a client for one profile's account. Every account has its own quota, so
+    /// each profile's rate limits are remembered separately.
+    pub fn for_profile(apikey: String, base_url: String, profile: &str) -> Self {
+        let agent = ureq::AgentBuilder::new()
+            .timeout_read(Duration::from_secs(50))
+            .timeout_write(Duration::from_secs(5))
+            .build();
+        let base_url = base_url.trim_end_matches('/').to_string();
+        let limits_key = profile_key(&base_url, profile);
+        let quota = Quota {
+            limits: RateLimits::load(&limits_key),
+            in_flight: 0,
+        };
+
+        let apikey = if apikey.is_empty() {
+            OnceCell::new()
+        } else {
+            OnceCell::with_value(apikey)
+        };
+
+        NexusClient {
+            agent,
+            apikey: Arc::new(apikey),
+            key_source: None,
+            base_url,
+            limits_key,
+            quota: Arc::new(Mutex::new(quota)),
+            throttle: Arc::new(Throttle::new(BURST_LIMIT)),
+            retry: RetryPolicy::default(),
+            cassette: None,
+            replaying: false,
+            offline: false,
+        }
+    }
+
+    /// Choose how to retry failed requests and whether to wait out exhausted quotas.
+    pub fn set_retry_policy(&mut self, retry: RetryPolicy) {
+        self.retry = retry;
+    }
+
+    /// Record every exchange with the Nexus to a cassette, or serve them all from one.
+    pub fn set_cassette(&mut self, cassette: Cassette) {
+        self.replaying = matches!(cassette, Cassette::Replay(_));
+        self.cassette = Some(Arc::new(Mutex::new(cassette)));
+    }
+
+    /// Use this api key from now on.
+    pub fn set_api_key(&mut self, apikey: String) {
+        self.apikey = Arc::new(OnceCell::with_value(apikey));
+    }
+
+    /// Where to find an api key if the client was made without one. It's asked only when a
+    /// request needs a key, since finding a saved key might mean asking for a passphrase.
+    pub fn set_key_source(&mut self, source: fn() -> Option<String>) {
+        self.key_source = Some(source);
+    }
+
+    /// The api key to send, looked up the first time it's needed.
+    fn apikey(&self) -> Result<&str, NexusError> {
+        self.apikey
+            .get_or_try_init(|| {
+                self.key_source
+                    .and_then(|source| source())
+                    .filter(|apikey| !apikey.is_empty())
+                    .ok_or(NexusError::MissingApiKey)
+            })
+            .map(String::as_str)
+    }
+
+    /// Refuse to send any request at all, so nothing leaves this machine.
+    pub fn set_offline(&mut self, offline: bool) {
+        self.offline = offline;
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    /// Set aside one request's worth of quota for a request we're about to send, or respond
+    /// with the limits that say we can't afford it.
+    fn claim(&self) -> Result<(), RateLimits> {
+        self.quota.lock().unwrap().claim()
+    }
+
+    /// Give back a claim once its request is done, noting what the response said about the
+    /// quota. A response with no rate-limit headers leaves the quota as it was.
+    // This would be a perfect use case for middleware.
+    fn release(&self, response: Option<&NexusResponse>) -> Result<(), NexusError> {
+        let mut quota = self.quota.lock().unwrap();
+        // We bail on parse failures because well, if this happens we've misunderstood the
+        // contract the Nexus is upholding with us.
+        let noted = quota.release(response)?;
+        // A cassette's limits are history; don't let them clobber the real ones.
+        if noted && !self.replaying {
+            quota.limits.as_of = Some(Utc::now());
+            if let Err(e) = quota.limits.save(&self.limits_key) {
+                log::warn!("failed to store rate limits: {:?}", e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Sleep until the rate-limit bucket that ran dry resets.
+    fn wait_for_reset(&self, limits: &RateLimits) {
+        let until = limits.next_reset();
+        let wait = (until - Utc::now())
+            .to_std()
+            .unwrap_or_default()
+            .max(Duration::from_secs(1));
+        log::warn!(
+            "Out of api calls; sleeping until {} ({})",
+            until
+                .with_timezone(&chrono::Local)
+                .format("%H:%M:%S")
+                .bold(),
+            chrono_humanize::HumanTime::from(until)
+        );
+        interrupt::sleep(wait);
+    }
+
+    /// Send a request, retrying transport failures, server errors, and burst rate-limiting
+    /// with backoff. If the retry policy says so, sleeps through exhausted quotas.
+    fn send(
+        &mut self,
+        method: &str,
+        uri: &str,
+        etag: Option<String>,
+        form: &[(&str, &str)],
+    ) -> Result<NexusResponse, NexusError> {
+        if self.offline {
+            let path = uri.strip_prefix(&self.base_url).unwrap_or(uri);
+            return Err(NexusError::Offline(format!(
+                "{} {} needs the Nexus",
+                method, path
+            )));
+        }
+
+        // A cassette already knows how this went; no waiting around.
+        // Nothing is claimed, so there's nothing to give back, but the limits are noted.
+        if self.replaying {
+            let response = self.send_once(method, uri, etag, form)?;
+            self.release(Some(&response))?;
+            return Ok(response);
+        }
+        self.apikey()?;
+
+        let mut attempt: u32 = 0;
+        loop {
+            if interrupt::requested() {
+                return Err(NexusError::Interrupted);
+            }
+            if let Err(limits) = self.claim() {
+                if self.retry.wait_for_reset {
+                    self.wait_for_reset(&limits);
+                    continue;
+                }
+                let message = if limits.hourly_remaining < 1 {
+                    format!("past the hourly api call limit of {}", limits.hourly_limit)
+                } else {
+                    format!("past the daily api call limit of {}", limits.daily_limit)
+                };
+                log::error!("Went {}! Wait until {}", message, limits.next_reset());
+                return Err(NexusError::RateLimited {
+                    uri: uri.to_string(),
+                    message,
+                    reset: limits.next_reset(),
+                });
+            }
+
+            self.throttle.wait();
+            let result = self.send_once(method, uri, etag.clone(), form);
+            let noted = self.release(result.as_ref().ok());
+            let reason = match &result {
+                Ok(response) if response.status == 429 => {
+                    if let Err(e) = noted {
+                        log::warn!("problem parsing headers: {:?}", e);
+                    }
+                    let limits = self.quota.lock().unwrap().available();
+                    if limits.exhausted() {
+                        if self.retry.wait_for_reset {
+                            self.wait_for_reset(&limits);
+                            continue;
+                        }
+                        return result;
+                    }
+                    // Quota remains, so we tripped the per-second burst limit.
+                    "rate-limited".to_string()
+                }
+                Ok(response) if RetryPolicy::is_transient(response.status) => {
+                    format!("status {}", response.status)
+                }
+                Ok(_) => return noted.and(result),
+                Err(NexusError::Transport { message, .. }) => message.clone(),
+                Err(e) => format!("{}", e),
+            };
+
+            if attempt >= self.retry.max_retries {
+                return result;
+            }
+            let delay = self.retry.backoff(attempt);
+            attempt += 1;
+            log::warn!(
+                "{} {} failed ({}); retry {} of {} in {:.1}s",
+                method,
+                uri,
+                reason,
+                attempt,
+                self.retry.max_retries,
+                delay.as_secs_f64()
+            );
+            interrupt::sleep(delay);
+        }
+    }
+
+    /// Send a request to the nexus, or play it back from a cassette. Responds with whatever
+    /// the Nexus said, error statuses included; only transport failures are errors here.
+    fn send_once(
+        &mut self,
+        method: &str,
+        uri: &str,
+        etag: Option<String>,
+        form: &[(&str, &str)],
+    ) -> Result<NexusResponse, NexusError> {
+        let path = uri.strip_prefix(&self.base_url).unwrap_or(uri);
+        let mut headers = vec![("user-agent", "modcache: github.com/ceejbot/modcache")];
+        if let Some(t) = etag.as_deref() {
+            headers.push(("if-none-match", t));
+        }
+        if let Some(cassette) = &self.cassette {
+            if let Cassette::Replay(player) = &mut *cassette.lock().unwrap() {
+                return player
+                    .play(method, path, &headers, form)
+                    .map_err(|e| NexusError::Cassette(format!("{:#}", e)));
+            }
+        }
+
+        let mut request = self
+            .agent
+            .request(method, uri)
+            .set("apikey", self.apikey.get().map_or("", String::as_str));
+        for (name, value) in headers.iter() {
+            request = request.set(name, value);
+        }
+        let result = if form.is_empty() {
+            request.call()
+        } else {
+            request.send_form(form)
+        };
+
+        let transport = |message: String| NexusError::Transport {
+            uri: uri.to_string(),
+            message,
+        };
+        let response = match result {
+            Ok(v) | Err(ureq::Error::Status(_, v)) => {
+                NexusResponse::from_ureq(v).map_err(|e| transport(e.to_string()))?
+            }
+            Err(ureq::Error::Transport(e)) => {
+                log::debug!("Transport layer error: {:?}", e);
+                // ureq's display repeats the url, which we already have.
+                let mut message = e.kind().to_string();
+                if let Some(m) = e.message() {
+                    message = format!("{}: {}", message, m);
+                }
+                if let Some(source) = std::error::Error::source(&e) {
+                    message = format!("{}: {}", message, source);
+                }
+                return Err(transport(message));
+            }
+        };
+
+        if let Some(cassette) = &self.cassette {
+            if let Cassette::Record(recorder) = &mut *cassette.lock().unwrap() {
+                if let Err(e) = recorder.record(method, path, &headers, form, &response) {
+                    log::warn!("failed to record nexus response to cassette: {:?}", e);
+                }
+            }
+        }
+        Ok(response)
+    }
+
+    /// Send a request and turn error statuses into errors, responding with the successful
+    /// response and its etag.
+    fn checked(
+        &mut self,
+        method: &str,
+        uri: &str,
+        etag: Option<String>,
+        form: &[(&str, &str)],
+    ) -> Result<(NexusResponse, String), NexusError> {
+        let response = self.send(method, uri, etag, form)?;
+        log::debug!("{} {} got status={}", method, uri, response.status);
+        if response.status >= 400 {
+            let reset = self.quota.lock().unwrap().limits.next_reset();
+            return Err(NexusError::from_status(
+                uri,
+                response.status,
+                &response.body,
+                reset,
+            ));
+        }
+        let etag = response.header("etag").unwrap_or_default().to_string();
+        Ok((response, etag))
+    }
+
+    /// Make a get request to the nexus with an etag, responding with `None` if the
+    /// Nexus says the data hasn't changed.
+    fn conditional_get<T: for<'de> Deserialize<'de>>(
+        &mut self,
+        uri: &str,
+        etag: Option<String>,
+    ) -> Result<(Option<T>, String), NexusError> {
+        let (response, etag) = self.checked("GET", uri, etag, &[])?;
+        if response.status == 304 {
+            return Ok((None, etag));
+        }
+        Ok((Some(response.json::<T>(uri)?), etag))
+    }
+
+    /// Unconditional nexus get; does not consider etags.
+    fn get<T: for<'de> Deserialize<'de>>(&mut self, uri: &str) -> Result<T, NexusError> {
+        let (response, _etag) = self.checked("GET", uri, None, &[])?;
+        response.json::<T>(uri)
+    }
+
+    /// Post data to the nexus, deserializing the response into the requested type.
+    pub fn post<T: for<'de> Deserialize<'de>>(
+        &mut self,
+        uri: &str,
+        body: &[(&str, &str)],
+    ) -> Result<T, NexusError> {
+        let (response, _etag) = self.checked("POST", uri, None, body)?;
+        response.json::<T>(uri)
+    }
+
+    /// Send a delete to the nexus, deserializing the response into the requested type.
+    pub fn delete<T: for<'de> Deserialize<'de>>(
+        &mut self,
+        uri: &str,
+        body: &[(&str, &str)],
+    ) -> Result<T, NexusError> {
+        let (response, _etag) = self.checked("DELETE", uri, None, body)?;
+        response.json::<T>(uri)
+    }
+}
+
+impl NexusApi for NexusClient {
+    fn is_offline(&self) -> bool {
+        self.offline
+    }
+
+    fn remaining_hour(&self) -> u16 {
+        self.rate_limits().hourly_remaining
+    }
+
+    fn remaining_day(&self) -> u16 {
+        self.rate_limits().daily_remaining
+    }
+
+    fn rate_limits(&self) -> RateLimits {
+        self.quota.lock().unwrap().available()
+    }
+
+    fn validate(&mut self) -> Result<AuthenticatedUser, NexusError> {
+        let uri = format!("{}/v1/users/validate.json", self.base_url);
+        self.get::<AuthenticatedUser>(&uri)
+    }
+
+    fn gameinfo(
+        &mut self,
+        game: &str,
+        etag: Option<String>,
+    ) -> Result<Option<GameMetadata>, NexusError> {
+        let uri = format!("{}/v1/games/{}.json", self.base_url, game);
+        let (found, etag) = self.conditional_get::<GameMetadata>(&uri, etag)?;
+        Ok(found.map(|mut metadata| {
+            metadata.set_etag(&etag);
+            metadata
+        }))
+    }
+
+    fn mod_by_id(
+        &mut self,
+        game: &str,
+        modid: u32,
+        etag: Option<String>,
+    ) -> Result<Option<ModInfoFull>, NexusError> {
+        let uri = format!("{}/v1/games/{}/mods/{}.json", self.base_url, game, modid);
+        let (found, etag) = self.conditional_get::<ModInfoFull>(&uri, etag)?;
+        Ok(found.map(|mut modinfo| {
+            modinfo.set_etag(&etag);
+            modinfo
+        }))
+    }
+
+    fn changelogs(
+        &mut self,
+        game: &str,
+        modid: u32,
+        etag: Option<String>,
+    ) -> Result<Option<Changelogs>, NexusError> {
+        let uri = format!(
+            "{}/v1/games/{}/mods/{}/changelogs.json",
+            self.base_url, game, modid
+        );
+        let (found, etag) = self.conditional_get::<Changelogs>(&uri, etag)?;
+        Ok(found.map(|mut changelogs| {
+            changelogs.set_etag(&etag);
+            changelogs
+        }))
+    }
+
+    fn files(
+        &mut self,
+        game: &str,
+        modid: u32,
+        etag: Option<String>,
+    ) -> Result<Option<Files>, NexusError> {
+        let uri = format!(
+            "{}/v1/games/{}/mods/{}/files.json",
+            self.base_url, game, modid
+        );
+        let (found, etag) = self.conditional_get::<Files>(&uri, etag)?;
+        Ok(found.map(|mut files| {
+            files.set_etag(&etag);
+            files
+        }))
+    }
+
+    fn mod_file_info(
+        &mut self,
+        game: &str,
+        mod_id: u32,
+        file: &str,
+    ) -> Result<FileInfo, NexusError> {
+        let uri = format!(
+            "{}/v1/games/{}/{}/files/{}.json",
+            self.base_url, game, mod_id, file
+        );
+        self.get::<FileInfo>(&uri)
+    }
+
+    fn tracked(&mut self, etag: Option<String>) -> Result<Option<Tracked>, NexusError> {
+        let uri = format!("{}/v1/user/tracked_mods.json", self.base_url);
+        let (found, etag) = self.conditional_get::<Vec<ModReference>>(&uri, etag)?;
+        Ok(found.map(|mods| Tracked {
+            mods,
+            etag,
+            profile: String::new(),
+        }))
+    }
+
+    fn track(&mut self, game: &str, mod_id: u32) -> Result<TrackingResponse, NexusError> {
+        let uri = format!(
+            "{}/v1/user/tracked_mods.json?domain_name={}",
+            self.base_url, game
+        );
+        self.post(&uri, &[("mod_id", &format!("{}", mod_id))])
+    }
+
+    fn untrack(&mut self, game: &str, mod_id: u32) -> Result<TrackingResponse, NexusError> {
+        let uri = format!(
+            "{}/v1/user/tracked_mods.json?domain_name={}",
+            self.base_url, game
+        );
+        self.delete(&uri, &[("mod_id", &format!("{}", mod_id))])
+    }
+
+    fn endorsements(
+        &mut self,
+        etag: Option<String>,
+    ) -> Result<Option<EndorsementList>, NexusError> {
+        let uri = format!("{}/v1/user/endorsements.json", self.base_url);
+        let (found, etag) = self.conditional_get::<Vec<UserEndorsement>>(&uri, etag)?;
+        Ok(found.map(|mods| EndorsementList {
+            mods,
+            etag,
+            profile: String::new(),
+        }))
+    }
+
+    fn endorse(&mut self, game: &str, mod_id: u32) -> Result<EndorseResponse, NexusError> {
+        let uri = format!(
+            "{}/v1/games/{}/mods/{}/endorse.json",
+            self.base_url, game, mod_id
+        );
+        self.post::<EndorseResponse>(&uri, &[("version", "*")])
+    }
+
+    fn abstain(&mut self, game: &str, mod_id: u32) -> Result<EndorseResponse, NexusError> {
+        let uri = format!(
+            "{}/v1/games/{}/mods/{}/abstain.json",
+            self.base_url, game, mod_id
+        );
+        self.post::<EndorseResponse>(&uri, &[("version", "*")])
+    }
+
+    fn trending(&mut self, game: &str) -> Result<ModInfoList, NexusError> {
+        let uri = format!("{}/v1/games/{}/mods/trending.json", self.base_url, game);
+        self.get::<ModInfoList>(&uri)
+    }
+
+    fn latest_added(&mut self, game: &str) -> Result<ModInfoList, NexusError> {
+        let uri = format!("{}/v1/games/{}/mods/latest_added.json", self.base_url, game);
+        self.get::<ModInfoList>(&uri)
+    }
+
+    fn latest_updated(&mut self, game: &str) -> Result<ModInfoList, NexusError> {
+        let uri = format!(
+            "{}/v1/games/{}/mods/latest_updated.json",
+            self.base_url, game
+        );
+        self.get::<ModInfoList>(&uri)
+    }
+
+    fn updated_mods(
+        &mut self,
+        game: &str,
+        period: UpdatePeriod,
+    ) -> Result<Vec<ModUpdate>, NexusError> {
+        let uri = format!(
+            "{}/v1/games/{}/mods/updated.json?period={}",
+            self.base_url, game, period
+        );
+        self.get::<Vec<ModUpdate>>(&uri)
+    }
+}
+
+/// Update the limits from a response's rate-limit headers, responding with whether it had any.
+fn note_limits(limits: &mut RateLimits, response: &NexusResponse) -> Result<bool, NexusError> {
+    if let Some(v) = parsed_header(response, "x-rl-hourly-limit")? {
+        limits.hourly_limit = v;
+    }
+    if let Some(v) = parsed_header(response, "x-rl-daily-limit")? {
+        limits.daily_limit = v;
+    }
+    // Until the Nexus has told us something, the stored numbers are only guesses.
+    let guessing = limits.as_of.is_none();
+    note_window(
+        &mut limits.hourly_remaining,
+        &mut limits.hourly_reset,
+        parsed_header(response, "x-rl-hourly-remaining")?,
+        parsed_header(response, "x-rl-hourly-reset")?,
+        guessing,
+    );
+    note_window(
+        &mut limits.daily_remaining,
+        &mut limits.daily_reset,
+        parsed_header(response, "x-rl-daily-remaining")?,
+        parsed_header(response, "x-rl-daily-reset")?,
+        guessing,
+    );
+    Ok(response.header("x-rl-hourly-remaining").is_some())
+}
+
+/// Take one bucket's remaining count and reset time from a response. Responses to concurrent
+/// requests can arrive out of order, but within one window the count only goes down, so the
+/// lowest we've been told is the truth.
+fn note_window(
+    remaining: &mut u16,
+    reset: &mut DateTime<Utc>,
+    reported: Option<u16>,
+    reported_reset: Option<DateTime<Utc>>,
+    guessing: bool,
+) {
+    if let Some(v) = reported {
+        let same_window = reported_reset.is_none_or(|r| r == *reset);
+        *remaining = if same_window && !guessing {
+            v.min(*remaining)
+        } else {
+            v
+        };
+    }
+    if let Some(v) = reported_reset {
+        *reset = v;
+    }
+}
+
+/// Parse a header value, if the response has that header.
+fn parsed_header<T: std::str::FromStr>(
+    response: &NexusResponse,
+    name: &str,
+) -> Result<Option<T>, NexusError> {
+    match response.header(name) {
+        None => Ok(None),
+        Some(v) => v
+            .trim()
+            .parse::<T>()
+            .map(Some)
+            .map_err(|_| NexusError::BadHeader {
+                name: name.to_string(),
+                value: v.to_string(),
+            }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A quota the Nexus told us about a minute ago, with an hour left in its window.
+    fn quota(hourly_remaining: u16) -> Quota {
+        let limits = RateLimits {
+            hourly_remaining,
+            hourly_reset: Utc::now() + chrono::Duration::hours(1),
+            as_of: Some(Utc::now() - chrono::Duration::minutes(1)),
+            ..RateLimits::default()
+        };
+        Quota {
+            limits,
+            in_flight: 0,
+        }
+    }
+
+    fn response(hourly_remaining: u16, hourly_reset: DateTime<Utc>) -> NexusResponse {
+        NexusResponse {
+            status: 200,
+            headers: vec![
+                (
+                    "x-rl-hourly-remaining".to_string(),
+                    hourly_remaining.to_string(),
+                ),
+                ("x-rl-hourly-reset".to_string(), hourly_reset.to_rfc3339()),
+            ],
+            body: String::new(),
+        }
+    }
+
+    #[test]
+    fn concurrent_claims_never_spend_more_than_remains() {
+        let quota = Arc::new(Mutex::new(quota(10)));
+        let claimed: usize = std::thread::scope(|scope| {
+            let threads: Vec<_> = (0..8)
+                .map(|_| {
+                    let quota = quota.clone();
+                    scope.spawn(move || {
+                        (0..5)
+                            .filter(|_| quota.lock().unwrap().claim().is_ok())
+                            .count()
+                    })
+                })
+                .collect();
+            threads.into_iter().map(|t| t.join().unwrap()).sum()
+        });
+        assert_eq!(claimed, 10);
+        let mut quota = quota.lock().unwrap();
+        assert_eq!(quota.in_flight, 10);
+        assert!(quota.available().exhausted());
+        assert!(quota.claim().is_err());
+    }
+
+    #[test]
+    fn releasing_gives_back_claims_and_notes_what_the_nexus_said() {
+        let quota = Arc::new(Mutex::new(quota(10)));
+        let reset = quota.lock().unwrap().limits.hourly_reset;
+        std::thread::scope(|scope| {
+            for remaining in 0..6 {
+                let quota = quota.clone();
+                scope.spawn(move || {
+                    quota.lock().unwrap().claim().unwrap();
+                    // Each response says what was left once it was counted.
+                    let answer = response(9 - remaining, reset);
+                    assert!(quota.lock().unwrap().release(Some(&answer)).unwrap());
+                });
+            }
+        });
+        let mut quota = quota.lock().unwrap();
+        assert_eq!(quota.in_flight, 0);
+        assert_eq!(quota.available().hourly_remaining, 4);
+
+        // A request that never got an answer gives its claim back and changes nothing else.
+        quota.claim().unwrap();
+        assert_eq!(quota.available().hourly_remaining, 3);
+        assert!(!quota.release(None).unwrap());
+        assert_eq!(quota.available().hourly_remaining, 4);
+    }
+
+    #[test]
+    fn out_of_order_responses_keep_the_lowest_count_in_a_window() {
+        let reset = Utc::now() + chrono::Duration::minutes(30);
+        let mut remaining = 50;
+        let mut current = reset;
+        note_window(&mut remaining, &mut current, Some(40), Some(reset), false);
+        note_window(&mut remaining, &mut current, Some(45), Some(reset), false);
+        assert_eq!(remaining, 40);
+
+        // A new window starts over, even with more left than before.
+        let next = reset + chrono::Duration::hours(1);
+        note_window(&mut remaining, &mut current, Some(99), Some(next), false);
+        assert_eq!((remaining, current), (99, next));
+
+        // Until the Nexus has told us anything, our numbers are guesses it overrides.
+        note_window(&mut remaining, &mut current, Some(100), Some(next), true);
+        assert_eq!(remaining, 100);
+    }
+}
@@ -0,0 +1,165 @@
+//! A bounded pool of worker threads for bulk fetches. Each worker gets its own clone of the
+//! client. Clones share their rate-limit bookkeeping and burst throttle, so the quota is
+//! counted once no matter how many workers are spending it.
+
+use std::sync::{mpsc, Mutex};
+
+use super::NexusError;
+use crate::interrupt;
+
+/// Fetch many things at once on up to `workers` threads. Whenever a worker is free, `next`
+/// is asked for something else to fetch; once it says `None`, nothing more is started.
+/// `done` sees each result on this thread, in the order they finish. If `done` fails,
+/// nothing more is started, requests already underway are finished and handed to `done`,
+/// and the first failure is the response. The same goes for Ctrl-C.
+pub fn run<N, K, T>(
+    nexus: &N,
+    workers: usize,
+    mut next: impl FnMut() -> Option<K>,
+    fetch: impl Fn(&K, &mut N) -> T + Sync,
+    mut done: impl FnMut(K, T) -> anyhow::Result<()>,
+) -> anyhow::Result<()>
+where
+    N: Clone + Send,
+    K: Send,
+    T: Send,
+{
+    let workers = workers.max(1);
+    let (job_tx, job_rx) = mpsc::channel::<K>();
+    let job_rx = Mutex::new(job_rx);
+    let (result_tx, result_rx) = mpsc::channel::<(K, T)>();
+
+    std::thread::scope(|scope| {
+        for _ in 0..workers {
+            let mut client = nexus.clone();
+            let result_tx = result_tx.clone();
+            let job_rx = &job_rx;
+            let fetch = &fetch;
+            scope.spawn(move || loop {
+                let job = job_rx.lock().unwrap().recv();
+                // The sender hangs up when there's no more work.
+                let Ok(key) = job else {
+                    break;
+                };
+                let result = fetch(&key, &mut client);
+                if result_tx.send((key, result)).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(result_tx);
+
+        let mut in_flight: usize = 0;
+        let mut starting = true;
+        let mut failure: Option<anyhow::Error> = None;
+        loop {
+            if interrupt::requested() && starting {
+                starting = false;
+                failure.get_or_insert(NexusError::Interrupted.into());
+            }
+            while starting && in_flight < workers {
+                match next() {
+                    Some(key) => {
+                        if job_tx.send(key).is_err() {
+                            starting = false;
+                        } else {
+                            in_flight += 1;
+                        }
+                    }
+                    None => starting = false,
+                }
+            }
+            if in_flight == 0 {
+                break;
+            }
+            let Ok((key, result)) = result_rx.recv() else {
+                break;
+            };
+            in_flight -= 1;
+            if let Err(e) = done(key, result) {
+                starting = false;
+                failure.get_or_insert(e);
+            }
+        }
+        drop(job_tx);
+
+        match failure {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    /// Stands in for a client; the pool only ever clones it.
+    #[derive(Clone)]
+    struct Client;
+
+    #[test]
+    fn every_job_started_is_finished() {
+        let mut ids = 0..50;
+        let mut seen = Vec::new();
+        run(
+            &Client,
+            4,
+            || ids.next(),
+            |id, _client| id * 2,
+            |id, doubled| {
+                assert_eq!(doubled, id * 2);
+                seen.push(id);
+                Ok(())
+            },
+        )
+        .unwrap();
+        seen.sort();
+        assert_eq!(seen, (0..50).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn a_fatal_error_stops_new_work_but_finishes_what_started() {
+        let fetched = AtomicUsize::new(0);
+        let started = Cell::new(0);
+        let mut finished = 0;
+        let mut started_when_it_failed = None;
+        let result = run(
+            &Client,
+            4,
+            || {
+                started.set(started.get() + 1);
+                Some(started.get())
+            },
+            |id, _client| {
+                fetched.fetch_add(1, Ordering::SeqCst);
+                if *id == 5 {
+                    Err(NexusError::MissingApiKey)
+                } else {
+                    Ok(())
+                }
+            },
+            |_id, result| {
+                finished += 1;
+                if result.is_err() {
+                    started_when_it_failed = Some(started.get());
+                }
+                result.map_err(anyhow::Error::from)
+            },
+        );
+
+        let e = result.expect_err("the pool stops on the failure");
+        assert!(matches!(
+            e.downcast_ref::<NexusError>(),
+            Some(NexusError::MissingApiKey)
+        ));
+        // Nothing is started once the failure is seen, and whatever was underway is handed
+        // to `done` before the pool returns.
+        assert_eq!(started_when_it_failed, Some(started.get()));
+        assert_eq!(finished, started.get());
+        assert_eq!(fetched.load(Ordering::SeqCst), started.get());
+    }
+}
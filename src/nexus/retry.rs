@@ -0,0 +1,46 @@
+//! How the client copes with a flaky network and a stingy api: exponential backoff with
+//! jitter for transient failures, and optionally sleeping until the quota resets.
+
+use std::time::Duration;
+
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// How many times to retry a request after its first attempt fails.
+    pub max_retries: u32,
+    /// The ceiling on the first retry's delay; it doubles with each attempt.
+    pub base_delay: Duration,
+    /// No single backoff is ever longer than this.
+    pub max_delay: Duration,
+    /// Sleep until the quota resets instead of failing when we run out of api calls.
+    pub wait_for_reset: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 4,
+            base_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(60),
+            wait_for_reset: false,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// The delay before retry number `attempt`, counting from zero. This is "equal jitter":
+    /// at least half the exponential ceiling, so retries never stampede, plus a random share
+    /// of the other half, so several clients don't retry in lockstep.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let ceiling = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max_delay);
+        let half = ceiling / 2;
+        half + half.mul_f64(fastrand::f64())
+    }
+
+    /// Server errors that are worth trying again.
+    pub fn is_transient(status: u16) -> bool {
+        matches!(status, 500 | 502 | 503 | 504)
+    }
+}
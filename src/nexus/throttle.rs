@@ -0,0 +1,86 @@
+//! The Nexus allows about 30 requests a second before it starts answering with 429s. Every
+//! clone of a client shares one throttle, so however many threads are fetching, together
+//! they stay under that burst limit.
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+#[derive(Debug)]
+pub struct Throttle {
+    per_second: usize,
+    sent: Mutex<VecDeque<Instant>>,
+}
+
+impl Throttle {
+    pub fn new(per_second: usize) -> Self {
+        Self {
+            per_second: per_second.max(1),
+            sent: Mutex::new(VecDeque::with_capacity(per_second)),
+        }
+    }
+
+    /// Block until sending another request would keep us within the limit for the last
+    /// second, then count it as sent.
+    pub fn wait(&self) {
+        let window = Duration::from_secs(1);
+        loop {
+            let delay = {
+                let mut sent = self.sent.lock().unwrap();
+                let now = Instant::now();
+                while sent
+                    .front()
+                    .is_some_and(|t| now.duration_since(*t) >= window)
+                {
+                    sent.pop_front();
+                }
+                if sent.len() < self.per_second {
+                    sent.push_back(now);
+                    return;
+                }
+                // The oldest request ages out of the window first.
+                window - now.duration_since(sent[0])
+            };
+            std::thread::sleep(delay);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::nexus::BURST_LIMIT;
+
+    #[test]
+    fn a_full_burst_goes_at_once_and_the_next_waits_for_the_window() {
+        let throttle = Throttle::new(BURST_LIMIT);
+        let start = Instant::now();
+        for _ in 0..BURST_LIMIT {
+            throttle.wait();
+        }
+        assert!(start.elapsed() < Duration::from_millis(500));
+        throttle.wait();
+        assert!(start.elapsed() >= Duration::from_secs(1));
+    }
+
+    #[test]
+    fn threads_sharing_a_throttle_share_its_limit() {
+        let throttle = Arc::new(Throttle::new(BURST_LIMIT));
+        let start = Instant::now();
+        std::thread::scope(|scope| {
+            for _ in 0..4 {
+                let throttle = throttle.clone();
+                scope.spawn(move || {
+                    for _ in 0..10 {
+                        throttle.wait();
+                    }
+                });
+            }
+        });
+        // Forty requests at thirty a second can't all go in the first second.
+        assert!(start.elapsed() >= Duration::from_secs(1));
+        assert!(throttle.sent.lock().unwrap().len() <= BURST_LIMIT);
+    }
+}
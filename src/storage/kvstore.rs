@@ -0,0 +1,91 @@
+//! The original cache: a kv store, which is sled underneath. Each bucket is a sled tree.
+
+use kv::{Bucket, Config, Store};
+
+use super::Storage;
+
+#[derive(Debug, Clone)]
+pub struct KvStorage {
+    store: Store,
+}
+
+impl KvStorage {
+    pub fn open(path: &str) -> anyhow::Result<Self> {
+        let store = Store::new(Config::new(path))?;
+        Ok(Self { store })
+    }
+
+    fn bucket(&self, name: &str) -> anyhow::Result<Bucket<'_, String, String>> {
+        Ok(self.store.bucket::<String, String>(Some(name))?)
+    }
+}
+
+impl Storage for KvStorage {
+    fn get(&self, bucket: &str, key: &str) -> anyhow::Result<Option<String>> {
+        Ok(self.bucket(bucket)?.get(&key.to_string())?)
+    }
+
+    fn set(&self, bucket: &str, key: &str, json: &str) -> anyhow::Result<()> {
+        let bucket = self.bucket(bucket)?;
+        bucket.set(&key.to_string(), &json.to_string())?;
+        bucket.flush()?;
+        Ok(())
+    }
+
+    fn remove(&self, bucket: &str, key: &str) -> anyhow::Result<()> {
+        let bucket = self.bucket(bucket)?;
+        bucket.remove(&key.to_string())?;
+        bucket.flush()?;
+        Ok(())
+    }
+
+    fn set_many(&self, bucket: &str, records: &[(String, String)]) -> anyhow::Result<()> {
+        let bucket = self.bucket(bucket)?;
+        for (key, json) in records.iter() {
+            bucket.set(key, json)?;
+        }
+        bucket.flush()?;
+        Ok(())
+    }
+
+    fn remove_many(&self, bucket: &str, keys: &[String]) -> anyhow::Result<()> {
+        let bucket = self.bucket(bucket)?;
+        for key in keys.iter() {
+            bucket.remove(key)?;
+        }
+        bucket.flush()?;
+        Ok(())
+    }
+
+    fn scan(&self, bucket: &str, prefix: &str) -> anyhow::Result<Vec<(String, String)>> {
+        let bucket = self.bucket(bucket)?;
+        let mut result = Vec::new();
+        for item in bucket.iter_prefix(&prefix.to_string())? {
+            let item = item?;
+            result.push((item.key::<String>()?, item.value::<String>()?));
+        }
+        Ok(result)
+    }
+
+    fn buckets(&self) -> anyhow::Result<Vec<String>> {
+        let mut names = Vec::new();
+        for name in self.store.buckets() {
+            // sled always has a default tree, which we never use.
+            if name != "__sled__default" && !self.bucket(&name)?.is_empty() {
+                names.push(name);
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+
+    fn size_on_disk(&self) -> anyhow::Result<u64> {
+        Ok(self.store.size_on_disk()?)
+    }
+
+    fn compact(&self) -> anyhow::Result<()> {
+        // sled reclaims space on its own as it goes; the most we can do is flush.
+        self.bucket("__sled__default")?.flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,182 @@
+//! The cache outlives the code that wrote it. Each cache records the schema version its
+//! records were written with, and opening an older one brings its records up to date.
+//! Records that can't be brought up to date, or that don't read as what they claim to be
+//! afterward, are left where they are and reported: logged as warnings and listed in the
+//! `unmigrated` bucket, so nothing disappears from the cache without a word.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::Storage;
+use crate::data::cached_types;
+
+/// The schema version this build of modcache reads and writes.
+pub static SCHEMA_VERSION: u32 = 2;
+
+static META: &str = "meta";
+static VERSION_KEY: &str = "schema_version";
+/// Where records we couldn't migrate are reported.
+pub static UNMIGRATED: &str = "unmigrated";
+
+/// One step forward in the schema: a change to every record in one bucket.
+struct Migration {
+    /// The schema version this migration brings the cache up to.
+    version: u32,
+    bucket: &'static str,
+    description: &'static str,
+    /// Rewrite a record in place. Leaving it alone is fine if it's already up to date.
+    upgrade: fn(&mut Value) -> anyhow::Result<()>,
+}
+
+static MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        bucket: "games",
+        description: "store top-level game categories with a null parent instead of false",
+        upgrade: null_parent_categories,
+    },
+    Migration {
+        version: 2,
+        bucket: "authed_users",
+        description: "forget the api keys the Nexus echoed back when validating",
+        upgrade: forget_api_key,
+    },
+];
+
+fn null_parent_categories(record: &mut Value) -> anyhow::Result<()> {
+    let Some(categories) = record.get_mut("categories") else {
+        return Ok(());
+    };
+    let Some(categories) = categories.as_array_mut() else {
+        anyhow::bail!("categories is not a list");
+    };
+    for category in categories.iter_mut() {
+        match category.get("parent_category") {
+            Some(Value::Bool(false)) => category["parent_category"] = Value::Null,
+            Some(Value::Number(_)) | Some(Value::Null) | None => {}
+            Some(other) => anyhow::bail!("unexpected parent_category {}", other),
+        }
+    }
+    Ok(())
+}
+
+/// Accounts were once stored with the api key `validate` responds with. Keys belong in the
+/// keyring or the credentials file, not in the cache.
+fn forget_api_key(record: &mut Value) -> anyhow::Result<()> {
+    if let Some(record) = record.as_object_mut() {
+        record.remove("key");
+    }
+    Ok(())
+}
+
+/// A record we couldn't bring up to date, and why.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Unmigrated {
+    pub bucket: String,
+    pub key: String,
+    pub error: String,
+}
+
+/// The schema version a cache was last written with. Caches from before we kept track
+/// are version 0.
+pub fn stored_version(store: &dyn Storage) -> anyhow::Result<u32> {
+    match store.get(META, VERSION_KEY)? {
+        Some(json) => Ok(serde_json::from_str(&json)?),
+        None => Ok(0),
+    }
+}
+
+/// Bring one record written at an older schema version up to date, as when importing.
+pub fn upgrade_record(bucket: &str, from: u32, record: &mut Value) -> anyhow::Result<()> {
+    for migration in MIGRATIONS
+        .iter()
+        .filter(|m| m.version > from && m.bucket == bucket)
+    {
+        (migration.upgrade)(record)?;
+    }
+    Ok(())
+}
+
+/// Bring a cache up to the current schema version, running every migration it hasn't
+/// seen yet, then checking that every record reads as its current type.
+pub fn upgrade(store: &dyn Storage) -> anyhow::Result<()> {
+    let found = stored_version(store)?;
+    if found == SCHEMA_VERSION {
+        return Ok(());
+    }
+    if found > SCHEMA_VERSION {
+        log::warn!(
+            "This cache was written by a newer modcache (schema version {}; this one reads {}). Some records may not make sense.",
+            found,
+            SCHEMA_VERSION
+        );
+        return Ok(());
+    }
+
+    let mut failures: BTreeMap<(String, String), String> = BTreeMap::new();
+    for migration in MIGRATIONS.iter().filter(|m| m.version > found) {
+        log::info!(
+            "Migrating the cache to version {}: {}",
+            migration.version,
+            migration.description
+        );
+        for (key, json) in store.scan(migration.bucket, "")? {
+            let mut record = match serde_json::from_str::<Value>(&json) {
+                Ok(v) => v,
+                Err(e) => {
+                    failures.insert((migration.bucket.to_string(), key), e.to_string());
+                    continue;
+                }
+            };
+            match (migration.upgrade)(&mut record) {
+                Ok(()) => store.set(migration.bucket, &key, &record.to_string())?,
+                Err(e) => {
+                    failures.insert((migration.bucket.to_string(), key), e.to_string());
+                }
+            }
+        }
+    }
+
+    for kind in cached_types() {
+        for (key, json) in store.scan(kind.bucket, "")? {
+            let id = (kind.bucket.to_string(), key);
+            if failures.contains_key(&id) {
+                continue;
+            }
+            if let Err(e) = (kind.read)(&json) {
+                failures.insert(id, e.to_string());
+            }
+        }
+    }
+
+    // Each upgrade reports afresh.
+    for (key, _json) in store.scan(UNMIGRATED, "")? {
+        store.remove(UNMIGRATED, &key)?;
+    }
+    for ((bucket, key), error) in failures.iter() {
+        log::warn!("Unable to migrate {}/{}: {}", bucket, key, error);
+        let report = Unmigrated {
+            bucket: bucket.clone(),
+            key: key.clone(),
+            error: error.clone(),
+        };
+        store.set(
+            UNMIGRATED,
+            &format!("{}/{}", bucket, key),
+            &serde_json::to_string(&report)?,
+        )?;
+    }
+    if !failures.is_empty() {
+        log::warn!(
+            "{} cached records couldn't be migrated to schema version {}. They're left as they were and listed in the `{}` bucket.",
+            failures.len(),
+            SCHEMA_VERSION,
+            UNMIGRATED
+        );
+    }
+
+    store.set(META, VERSION_KEY, &SCHEMA_VERSION.to_string())?;
+    Ok(())
+}
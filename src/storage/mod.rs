@@ -0,0 +1,223 @@
+//! Where the cache lives on disk. Everything modcache stores is a json record, filed by
+//! bucket and key, and a `Storage` backend keeps them. The kv store (sled underneath) is
+//! the default. The SQLite backend also gives mods real columns, so searches and status
+//! filters are queries instead of scans, and other tools can read the cache with plain SQL.
+
+use std::fmt::Debug;
+use std::str::FromStr;
+
+use anyhow::Context;
+use regex::{Regex, RegexBuilder};
+use serde_json::Value;
+
+use crate::data::ModStatus;
+
+pub mod kvstore;
+pub mod migrations;
+pub mod remote;
+pub mod sqlite;
+
+pub use kvstore::KvStorage;
+pub use remote::RemoteStorage;
+pub use sqlite::SqliteStorage;
+
+/// The bucket full mod records live in. Backends may treat it specially.
+pub static MODS: &str = "mods";
+
+pub trait Storage: Debug + Send + Sync {
+    /// Look up a record, responding with its json if it's there.
+    fn get(&self, bucket: &str, key: &str) -> anyhow::Result<Option<String>>;
+    /// Store a record, replacing any already under that key.
+    fn set(&self, bucket: &str, key: &str, json: &str) -> anyhow::Result<()>;
+    /// Remove a record. Removing one that isn't there is not an error.
+    fn remove(&self, bucket: &str, key: &str) -> anyhow::Result<()>;
+    /// Store several records in one bucket at once, as keys and json. Backends that can
+    /// write them together rather than one by one should.
+    fn set_many(&self, bucket: &str, records: &[(String, String)]) -> anyhow::Result<()> {
+        for (key, json) in records.iter() {
+            self.set(bucket, key, json)?;
+        }
+        Ok(())
+    }
+    /// Remove several records from one bucket at once.
+    fn remove_many(&self, bucket: &str, keys: &[String]) -> anyhow::Result<()> {
+        for key in keys.iter() {
+            self.remove(bucket, key)?;
+        }
+        Ok(())
+    }
+    /// Every record in a bucket with a key starting with this prefix, as key and json.
+    fn scan(&self, bucket: &str, prefix: &str) -> anyhow::Result<Vec<(String, String)>>;
+    /// The names of every bucket with anything in it.
+    fn buckets(&self) -> anyhow::Result<Vec<String>>;
+    /// How many bytes the cache takes up on disk.
+    fn size_on_disk(&self) -> anyhow::Result<u64>;
+    /// Write everything out and give back whatever space the backend can.
+    fn compact(&self) -> anyhow::Result<()>;
+    /// Find the mods cached for a game that match a query, responding with their json.
+    /// By default this scans every mod for the game; backends that can do better should.
+    fn find_mods(&self, game: &str, query: &ModQuery) -> anyhow::Result<Vec<String>> {
+        let matcher = query.matcher()?;
+        let found = self
+            .scan(MODS, &format!("{}/", game))?
+            .into_iter()
+            .filter(|(_key, json)| match serde_json::from_str::<Value>(json) {
+                Ok(record) => matcher.matches(&record),
+                // Let the caller decide what to say about records it can't read.
+                Err(_) => true,
+            })
+            .map(|(_key, json)| json)
+            .collect();
+        Ok(found)
+    }
+}
+
+/// Which backend to keep the cache in.
+#[derive(Debug, Clone, Copy, Default)]
+pub enum Backend {
+    #[default]
+    Kv,
+    Sqlite,
+}
+
+impl FromStr for Backend {
+    type Err = anyhow::Error;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input.to_lowercase().as_str() {
+            "kv" | "sled" => Ok(Backend::Kv),
+            "sqlite" => Ok(Backend::Sqlite),
+            _ => anyhow::bail!("the cache backend must be `sled` or `sqlite`"),
+        }
+    }
+}
+
+impl Backend {
+    /// Where the cache goes if nobody says otherwise.
+    pub fn default_path(&self) -> &'static str {
+        match self {
+            Backend::Kv => "./db/nexus_cache.sled",
+            Backend::Sqlite => "./db/nexus_cache.sqlite",
+        }
+    }
+
+    /// Open the cache, bringing its records up to the current schema version if needed.
+    /// If another modcache has the kv store open, go through that process instead, until it
+    /// exits and we take the store over.
+    pub fn open(&self, path: &str) -> anyhow::Result<Box<dyn Storage>> {
+        let store: Box<dyn Storage> = match self {
+            Backend::Kv => match KvStorage::open(path) {
+                Ok(store) => {
+                    if let Err(e) = remote::serve(store.clone(), path) {
+                        log::warn!(
+                            "Unable to share the cache with other modcache processes: {e:#}"
+                        );
+                    }
+                    Box::new(store)
+                }
+                Err(e) => {
+                    // The process that has it open already brought it up to date.
+                    return match RemoteStorage::connect(path) {
+                        Ok(remote) => Ok(Box::new(remote)),
+                        Err(remote_error) => Err(e.context(format!(
+                            "Unable to open the cache at {}, or to reach another modcache using it: {:#}",
+                            path, remote_error
+                        ))),
+                    };
+                }
+            },
+            Backend::Sqlite => Box::new(SqliteStorage::open(path)?),
+        };
+        migrations::upgrade(store.as_ref())?;
+        Ok(store)
+    }
+}
+
+/// The questions we ask of a game's cached mods. Patterns are case-insensitive regexes.
+#[derive(Debug, Clone)]
+pub enum ModQuery {
+    All,
+    /// Mods with names matching the pattern.
+    Name(String),
+    /// Mods with uploader or author credits matching the pattern.
+    Author(String),
+    Status(ModStatus),
+}
+
+impl ModQuery {
+    /// The fields each pattern query looks at.
+    pub fn fields(&self) -> &'static [&'static str] {
+        match self {
+            ModQuery::Name(_) => &["name"],
+            ModQuery::Author(_) => &["uploaded_by", "author"],
+            ModQuery::All | ModQuery::Status(_) => &[],
+        }
+    }
+
+    pub fn pattern(&self) -> Option<&str> {
+        match self {
+            ModQuery::Name(p) | ModQuery::Author(p) => Some(p),
+            ModQuery::All | ModQuery::Status(_) => None,
+        }
+    }
+
+    /// The status as it's spelled in stored json.
+    pub fn status(&self) -> Option<String> {
+        match self {
+            ModQuery::Status(status) => serde_json::to_value(status)
+                .ok()
+                .and_then(|v| v.as_str().map(|s| s.to_string())),
+            _ => None,
+        }
+    }
+
+    fn matcher(&self) -> anyhow::Result<Matcher> {
+        let pattern = match self.pattern() {
+            Some(p) => Some(case_insensitive(p)?),
+            None => None,
+        };
+        Ok(Matcher {
+            fields: self.fields(),
+            pattern,
+            status: self.status(),
+        })
+    }
+}
+
+/// Compile a pattern from the user, saying what's wrong with it if it doesn't compile.
+pub fn case_insensitive(pattern: &str) -> anyhow::Result<Regex> {
+    RegexBuilder::new(pattern)
+        .case_insensitive(true)
+        .build()
+        .with_context(|| {
+            format!(
+                "`{}` isn't a valid pattern. Put a \\ before characters like ( [ + * ? to look for them as they are.",
+                pattern
+            )
+        })
+}
+
+/// A query, ready to test json records against.
+struct Matcher {
+    fields: &'static [&'static str],
+    pattern: Option<Regex>,
+    status: Option<String>,
+}
+
+impl Matcher {
+    fn matches(&self, record: &Value) -> bool {
+        if let Some(status) = &self.status {
+            if record["status"].as_str() != Some(status) {
+                return false;
+            }
+        }
+        match &self.pattern {
+            Some(patt) => self
+                .fields
+                .iter()
+                .filter_map(|field| record[*field].as_str())
+                .any(|text| patt.is_match(text)),
+            None => true,
+        }
+    }
+}
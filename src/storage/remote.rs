@@ -0,0 +1,436 @@
+//! sled lets only one process at a time open a store, so a long `populate` would otherwise
+//! lock everyone else out of the cache. Whichever modcache has the kv store open also
+//! answers storage requests from other modcache processes, over http on a local port it
+//! notes in a file next to the store. Processes that find the store locked go through it
+//! instead. SQLite handles several processes by itself and needs none of this.
+//!
+//! A process that goes through another depends on it only while it runs. If the owner exits
+//! first, the next request finds nobody answering, and the process opens the store itself
+//! and starts serving, or goes through whichever process got there first.
+//!
+//! The file holds the token that lets a process in, so only its owner can read it. It's kept
+//! out of the store, so copying the store doesn't copy a live token, and removed when the
+//! serving process exits.
+
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, RwLock};
+use std::time::Duration;
+
+use anyhow::Context;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::OsRng;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tiny_http::{Header, Request, Response, Server};
+
+use super::{KvStorage, Storage};
+
+/// Added to the store's path to name the file that says where its owner is listening.
+static SERVER_FILE: &str = ".server.json";
+static TOKEN_HEADER: &str = "x-modcache-token";
+/// How many times to look for a new owner after the old one goes away, and how long to wait
+/// between looks while another process is still taking over.
+const TAKEOVER_ATTEMPTS: usize = 20;
+const TAKEOVER_PAUSE: Duration = Duration::from_millis(100);
+
+/// The server file this process wrote, to clean up on the way out.
+static SERVING: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+/// Where to find the process that has the store open.
+#[derive(Serialize, Deserialize, Debug)]
+struct ServerInfo {
+    pid: u32,
+    port: u16,
+    /// Sent with every request, so only processes that can read the store can use it.
+    token: String,
+}
+
+/// Everything one process can ask of another's store.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "op", rename_all = "snake_case")]
+enum Op {
+    Ping,
+    Get {
+        bucket: String,
+        key: String,
+    },
+    Set {
+        bucket: String,
+        key: String,
+        json: String,
+    },
+    Remove {
+        bucket: String,
+        key: String,
+    },
+    SetMany {
+        bucket: String,
+        records: Vec<(String, String)>,
+    },
+    RemoveMany {
+        bucket: String,
+        keys: Vec<String>,
+    },
+    Scan {
+        bucket: String,
+        prefix: String,
+    },
+    Buckets,
+    SizeOnDisk,
+    Compact,
+}
+
+impl Op {
+    fn apply(self, store: &dyn Storage) -> anyhow::Result<Value> {
+        let response = match self {
+            Op::Ping => Value::Null,
+            Op::Get { bucket, key } => serde_json::to_value(store.get(&bucket, &key)?)?,
+            Op::Set { bucket, key, json } => {
+                store.set(&bucket, &key, &json)?;
+                Value::Null
+            }
+            Op::Remove { bucket, key } => {
+                store.remove(&bucket, &key)?;
+                Value::Null
+            }
+            Op::SetMany { bucket, records } => {
+                store.set_many(&bucket, &records)?;
+                Value::Null
+            }
+            Op::RemoveMany { bucket, keys } => {
+                store.remove_many(&bucket, &keys)?;
+                Value::Null
+            }
+            Op::Scan { bucket, prefix } => serde_json::to_value(store.scan(&bucket, &prefix)?)?,
+            Op::Buckets => serde_json::to_value(store.buckets()?)?,
+            Op::SizeOnDisk => serde_json::to_value(store.size_on_disk()?)?,
+            Op::Compact => {
+                store.compact()?;
+                Value::Null
+            }
+        };
+        Ok(response)
+    }
+}
+
+fn server_file(path: &str) -> PathBuf {
+    PathBuf::from(format!("{}{}", path.trim_end_matches('/'), SERVER_FILE))
+}
+
+/// Write the server file readable only by its owner.
+fn write_server_file(path: &Path, info: &ServerInfo) -> anyhow::Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options
+        .open(path)
+        .with_context(|| format!("Unable to write {}", path.display()))?;
+    file.write_all(serde_json::to_string(info)?.as_bytes())?;
+    Ok(())
+}
+
+/// A token nobody can guess, from the operating system's random number generator.
+fn new_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Remove the server file, if this process is serving. Call on the way out.
+pub fn stop_serving() {
+    let Some(file) = SERVING.lock().unwrap().take() else {
+        return;
+    };
+    if let Err(e) = std::fs::remove_file(&file) {
+        log::debug!("Unable to remove {}: {}", file.display(), e);
+    }
+}
+
+/// Answer requests for this store from other modcache processes, on a background thread,
+/// for as long as this process runs. Call `stop_serving` before exiting.
+pub fn serve<S: Storage + 'static>(store: S, path: &str) -> anyhow::Result<()> {
+    let server = Server::http("127.0.0.1:0").map_err(|e| anyhow::anyhow!(e))?;
+    let port = server
+        .server_addr()
+        .to_ip()
+        .map(|addr| addr.port())
+        .context("the store server isn't listening on a port")?;
+    let info = ServerInfo {
+        pid: std::process::id(),
+        port,
+        token: new_token(),
+    };
+    let file = server_file(path);
+    // An existing file would keep its permissions, so start afresh.
+    let _ = std::fs::remove_file(&file);
+    write_server_file(&file, &info)?;
+    *SERVING.lock().unwrap() = Some(file);
+    crate::interrupt::watch();
+    log::debug!(
+        "Sharing the cache with other modcache processes on port {}",
+        port
+    );
+
+    std::thread::spawn(move || {
+        for request in server.incoming_requests() {
+            if let Err(e) = respond(&store, &info.token, request) {
+                log::warn!("Unable to answer another modcache process: {:?}", e);
+            }
+        }
+    });
+    Ok(())
+}
+
+fn respond(store: &dyn Storage, token: &str, mut request: Request) -> anyhow::Result<()> {
+    let authed = request
+        .headers()
+        .iter()
+        .any(|h| h.field.equiv(TOKEN_HEADER) && h.value.as_str() == token);
+    let (status, body) = if authed {
+        let mut body = String::new();
+        request.as_reader().read_to_string(&mut body)?;
+        match serde_json::from_str::<Op>(&body) {
+            Ok(op) => match op.apply(store) {
+                Ok(response) => (200, response.to_string()),
+                Err(e) => (500, format!("{:#}", e)),
+            },
+            Err(e) => (400, e.to_string()),
+        }
+    } else {
+        (403, "not a modcache request".to_string())
+    };
+
+    let header = Header::from_bytes("content-type", "application/json; charset=utf-8")
+        .expect("header names are ascii");
+    request.respond(
+        Response::from_string(body)
+            .with_status_code(status)
+            .with_header(header),
+    )?;
+    Ok(())
+}
+
+/// A store another modcache process has open, reached through that process. If that process
+/// goes away, this one takes the store over or finds whoever did.
+#[derive(Debug)]
+pub struct RemoteStorage {
+    path: String,
+    link: RwLock<Link>,
+}
+
+/// How we're reaching the store right now.
+#[derive(Debug)]
+enum Link {
+    Remote(Owner),
+    /// The owner went away and we opened the store ourselves.
+    Local(KvStorage),
+}
+
+/// The process that has the store open.
+#[derive(Debug)]
+struct Owner {
+    agent: ureq::Agent,
+    pid: u32,
+    url: String,
+    token: String,
+}
+
+impl Owner {
+    fn connect(path: &str) -> anyhow::Result<Self> {
+        let json = std::fs::read_to_string(server_file(path))
+            .context("no other modcache is sharing it")?;
+        let info: ServerInfo = serde_json::from_str(&json)?;
+        let agent = ureq::AgentBuilder::new()
+            .timeout_connect(Duration::from_secs(2))
+            .build();
+        let owner = Self {
+            agent,
+            pid: info.pid,
+            url: format!("http://127.0.0.1:{}/", info.port),
+            token: info.token,
+        };
+        let pid = info.pid;
+        owner.send(&Op::Ping).map_err(|e| {
+            anyhow::Error::new(*e).context(format!("process {pid} isn't answering"))
+        })??;
+        Ok(owner)
+    }
+
+    /// Send one request. The outer error means the owner couldn't be reached at all; the
+    /// inner one is the owner's answer when it couldn't do what we asked.
+    fn send(&self, op: &Op) -> Result<anyhow::Result<Value>, Box<ureq::Transport>> {
+        let body = match serde_json::to_string(op) {
+            Ok(body) => body,
+            Err(e) => return Ok(Err(e.into())),
+        };
+        let result = self
+            .agent
+            .post(&self.url)
+            .set(TOKEN_HEADER, &self.token)
+            .send_string(&body);
+        let answer = match result {
+            Ok(response) => response
+                .into_string()
+                .map_err(anyhow::Error::from)
+                .and_then(|text| Ok(serde_json::from_str(&text)?)),
+            Err(ureq::Error::Status(_, response)) => Err(anyhow::anyhow!(
+                "the modcache sharing the cache said: {}",
+                response.into_string().unwrap_or_default()
+            )),
+            Err(ureq::Error::Transport(e)) => return Err(Box::new(e)),
+        };
+        Ok(answer)
+    }
+}
+
+impl RemoteStorage {
+    /// Connect to whichever process has the store at this path open, if it's answering.
+    pub fn connect(path: &str) -> anyhow::Result<Self> {
+        let owner = Owner::connect(path)?;
+        log::info!(
+            "The cache is open in another modcache (process {}); going through it.",
+            owner.pid
+        );
+        Ok(Self {
+            path: path.to_string(),
+            link: RwLock::new(Link::Remote(owner)),
+        })
+    }
+
+    fn call(&self, op: Op) -> anyhow::Result<Value> {
+        let (token, lost) = {
+            let link = self.link.read().unwrap();
+            match &*link {
+                Link::Local(store) => return op.apply(store),
+                Link::Remote(owner) => match owner.send(&op) {
+                    Ok(answer) => return answer,
+                    Err(e) => (owner.token.clone(), e),
+                },
+            }
+        };
+        self.take_over(&token, lost)?;
+        let link = self.link.read().unwrap();
+        match &*link {
+            Link::Local(store) => op.apply(store),
+            Link::Remote(owner) => owner.send(&op)?,
+        }
+    }
+
+    /// The owner with this token stopped answering. Open the store ourselves if it's free,
+    /// or go through the process that opened it first.
+    fn take_over(&self, token: &str, lost: Box<ureq::Transport>) -> anyhow::Result<()> {
+        let mut link = self.link.write().unwrap();
+        // Another thread may have found the new owner while we waited for the lock.
+        match &*link {
+            Link::Remote(owner) if owner.token == token => {}
+            _ => return Ok(()),
+        }
+        log::info!("The modcache sharing the cache went away ({}).", lost);
+
+        for _ in 0..TAKEOVER_ATTEMPTS {
+            match KvStorage::open(&self.path) {
+                Ok(store) => {
+                    if let Err(e) = serve(store.clone(), &self.path) {
+                        log::warn!(
+                            "Unable to share the cache with other modcache processes: {e:#}"
+                        );
+                    }
+                    log::info!("Opened the cache here and took over sharing it.");
+                    *link = Link::Local(store);
+                    return Ok(());
+                }
+                Err(_) => {
+                    // Either the new owner hasn't written its server file yet, or the
+                    // file is still the old owner's.
+                    if let Ok(owner) = Owner::connect(&self.path) {
+                        if owner.token != token {
+                            log::info!(
+                                "The cache is now open in process {}; going through it.",
+                                owner.pid
+                            );
+                            *link = Link::Remote(owner);
+                            return Ok(());
+                        }
+                    }
+                }
+            }
+            std::thread::sleep(TAKEOVER_PAUSE);
+        }
+        Err(anyhow::Error::new(*lost).context(format!(
+            "The modcache sharing the cache at {} went away, and it couldn't be opened here",
+            self.path
+        )))
+    }
+
+    fn call_for<T: for<'de> Deserialize<'de>>(&self, op: Op) -> anyhow::Result<T> {
+        Ok(serde_json::from_value(self.call(op)?)?)
+    }
+}
+
+impl Storage for RemoteStorage {
+    fn get(&self, bucket: &str, key: &str) -> anyhow::Result<Option<String>> {
+        self.call_for(Op::Get {
+            bucket: bucket.to_string(),
+            key: key.to_string(),
+        })
+    }
+
+    fn set(&self, bucket: &str, key: &str, json: &str) -> anyhow::Result<()> {
+        self.call(Op::Set {
+            bucket: bucket.to_string(),
+            key: key.to_string(),
+            json: json.to_string(),
+        })?;
+        Ok(())
+    }
+
+    fn remove(&self, bucket: &str, key: &str) -> anyhow::Result<()> {
+        self.call(Op::Remove {
+            bucket: bucket.to_string(),
+            key: key.to_string(),
+        })?;
+        Ok(())
+    }
+
+    fn set_many(&self, bucket: &str, records: &[(String, String)]) -> anyhow::Result<()> {
+        self.call(Op::SetMany {
+            bucket: bucket.to_string(),
+            records: records.to_vec(),
+        })?;
+        Ok(())
+    }
+
+    fn remove_many(&self, bucket: &str, keys: &[String]) -> anyhow::Result<()> {
+        self.call(Op::RemoveMany {
+            bucket: bucket.to_string(),
+            keys: keys.to_vec(),
+        })?;
+        Ok(())
+    }
+
+    fn scan(&self, bucket: &str, prefix: &str) -> anyhow::Result<Vec<(String, String)>> {
+        self.call_for(Op::Scan {
+            bucket: bucket.to_string(),
+            prefix: prefix.to_string(),
+        })
+    }
+
+    fn buckets(&self) -> anyhow::Result<Vec<String>> {
+        self.call_for(Op::Buckets)
+    }
+
+    fn size_on_disk(&self) -> anyhow::Result<u64> {
+        self.call_for(Op::SizeOnDisk)
+    }
+
+    fn compact(&self) -> anyhow::Result<()> {
+        self.call(Op::Compact)?;
+        Ok(())
+    }
+}
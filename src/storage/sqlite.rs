@@ -0,0 +1,430 @@
+//! The cache in a SQLite database. Most records go in one `records` table, as json. Mods get
+//! a table of their own, with the fields we search and filter on pulled out into indexed
+//! columns alongside the json.
+
+use std::sync::{Arc, Mutex};
+
+use regex::Regex;
+use rusqlite::functions::FunctionFlags;
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension};
+use serde_json::Value;
+
+use super::{case_insensitive, ModQuery, Storage, MODS};
+
+static SCHEMA: &str = r#"
+CREATE TABLE IF NOT EXISTS records (
+    bucket TEXT NOT NULL,
+    key TEXT NOT NULL,
+    value TEXT NOT NULL,
+    PRIMARY KEY (bucket, key)
+);
+CREATE TABLE IF NOT EXISTS mods (
+    key TEXT PRIMARY KEY,
+    domain_name TEXT NOT NULL,
+    mod_id INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    summary TEXT NOT NULL,
+    author TEXT NOT NULL,
+    uploaded_by TEXT NOT NULL,
+    status TEXT NOT NULL,
+    category_id INTEGER NOT NULL,
+    created_timestamp INTEGER NOT NULL,
+    updated_timestamp INTEGER NOT NULL,
+    value TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS mods_by_status ON mods (domain_name, status);
+CREATE INDEX IF NOT EXISTS mods_by_category ON mods (domain_name, category_id);
+DROP INDEX IF EXISTS mods_by_author;
+DROP INDEX IF EXISTS mods_by_uploader;
+CREATE INDEX IF NOT EXISTS mods_by_name_nocase ON mods (domain_name, name COLLATE NOCASE);
+CREATE INDEX IF NOT EXISTS mods_by_author_nocase ON mods (domain_name, author COLLATE NOCASE);
+CREATE INDEX IF NOT EXISTS mods_by_uploader_nocase
+    ON mods (domain_name, uploaded_by COLLATE NOCASE);
+CREATE INDEX IF NOT EXISTS mods_by_updated ON mods (domain_name, updated_timestamp);
+"#;
+
+#[derive(Debug)]
+pub struct SqliteStorage {
+    conn: Mutex<Connection>,
+}
+
+impl SqliteStorage {
+    pub fn open(path: &str) -> anyhow::Result<Self> {
+        if let Some(dir) = std::path::Path::new(path).parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let conn = Connection::open(path)?;
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        conn.pragma_update(None, "synchronous", "NORMAL")?;
+        add_regexp(&conn)?;
+        conn.execute_batch(SCHEMA)?;
+        Ok(Self {
+            conn: Mutex::new(conn),
+        })
+    }
+}
+
+/// Store a mod in its own table, on a connection or in a transaction.
+fn set_mod(conn: &Connection, key: &str, json: &str) -> anyhow::Result<()> {
+    let record: Value = serde_json::from_str(json)?;
+    let text = |field: &str| record[field].as_str().unwrap_or_default().to_string();
+    let number = |field: &str| record[field].as_u64().unwrap_or_default() as i64;
+    conn.execute(
+        "INSERT OR REPLACE INTO mods (key, domain_name, mod_id, name, summary, author,
+            uploaded_by, status, category_id, created_timestamp, updated_timestamp, value)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+        params![
+            key,
+            text("domain_name"),
+            number("mod_id"),
+            text("name"),
+            text("summary"),
+            text("author"),
+            text("uploaded_by"),
+            text("status"),
+            number("category_id"),
+            number("created_timestamp"),
+            number("updated_timestamp"),
+            json,
+        ],
+    )?;
+    Ok(())
+}
+
+/// The first key after every key that starts with the prefix: the prefix with its last
+/// character bumped to the next one. `None` if no such key exists, as for an empty prefix.
+fn past_prefix(prefix: &str) -> Option<String> {
+    let mut chars: Vec<char> = prefix.chars().collect();
+    while let Some(last) = chars.pop() {
+        let next = (last as u32 + 1..=char::MAX as u32).find_map(char::from_u32);
+        if let Some(next) = next {
+            chars.push(next);
+            return Some(chars.into_iter().collect());
+        }
+    }
+    None
+}
+
+/// A pattern that's plain text, perhaps anchored at either end, as a test SQLite runs itself
+/// instead of calling back to a regex for every row. Only a pattern anchored at the start,
+/// `^SkyUI` or `^SkyUI$`, can be looked up in the case-insensitive indexes. Text to find
+/// anywhere in a name needs a `LIKE` with a leading wildcard, so SQLite still reads each of
+/// the game's mods for it, though faster than a regex would.
+#[derive(Debug, PartialEq)]
+enum PlainMatch {
+    Equal(String),
+    Like(String),
+}
+
+impl PlainMatch {
+    /// `None` for anything that needs a regex. SQLite only folds case for ASCII, so text
+    /// that isn't ASCII needs one too.
+    fn of(pattern: &str) -> Option<Self> {
+        let (start, rest) = match pattern.strip_prefix('^') {
+            Some(rest) => (true, rest),
+            None => (false, pattern),
+        };
+        let (end, text) = match rest.strip_suffix('$') {
+            Some(text) => (true, text),
+            None => (false, rest),
+        };
+        let special = |c: char| r"\.+*?()|[]{}^$".contains(c);
+        if text.is_empty() || !text.is_ascii() || text.contains(special) {
+            return None;
+        }
+        if start && end {
+            return Some(PlainMatch::Equal(text.to_string()));
+        }
+        let escaped = text.replace('%', r"\%").replace('_', r"\_");
+        Some(PlainMatch::Like(format!(
+            "{}{}{}",
+            if start { "" } else { "%" },
+            escaped,
+            if end { "" } else { "%" }
+        )))
+    }
+
+    fn test(&self, field: &str, arg: usize) -> String {
+        match self {
+            PlainMatch::Equal(_) => format!("{} = ?{} COLLATE NOCASE", field, arg),
+            PlainMatch::Like(_) => format!(r"{} LIKE ?{} ESCAPE '\'", field, arg),
+        }
+    }
+
+    fn arg(self) -> String {
+        match self {
+            PlainMatch::Equal(text) | PlainMatch::Like(text) => text,
+        }
+    }
+}
+
+/// SQLite has the REGEXP operator but leaves it to us to supply the function behind it.
+fn add_regexp(conn: &Connection) -> rusqlite::Result<()> {
+    conn.create_scalar_function(
+        "regexp",
+        2,
+        FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+        |ctx| {
+            // Compile each pattern once per statement, not once per row.
+            let patt: Arc<Regex> = ctx.get_or_create_aux(0, |vr| case_insensitive(vr.as_str()?))?;
+            let text = ctx.get::<Option<String>>(1)?.unwrap_or_default();
+            Ok(patt.is_match(&text))
+        },
+    )
+}
+
+impl Storage for SqliteStorage {
+    fn get(&self, bucket: &str, key: &str) -> anyhow::Result<Option<String>> {
+        let conn = self.conn.lock().unwrap();
+        let found = if bucket == MODS {
+            conn.query_row("SELECT value FROM mods WHERE key = ?1", [key], |row| {
+                row.get(0)
+            })
+        } else {
+            conn.query_row(
+                "SELECT value FROM records WHERE bucket = ?1 AND key = ?2",
+                [bucket, key],
+                |row| row.get(0),
+            )
+        };
+        Ok(found.optional()?)
+    }
+
+    fn set(&self, bucket: &str, key: &str, json: &str) -> anyhow::Result<()> {
+        if bucket == MODS {
+            return set_mod(&self.conn.lock().unwrap(), key, json);
+        }
+        self.conn.lock().unwrap().execute(
+            "INSERT OR REPLACE INTO records (bucket, key, value) VALUES (?1, ?2, ?3)",
+            [bucket, key, json],
+        )?;
+        Ok(())
+    }
+
+    fn remove(&self, bucket: &str, key: &str) -> anyhow::Result<()> {
+        let conn = self.conn.lock().unwrap();
+        if bucket == MODS {
+            conn.execute("DELETE FROM mods WHERE key = ?1", [key])?;
+        } else {
+            conn.execute(
+                "DELETE FROM records WHERE bucket = ?1 AND key = ?2",
+                [bucket, key],
+            )?;
+        }
+        Ok(())
+    }
+
+    fn set_many(&self, bucket: &str, records: &[(String, String)]) -> anyhow::Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        if bucket == MODS {
+            for (key, json) in records.iter() {
+                set_mod(&tx, key, json)?;
+            }
+        } else {
+            let mut insert = tx.prepare(
+                "INSERT OR REPLACE INTO records (bucket, key, value) VALUES (?1, ?2, ?3)",
+            )?;
+            for (key, json) in records.iter() {
+                insert.execute([bucket, key, json])?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn remove_many(&self, bucket: &str, keys: &[String]) -> anyhow::Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        {
+            if bucket == MODS {
+                let mut delete = tx.prepare("DELETE FROM mods WHERE key = ?1")?;
+                for key in keys.iter() {
+                    delete.execute([key])?;
+                }
+            } else {
+                let mut delete =
+                    tx.prepare("DELETE FROM records WHERE bucket = ?1 AND key = ?2")?;
+                for key in keys.iter() {
+                    delete.execute([bucket, key])?;
+                }
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn scan(&self, bucket: &str, prefix: &str) -> anyhow::Result<Vec<(String, String)>> {
+        let conn = self.conn.lock().unwrap();
+        let pair = |row: &rusqlite::Row<'_>| Ok((row.get(0)?, row.get(1)?));
+        // A range of keys rather than a test of each one, so the primary key's index does
+        // the work.
+        let (mut sql, mut args) = if bucket == MODS {
+            (
+                "SELECT key, value FROM mods WHERE key >= ?".to_string(),
+                vec![prefix.to_string()],
+            )
+        } else {
+            (
+                "SELECT key, value FROM records WHERE bucket = ? AND key >= ?".to_string(),
+                vec![bucket.to_string(), prefix.to_string()],
+            )
+        };
+        if let Some(end) = past_prefix(prefix) {
+            sql.push_str(" AND key < ?");
+            args.push(end);
+        }
+        sql.push_str(" ORDER BY key");
+        let rows = conn
+            .prepare(&sql)?
+            .query_map(params_from_iter(args.iter()), pair)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(rows)
+    }
+
+    fn buckets(&self) -> anyhow::Result<Vec<String>> {
+        let conn = self.conn.lock().unwrap();
+        let mut names: Vec<String> = conn
+            .prepare("SELECT DISTINCT bucket FROM records ORDER BY bucket")?
+            .query_map([], |row| row.get(0))?
+            .collect::<Result<_, _>>()?;
+        let mods: i64 = conn.query_row("SELECT count(*) FROM mods", [], |row| row.get(0))?;
+        if mods > 0 {
+            names.push(MODS.to_string());
+        }
+        Ok(names)
+    }
+
+    fn size_on_disk(&self) -> anyhow::Result<u64> {
+        let conn = self.conn.lock().unwrap();
+        let pages: i64 = conn.query_row("PRAGMA page_count", [], |row| row.get(0))?;
+        let page_size: i64 = conn.query_row("PRAGMA page_size", [], |row| row.get(0))?;
+        // Count what's waiting in the write-ahead log too.
+        let wal = conn
+            .path()
+            .and_then(|path| std::fs::metadata(format!("{}-wal", path)).ok())
+            .map(|meta| meta.len())
+            .unwrap_or(0);
+        Ok((pages * page_size) as u64 + wal)
+    }
+
+    fn compact(&self) -> anyhow::Result<()> {
+        let conn = self.conn.lock().unwrap();
+        // Vacuuming in WAL mode writes the rebuilt database to the log, so checkpoint after.
+        conn.execute_batch("VACUUM; PRAGMA wal_checkpoint(TRUNCATE);")?;
+        Ok(())
+    }
+
+    fn find_mods(&self, game: &str, query: &ModQuery) -> anyhow::Result<Vec<String>> {
+        // Check the pattern here, so a bad one is a clear error instead of a sqlite one.
+        if let Some(p) = query.pattern() {
+            case_insensitive(p)?;
+        }
+
+        let mut sql = "SELECT value FROM mods WHERE domain_name = ?1".to_string();
+        let mut args = vec![game.to_string()];
+        if let Some(status) = query.status() {
+            args.push(status);
+            sql.push_str(&format!(" AND status = ?{}", args.len()));
+        }
+        if let Some(p) = query.pattern() {
+            let plain = PlainMatch::of(p);
+            let tests: Vec<String> = query
+                .fields()
+                .iter()
+                .map(|field| match &plain {
+                    Some(plain) => plain.test(field, args.len() + 1),
+                    None => format!("{} REGEXP ?{}", field, args.len() + 1),
+                })
+                .collect();
+            args.push(plain.map_or_else(|| p.to_string(), PlainMatch::arg));
+            sql.push_str(&format!(" AND ({})", tests.join(" OR ")));
+        }
+        sql.push_str(" ORDER BY mod_id");
+
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&sql)?;
+        let rows = stmt.query_map(params_from_iter(args.iter()), |row| row.get(0))?;
+        Ok(rows.collect::<Result<Vec<String>, _>>()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_patterns_become_sql_tests() {
+        let like = |text: &str| Some(PlainMatch::Like(text.to_string()));
+        assert_eq!(PlainMatch::of("SkyUI"), like("%SkyUI%"));
+        assert_eq!(PlainMatch::of("^Sky"), like("Sky%"));
+        assert_eq!(PlainMatch::of("UI$"), like("%UI"));
+        assert_eq!(PlainMatch::of("100%_done"), like(r"%100\%\_done%"));
+        assert_eq!(
+            PlainMatch::of("^SkyUI$"),
+            Some(PlainMatch::Equal("SkyUI".to_string()))
+        );
+        for regex in [
+            "Sky.*UI",
+            "^$",
+            "",
+            "a|b",
+            r"\d+",
+            "Skyrim (SE)",
+            "Équipement",
+        ] {
+            assert_eq!(PlainMatch::of(regex), None, "{:?}", regex);
+        }
+    }
+
+    /// How SQLite plans to find a game's mods with names matching a pattern.
+    fn name_plan(pattern: &str) -> String {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(SCHEMA).unwrap();
+        let plain = PlainMatch::of(pattern).unwrap();
+        let sql = format!(
+            "EXPLAIN QUERY PLAN SELECT value FROM mods WHERE domain_name = ?1 AND {}",
+            plain.test("name", 2)
+        );
+        let mut stmt = conn.prepare(&sql).unwrap();
+        let details = stmt
+            .query_map(params!["skyrim", plain.arg()], |row| {
+                row.get::<_, String>(3)
+            })
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        details.join("; ")
+    }
+
+    #[test]
+    fn only_patterns_anchored_at_the_start_use_the_name_index() {
+        for anchored in ["^SkyUI$", "^Sky"] {
+            let plan = name_plan(anchored);
+            assert!(
+                plan.contains("mods_by_name_nocase"),
+                "{}: {}",
+                anchored,
+                plan
+            );
+        }
+        for anywhere in ["SkyUI", "UI$"] {
+            let plan = name_plan(anywhere);
+            assert!(
+                !plan.contains("mods_by_name_nocase"),
+                "{}: {}",
+                anywhere,
+                plan
+            );
+        }
+    }
+
+    #[test]
+    fn past_prefix_bounds_exactly_the_keys_with_the_prefix() {
+        assert_eq!(past_prefix("game/"), Some("game0".to_string()));
+        assert_eq!(past_prefix("a\u{d7ff}"), Some("a\u{e000}".to_string()));
+        assert_eq!(past_prefix("a\u{10ffff}"), Some("b".to_string()));
+        assert_eq!(past_prefix("\u{10ffff}"), None);
+        assert_eq!(past_prefix(""), None);
+    }
+}
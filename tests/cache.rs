@@ -0,0 +1,98 @@
+//! Looking after the cache: what `cache gc` keeps and what it clears out.
+
+mod common;
+
+use chrono::{Duration, Utc};
+use common::{dump_header, dump_record, game_record, mod_record, Cache};
+use rusqlite::Connection;
+use serde_json::json;
+
+static GAME: &str = "skyrim";
+
+fn days_ago(days: i64) -> String {
+    (Utc::now() - Duration::days(days)).to_rfc3339()
+}
+
+/// The ids of the mods still cached, read without showing them, which would count as a view.
+fn cached_ids(cache: &Cache) -> Vec<u32> {
+    let conn = Connection::open(cache.path()).unwrap();
+    let mut stmt = conn
+        .prepare("SELECT mod_id FROM mods ORDER BY mod_id")
+        .unwrap();
+    let ids = stmt
+        .query_map([], |row| row.get(0))
+        .unwrap()
+        .collect::<Result<Vec<u32>, _>>()
+        .unwrap();
+    ids
+}
+
+/// Whether there's a note in this bucket about when the record under `stamp` was touched.
+fn has_note(cache: &Cache, bucket: &str, stamp: &str) -> bool {
+    let conn = Connection::open(cache.path()).unwrap();
+    conn.query_row(
+        "SELECT COUNT(*) FROM records WHERE bucket = ?1 AND key = ?2",
+        [bucket, stamp],
+        |row| row.get::<_, i64>(0),
+    )
+    .unwrap()
+        > 0
+}
+
+#[test]
+fn gc_keeps_what_was_tracked_fetched_or_viewed_since_the_cutoff() {
+    let cache = Cache::new("gc", "sqlite");
+    let tracked = json!({
+        "mods": [{ "domain_name": GAME, "mod_id": 1 }],
+        "etag": "",
+    });
+    let mut lines = vec![
+        dump_header(2),
+        dump_record("games", GAME, None, &game_record(GAME)),
+        dump_record("mod_ref_lists", "tracked", None, &tracked),
+    ];
+    let mods = [
+        (1, "Tracked Long Ago", Some(days_ago(200))),
+        (2, "Fetched Inside", Some(days_ago(89))),
+        (3, "Fetched Outside", Some(days_ago(91))),
+        (4, "Listed Lately", Some(days_ago(200))),
+        (5, "Never Dated", None),
+    ];
+    for (id, name, fetched) in mods.iter() {
+        lines.push(dump_record(
+            "mods",
+            &format!("{}/{}", GAME, id),
+            fetched.as_deref(),
+            &mod_record(GAME, *id, name, name),
+        ));
+    }
+    let dump = cache.scratch.write("gc.jsonl", &lines.join("\n"));
+    cache.run(&["import", dump.to_str().unwrap()]);
+
+    // Showing a mod in a listing counts as viewing it, not only opening it with `mod`.
+    let listed = cache.run(&["--offline", "by-name", "^Listed", GAME]);
+    assert!(listed.contains("Listed Lately"), "{}", listed);
+
+    let dry = cache.run(&["--offline", "cache", "gc", "--dry-run"]);
+    assert!(dry.contains("Would remove 1 untracked mods"), "{}", dry);
+    let ran = cache.run(&["--offline", "cache", "gc"]);
+    assert!(ran.contains("Removing 1 untracked mods"), "{}", ran);
+    assert!(
+        ran.contains("Keeping 1 untracked mods with no record"),
+        "{}",
+        ran
+    );
+
+    assert_eq!(cached_ids(&cache), vec![1, 2, 4, 5]);
+    // The undated mod's clock is a view; it was never fetched, so nothing may think it fresh.
+    let stamp = format!("mods/{}/5", GAME);
+    assert!(has_note(&cache, "viewed", &stamp));
+    assert!(!has_note(&cache, "fetched", &stamp));
+
+    // A shorter window takes the mod fetched 89 days ago too, and the undated mod's clock
+    // started at the last run.
+    let ran = cache.run(&["--offline", "cache", "gc", "--days", "30"]);
+    assert!(ran.contains("Removing 1 untracked mods"), "{}", ran);
+    assert!(!ran.contains("no record"), "{}", ran);
+    assert_eq!(cached_ids(&cache), vec![1, 4, 5]);
+}
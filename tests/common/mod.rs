@@ -0,0 +1,272 @@
+//! Helpers for driving the modcache binary against the mock Nexus and a scratch cache.
+
+#![allow(dead_code)]
+
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Output, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use serde_json::Value;
+
+static SCRATCH_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+pub fn fixtures() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("test")
+        .join("fixtures")
+}
+
+/// A fixture, as json.
+pub fn fixture(name: &str) -> Value {
+    let path = fixtures().join(format!("{}.json", name));
+    let text = std::fs::read_to_string(&path).expect("fixture is readable");
+    serde_json::from_str(&text).expect("fixture is json")
+}
+
+/// A directory of our own for one test, with its cache, config, and dumps, removed when
+/// the test is done.
+pub struct Scratch {
+    pub dir: PathBuf,
+}
+
+impl Scratch {
+    pub fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!(
+            "modcache-test-{}-{}-{}",
+            std::process::id(),
+            SCRATCH_COUNT.fetch_add(1, Ordering::SeqCst),
+            name
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).expect("scratch directory can be made");
+        Self { dir }
+    }
+
+    pub fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(name)
+    }
+
+    pub fn write(&self, name: &str, contents: &str) -> PathBuf {
+        let path = self.path(name);
+        std::fs::write(&path, contents).expect("scratch file can be written");
+        path
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// The mock Nexus, running on whatever port the OS gives it until dropped.
+pub struct Mock {
+    child: Child,
+    pub url: String,
+}
+
+impl Mock {
+    pub fn start(args: &[&str]) -> Self {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_modcache"))
+            .args(["mock-server", "--port", "0", "--fixtures"])
+            .arg(fixtures())
+            .args(args)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("mock server starts");
+        // It says where it's listening once it's ready.
+        let mut line = String::new();
+        BufReader::new(child.stdout.take().expect("mock stdout is piped"))
+            .read_line(&mut line)
+            .expect("mock server reports its address");
+        let line = strip_ansi(&line);
+        let start = line.find("http://").expect("mock server prints its url");
+        let url = line[start..].trim().to_string();
+        Self { child, url }
+    }
+}
+
+impl Drop for Mock {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// One cache, in one backend, and the modcache commands that use it.
+pub struct Cache {
+    pub scratch: Scratch,
+    pub backend: &'static str,
+    pub base_url: Option<String>,
+}
+
+impl Cache {
+    pub fn new(name: &str, backend: &'static str) -> Self {
+        Self {
+            scratch: Scratch::new(name),
+            backend,
+            base_url: None,
+        }
+    }
+
+    /// The same cache, talking to this mock.
+    pub fn with_mock(mut self, mock: &Mock) -> Self {
+        self.base_url = Some(mock.url.clone());
+        self
+    }
+
+    pub fn path(&self) -> PathBuf {
+        match self.backend {
+            "sqlite" => self.scratch.path("cache.db"),
+            _ => self.scratch.path("cache"),
+        }
+    }
+
+    /// A modcache command with a key and everything else pointed at this test's scratch
+    /// directory, so nothing of the real user's leaks in.
+    pub fn command(&self) -> Command {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_modcache"));
+        for var in [
+            "NEXUS_REPLAY",
+            "NEXUS_RECORD",
+            "MODCACHE_PROFILE",
+            "MODCACHE_PASSPHRASE",
+        ] {
+            command.env_remove(var);
+        }
+        command
+            .env("RUST_BACKTRACE", "0")
+            .env("HOME", &self.scratch.dir)
+            .env("XDG_CONFIG_HOME", self.scratch.path("config"))
+            .env("XDG_DATA_HOME", self.scratch.path("data"))
+            .env("MODCACHE_CONFIG", self.scratch.path("config.toml"))
+            .env("NEXUS_API_KEY", "test-key")
+            .env("NEXUS_CACHE_BACKEND", self.backend)
+            .env("NEXUS_CACHE_PATH", self.path())
+            // Nothing should reach the real Nexus, even by mistake.
+            .env(
+                "NEXUS_BASE_URL",
+                self.base_url.as_deref().unwrap_or("http://127.0.0.1:9"),
+            );
+        command
+    }
+
+    /// Run modcache with these arguments, insisting that it succeeds, and return what it
+    /// printed.
+    pub fn run(&self, args: &[&str]) -> String {
+        let mut command = self.command();
+        command.args(args);
+        succeeded(command)
+    }
+
+    /// Run modcache with these arguments, insisting that it fails, and return its errors.
+    pub fn fail(&self, args: &[&str]) -> String {
+        let mut command = self.command();
+        command.args(args);
+        failed(command)
+    }
+
+    /// Run modcache with `--json` and read what it printed.
+    pub fn json(&self, args: &[&str]) -> Value {
+        let mut all = vec!["--json"];
+        all.extend_from_slice(args);
+        let out = self.run(&all);
+        serde_json::from_str(&out).unwrap_or_else(|e| panic!("{}: not json: {}", e, out))
+    }
+}
+
+fn describe(output: &Output) -> String {
+    format!(
+        "status {}\n--- stdout\n{}\n--- stderr\n{}",
+        output.status,
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    )
+}
+
+pub fn succeeded(mut command: Command) -> String {
+    let output = command.output().expect("modcache runs");
+    assert!(output.status.success(), "{}", describe(&output));
+    strip_ansi(&String::from_utf8_lossy(&output.stdout))
+}
+
+pub fn failed(mut command: Command) -> String {
+    let output = command.output().expect("modcache runs");
+    assert!(!output.status.success(), "{}", describe(&output));
+    strip_ansi(&String::from_utf8_lossy(&output.stderr))
+}
+
+/// Text without the colors we print everywhere.
+pub fn strip_ansi(text: &str) -> String {
+    let mut plain = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\u{1b}' {
+            // Skip the `[`, any parameters, and the letter that ends the sequence.
+            for c in chars.by_ref() {
+                if c.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+        } else {
+            plain.push(c);
+        }
+    }
+    plain
+}
+
+/// The header line of a dump.
+pub fn dump_header(schema_version: u32) -> String {
+    serde_json::json!({
+        "format": "modcache-dump",
+        "version": 1,
+        "schema_version": schema_version,
+        "exported": "2024-01-01T00:00:00Z",
+    })
+    .to_string()
+}
+
+/// One record line of a dump.
+pub fn dump_record(bucket: &str, key: &str, fetched: Option<&str>, value: &Value) -> String {
+    let mut record = serde_json::json!({ "bucket": bucket, "key": key, "value": value });
+    if let Some(fetched) = fetched {
+        record["fetched"] = Value::from(fetched);
+    }
+    record.to_string()
+}
+
+/// A mod from the fixtures, moved to another game and id and renamed.
+pub fn mod_record(game: &str, mod_id: u32, name: &str, description: &str) -> Value {
+    let mut modinfo = fixture("mod");
+    modinfo["domain_name"] = Value::from(game);
+    modinfo["mod_id"] = Value::from(mod_id);
+    modinfo["name"] = Value::from(name);
+    modinfo["summary"] = Value::from(name);
+    modinfo["description"] = Value::from(description);
+    modinfo
+}
+
+/// The game fixture, as some other game.
+pub fn game_record(game: &str) -> Value {
+    let mut gameinfo = fixture("game");
+    gameinfo["domain_name"] = Value::from(game);
+    gameinfo
+}
+
+/// The bucket and key of every record in a dump.
+pub fn dump_keys(dump: &Path) -> Vec<(String, String)> {
+    let text = std::fs::read_to_string(dump).expect("dump is readable");
+    text.lines()
+        .skip(1)
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let record: Value = serde_json::from_str(line).expect("dump lines are json");
+            (
+                record["bucket"].as_str().unwrap_or_default().to_string(),
+                record["key"].as_str().unwrap_or_default().to_string(),
+            )
+        })
+        .collect()
+}
@@ -0,0 +1,201 @@
+//! Export and import: what a dump holds, and which copy of a record wins.
+
+mod common;
+
+use common::{dump_header, dump_keys, dump_record, fixture, game_record, mod_record, Cache, Mock};
+use serde_json::Value;
+
+static GAME: &str = "skyrimspecialedition";
+
+fn buckets(keys: &[(String, String)]) -> Vec<&str> {
+    let mut buckets: Vec<&str> = keys.iter().map(|(bucket, _key)| bucket.as_str()).collect();
+    buckets.sort();
+    buckets.dedup();
+    buckets
+}
+
+#[test]
+fn personal_lists_are_exported_only_when_asked_for() {
+    let mock = Mock::start(&[]);
+    let cache = Cache::new("export", "sqlite").with_mock(&mock);
+    cache.run(&["validate"]);
+    cache.run(&["mod", "45870", GAME]);
+    cache.run(&["tracked"]);
+    cache.run(&["--json", "endorsements"]);
+
+    let shared = cache.scratch.path("shared.jsonl");
+    cache.run(&["export", shared.to_str().unwrap()]);
+    let keys = dump_keys(&shared);
+    assert!(keys.contains(&("mods".to_string(), format!("{}/45870", GAME))));
+    let found = buckets(&keys);
+    for private in ["authed_users", "mod_ref_lists", "endorsements"] {
+        assert!(!found.contains(&private), "{} in {:?}", private, found);
+    }
+
+    let personal = cache.scratch.path("personal.jsonl.gz");
+    cache.run(&["export", "--personal", personal.to_str().unwrap()]);
+    let copy = Cache::new("export-copy", "kv");
+    let imported = copy.run(&["import", personal.to_str().unwrap()]);
+    assert!(imported.contains("kept 0"), "{}", imported);
+    let unpacked = copy.scratch.path("unpacked.jsonl");
+    copy.run(&["export", "--personal", unpacked.to_str().unwrap()]);
+    let found = buckets(&dump_keys(&unpacked)).join(" ");
+    assert!(found.contains("mod_ref_lists"), "{}", found);
+    assert!(found.contains("endorsements"), "{}", found);
+    assert!(!found.contains("authed_users"), "{}", found);
+}
+
+/// Import one version of mod 1 and say what became of it.
+fn import_version(
+    cache: &Cache,
+    name: &str,
+    updated: u64,
+    fetched: Option<&str>,
+) -> (String, String) {
+    let mut modinfo = mod_record("skyrim", 1, name, "A mod.");
+    modinfo["updated_timestamp"] = Value::from(updated);
+    let lines = [
+        dump_header(1),
+        dump_record("mods", "skyrim/1", fetched, &modinfo),
+    ];
+    let dump = cache.scratch.write("version.jsonl", &lines.join("\n"));
+    let tally = cache.run(&["import", dump.to_str().unwrap()]);
+    let now = cache.json(&["--offline", "mod", "1", "skyrim"]);
+    (tally, now["name"].as_str().unwrap_or_default().to_string())
+}
+
+#[test]
+fn the_newer_copy_of_a_mod_wins_an_import() {
+    let cache = Cache::new("merge", "kv");
+
+    let (tally, name) = import_version(&cache, "First", 1000, None);
+    assert!(tally.contains("Added 1"), "{}", tally);
+    assert_eq!(name, "First");
+
+    // The Nexus's own update time decides first.
+    let (tally, name) = import_version(&cache, "Older", 999, Some("2030-01-01T00:00:00Z"));
+    assert!(tally.contains("kept 1"), "{}", tally);
+    assert_eq!(name, "First");
+    let (tally, name) = import_version(&cache, "Newer", 1001, None);
+    assert!(tally.contains("updated 1"), "{}", tally);
+    assert_eq!(name, "Newer");
+
+    // Then when each copy was fetched, and a copy fetched at an unknown time never wins.
+    let (tally, name) = import_version(&cache, "Refetched", 1001, Some("2020-01-01T00:00:00Z"));
+    assert!(tally.contains("updated 1"), "{}", tally);
+    assert_eq!(name, "Refetched");
+    let (tally, name) = import_version(&cache, "Stale", 1001, Some("2019-01-01T00:00:00Z"));
+    assert!(tally.contains("kept 1"), "{}", tally);
+    assert_eq!(name, "Refetched");
+    let (tally, name) = import_version(&cache, "Undated", 1001, None);
+    assert!(tally.contains("kept 1"), "{}", tally);
+    assert_eq!(name, "Refetched");
+}
+
+#[test]
+fn an_imported_tracked_list_never_replaces_ours() {
+    let mock = Mock::start(&[]);
+    let ours = Cache::new("ours", "sqlite").with_mock(&mock);
+    ours.run(&["tracked"]);
+    let dump = ours.scratch.path("ours.jsonl");
+    ours.run(&["export", "--personal", dump.to_str().unwrap()]);
+
+    // Someone else's list, fetched later and with one mod fewer.
+    let text = std::fs::read_to_string(&dump).unwrap();
+    let mut tracked: Value = text
+        .lines()
+        .skip(1)
+        .map(|line| serde_json::from_str::<Value>(line).unwrap())
+        .find(|record| record["bucket"] == "mod_ref_lists")
+        .expect("the tracked list was exported");
+    let before = tracked["value"]["mods"].as_array().unwrap().len();
+    tracked["value"]["mods"].as_array_mut().unwrap().pop();
+    let key = tracked["key"].as_str().unwrap().to_string();
+    let lines = [
+        dump_header(1),
+        dump_record(
+            "mod_ref_lists",
+            &key,
+            Some("2030-01-01T00:00:00Z"),
+            &tracked["value"],
+        ),
+    ];
+    let theirs = ours.scratch.write("theirs.jsonl", &lines.join("\n"));
+
+    let list = ours.json(&["--offline", "tracked"]);
+    let tally = ours.run(&["import", theirs.to_str().unwrap()]);
+    assert!(tally.contains("kept 1"), "{}", tally);
+    assert_eq!(ours.json(&["--offline", "tracked"]), list);
+
+    // Into a cache with no list of its own, it's welcome.
+    let empty = Cache::new("empty", "kv");
+    let tally = empty.run(&["import", theirs.to_str().unwrap()]);
+    assert!(tally.contains("Added 1"), "{}", tally);
+    let copied = empty.scratch.path("copied.jsonl");
+    empty.run(&["export", "--personal", copied.to_str().unwrap()]);
+    let text = std::fs::read_to_string(&copied).unwrap();
+    let copied: Value = serde_json::from_str(text.lines().nth(1).unwrap()).unwrap();
+    assert_eq!(
+        copied["value"]["mods"].as_array().unwrap().len(),
+        before - 1
+    );
+}
+
+/// Import file lists and changelogs for mod 1, fetched at this time.
+fn import_texts(cache: &Cache, file_text: &str, changelog: &str, fetched: &str) -> String {
+    let mut files = fixture("files");
+    files["domain_name"] = Value::from("skyrim");
+    files["mod_id"] = Value::from(1);
+    files["etag"] = Value::from("");
+    files["files"][0]["description"] = Value::from(file_text);
+    let changelogs = serde_json::json!({
+        "domain_name": "skyrim",
+        "mod_id": 1,
+        "etag": "",
+        "1.0.0": [changelog],
+    });
+    let lines = [
+        dump_header(2),
+        dump_record("games", "skyrim", None, &game_record("skyrim")),
+        dump_record("files", "skyrim/1", Some(fetched), &files),
+        dump_record("changelogs", "skyrim/1", Some(fetched), &changelogs),
+    ];
+    let dump = cache.scratch.write("texts.jsonl", &lines.join("\n"));
+    cache.run(&["import", dump.to_str().unwrap()])
+}
+
+fn finds_mod_1(cache: &Cache, text: &str) -> bool {
+    let found = cache.json(&["--offline", "search", text, "skyrim"]);
+    let found = found.as_array().expect("a list of mods");
+    found.iter().any(|m| m["mod_id"] == 1)
+}
+
+#[test]
+fn imported_file_lists_and_changelogs_are_searchable_at_once() {
+    let cache = Cache::new("texts", "sqlite");
+    import_version(&cache, "Plain", 1000, None);
+
+    let tally = import_texts(
+        &cache,
+        "Wyvernscale armor",
+        "Adds frostbloom",
+        "2020-01-01T00:00:00Z",
+    );
+    assert!(tally.contains("Added 3"), "{}", tally);
+    assert!(finds_mod_1(&cache, "wyvernscale"));
+    assert!(finds_mod_1(&cache, "frostbloom"));
+
+    // Newer copies replace the old text in the index, with the mod itself kept as it was.
+    let tally = import_texts(
+        &cache,
+        "Drakehide armor",
+        "Adds emberroot",
+        "2021-01-01T00:00:00Z",
+    );
+    assert!(tally.contains("updated 2"), "{}", tally);
+    assert!(finds_mod_1(&cache, "plain"));
+    assert!(finds_mod_1(&cache, "drakehide"));
+    assert!(finds_mod_1(&cache, "emberroot"));
+    assert!(!finds_mod_1(&cache, "wyvernscale"));
+    assert!(!finds_mod_1(&cache, "frostbloom"));
+}
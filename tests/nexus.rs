@@ -0,0 +1,209 @@
+//! Talking to the Nexus: etags, retries, and cassettes, against the mock.
+
+mod common;
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use chrono::{DateTime, Duration, Utc};
+use common::{dump_header, dump_record, fixtures, game_record, mod_record, Cache, Mock};
+use rusqlite::Connection;
+use tiny_http::{Header, Response, Server};
+
+static GAME: &str = "skyrimspecialedition";
+
+#[test]
+fn refreshing_an_unchanged_mod_gets_a_304_and_keeps_the_record() {
+    let mock = Mock::start(&[]);
+    let cache = Cache::new("etag", "sqlite").with_mock(&mock);
+
+    let fetched = cache.json(&["mod", "45870", GAME]);
+    assert_eq!(fetched["name"], "Northpoint SE");
+
+    let mut refresh = cache.command();
+    refresh.args(["-vvv", "--json", "--refresh", "mod", "45870", GAME]);
+    let output = refresh.output().expect("modcache runs");
+    assert!(output.status.success());
+    let log = String::from_utf8_lossy(&output.stderr);
+    assert!(log.contains("got status=304"), "no 304 in:\n{}", log);
+    let refreshed: serde_json::Value =
+        serde_json::from_slice(&output.stdout).expect("mod prints json");
+    assert_eq!(refreshed, fetched);
+
+    // And the record is still there to read offline.
+    assert_eq!(cache.json(&["--offline", "mod", "45870", GAME]), fetched);
+}
+
+#[test]
+fn an_undated_record_is_trusted_unless_a_max_age_is_given() {
+    let mock = Mock::start(&[]);
+    let cache = Cache::new("undated", "sqlite").with_mock(&mock);
+    let lines = [
+        dump_header(2),
+        dump_record("games", GAME, None, &game_record(GAME)),
+        dump_record(
+            "mods",
+            &format!("{}/45870", GAME),
+            None,
+            &mod_record(
+                GAME,
+                45870,
+                "Northpoint Before",
+                "Cached before fetch times",
+            ),
+        ),
+    ];
+    let dump = cache.scratch.write("undated.jsonl", &lines.join("\n"));
+    cache.run(&["import", dump.to_str().unwrap()]);
+
+    let trusted = cache.json(&["mod", "45870", GAME]);
+    assert_eq!(trusted["name"], "Northpoint Before");
+
+    // Even a generous max age can't vouch for a record whose age is unknown.
+    let mut aged = cache.command();
+    aged.args(["-vv", "--json", "--max-age", "30d", "mod", "45870", GAME]);
+    let output = aged.output().expect("modcache runs");
+    let log = common::strip_ansi(&String::from_utf8_lossy(&output.stderr));
+    assert!(output.status.success(), "{}", log);
+    assert!(log.contains("cached copy is stale"), "{}", log);
+    let refreshed: serde_json::Value =
+        serde_json::from_slice(&output.stdout).expect("mod prints json");
+    assert_eq!(refreshed["name"], "Northpoint SE");
+}
+
+#[test]
+fn rate_limits_survive_a_restart_and_reset_at_the_boundary() {
+    let mock = Mock::start(&["--hourly-limit", "50", "--daily-limit", "500"]);
+    let cache = Cache::new("limits", "sqlite").with_mock(&mock);
+
+    cache.run(&["mod", "45870", GAME]);
+    cache.run(&["mod", "45870", GAME, "--refresh"]);
+
+    // A fresh process that makes no requests knows what the last one was told.
+    let limits = cache.json(&["--offline", "quota"]);
+    assert_eq!(limits["hourly_limit"], 50);
+    assert_eq!(limits["hourly_remaining"], 48);
+    assert_eq!(limits["daily_limit"], 500);
+    assert_eq!(limits["daily_remaining"], 498);
+    assert!(limits["as_of"].is_string(), "{}", limits);
+
+    {
+        // As if the last run spent the hour's quota and the hour has since ended.
+        let conn = Connection::open(cache.path()).unwrap();
+        let stored = conn
+            .execute(
+                "UPDATE records SET value = json_set(value,
+                    '$.hourly_remaining', 0, '$.hourly_reset', '2000-01-01T00:00:00Z')
+                 WHERE bucket = 'rate_limits'",
+                [],
+            )
+            .unwrap();
+        assert_eq!(stored, 1);
+    }
+    let limits = cache.json(&["--offline", "quota"]);
+    assert_eq!(limits["hourly_remaining"], 50);
+    assert_eq!(limits["daily_remaining"], 498);
+    let reset: DateTime<Utc> = limits["hourly_reset"].as_str().unwrap().parse().unwrap();
+    assert!(reset > Utc::now(), "{}", limits);
+    assert!(reset <= Utc::now() + Duration::hours(1), "{}", limits);
+
+    {
+        // And the day's, at midnight.
+        let conn = Connection::open(cache.path()).unwrap();
+        conn.execute(
+            "UPDATE records SET value = json_set(value,
+                '$.daily_remaining', 0, '$.daily_reset', '2000-01-01T00:00:00Z')
+             WHERE bucket = 'rate_limits'",
+            [],
+        )
+        .unwrap();
+    }
+    let limits = cache.json(&["--offline", "quota"]);
+    assert_eq!(limits["daily_remaining"], 500);
+    let reset: DateTime<Utc> = limits["daily_reset"].as_str().unwrap().parse().unwrap();
+    assert_eq!(reset.time(), chrono::NaiveTime::MIN, "{}", limits);
+    assert!(reset > Utc::now(), "{}", limits);
+}
+
+#[test]
+fn a_nexus_that_keeps_failing_is_retried_then_reported() {
+    let mock = Mock::start(&["--flaky", "1.0"]);
+    let cache = Cache::new("flaky", "sqlite").with_mock(&mock);
+
+    let errors = cache.fail(&["mod", "45870", GAME]);
+    for attempt in 1..=4 {
+        assert!(
+            errors.contains(&format!("retry {} of 4", attempt)),
+            "no retry {} in:\n{}",
+            attempt,
+            errors
+        );
+    }
+    assert!(errors.contains("503"), "{}", errors);
+    assert!(!errors.contains("retry 5"), "{}", errors);
+}
+
+#[test]
+fn a_transient_failure_is_retried_until_it_succeeds() {
+    // The mock fails at random, so this one fails exactly twice.
+    let server = Server::http("127.0.0.1:0").expect("stub server starts");
+    let port = server.server_addr().to_ip().expect("stub has an ip").port();
+    let requests = Arc::new(AtomicUsize::new(0));
+    let counted = requests.clone();
+    let modinfo = std::fs::read(fixtures().join("mod.json")).expect("fixture is readable");
+    std::thread::spawn(move || {
+        let json: Header = "content-type: application/json".parse().unwrap();
+        for request in server.incoming_requests() {
+            let response = if counted.fetch_add(1, Ordering::SeqCst) < 2 {
+                Response::from_data(b"{\"message\":\"Service Unavailable\"}".to_vec())
+                    .with_status_code(503)
+            } else {
+                Response::from_data(modinfo.clone())
+            };
+            let _ = request.respond(response.with_header(json.clone()));
+        }
+    });
+
+    let mut cache = Cache::new("recover", "kv");
+    cache.base_url = Some(format!("http://127.0.0.1:{}", port));
+    let mut command = cache.command();
+    command.args(["--json", "mod", "45870", GAME]);
+    let output = command.output().expect("modcache runs");
+    let log = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}", log);
+    assert!(log.contains("retry 2 of 4"), "{}", log);
+    assert_eq!(requests.load(Ordering::SeqCst), 3);
+    let fetched: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(fetched["mod_id"], 45870);
+}
+
+#[test]
+fn a_recorded_cassette_replays_without_the_nexus() {
+    let mock = Mock::start(&[]);
+    let recording = Cache::new("record", "sqlite").with_mock(&mock);
+    let tape = recording.scratch.path("tape.json");
+
+    let mut record = recording.command();
+    record
+        .env("NEXUS_RECORD", &tape)
+        .args(["--json", "mod", "92607", GAME]);
+    let recorded = common::succeeded(record);
+    drop(mock);
+
+    // A fresh cache, no key, and nothing listening.
+    let replaying = Cache::new("replay", "kv");
+    let mut replay = replaying.command();
+    replay
+        .env_remove("NEXUS_API_KEY")
+        .env("NEXUS_REPLAY", &tape)
+        .args(["--json", "mod", "92607", GAME]);
+    assert_eq!(common::succeeded(replay), recorded);
+
+    // Anything the cassette doesn't have is an error, not a trip to the network.
+    let mut missing = replaying.command();
+    missing
+        .env("NEXUS_REPLAY", &tape)
+        .args(["mod", "45870", GAME]);
+    let errors = common::failed(missing);
+    assert!(errors.contains("no recorded response"), "{}", errors);
+}
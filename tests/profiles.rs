@@ -0,0 +1,78 @@
+//! Profiles share a cache but not an account: each keeps its own personal records and
+//! spends its own quota.
+
+mod common;
+
+use common::{Cache, Mock};
+use rusqlite::Connection;
+
+static CONFIG: &str = r#"
+[profiles.alice]
+api_key = "alice-key"
+
+[profiles.bob]
+game = "fallout4"
+"#;
+
+fn keys(cache: &Cache, bucket: &str) -> Vec<String> {
+    let conn = Connection::open(cache.path()).unwrap();
+    let mut stmt = conn
+        .prepare("SELECT key FROM records WHERE bucket = ?1 ORDER BY key")
+        .unwrap();
+    let keys = stmt
+        .query_map([bucket], |row| row.get(0))
+        .unwrap()
+        .collect::<Result<Vec<String>, _>>()
+        .unwrap();
+    keys
+}
+
+#[test]
+fn each_profile_keeps_its_own_tracked_list() {
+    let mock = Mock::start(&[]);
+    let cache = Cache::new("profiles", "sqlite").with_mock(&mock);
+    cache.scratch.write("config.toml", CONFIG);
+
+    cache.run(&["--profile", "alice", "tracked"]);
+    cache.run(&["--profile", "alice", "--json", "endorsements"]);
+    cache.run(&["tracked"]);
+    assert_eq!(
+        keys(&cache, "mod_ref_lists"),
+        vec!["tracked".to_string(), "tracked@alice".to_string()]
+    );
+    assert_eq!(keys(&cache, "endorsements"), vec!["endorsements@alice"]);
+    let limits = keys(&cache, "rate_limits");
+    assert!(
+        limits.contains(&format!("{}@alice", mock.url)),
+        "{:?}",
+        limits
+    );
+
+    // The environment picks a profile too, and each reads only its own list.
+    let mut command = cache.command();
+    command
+        .env("MODCACHE_PROFILE", "alice")
+        .args(["--offline", "--json", "tracked"]);
+    let tracked: serde_json::Value = serde_json::from_str(&common::succeeded(command)).unwrap();
+    assert!(!tracked["mods"].as_array().unwrap().is_empty());
+    let errors = cache.fail(&["--profile", "bob", "--offline", "tracked"]);
+    assert!(errors.contains("tracked@bob isn't cached"), "{}", errors);
+}
+
+#[test]
+fn a_profile_never_borrows_the_environments_key() {
+    let mock = Mock::start(&[]);
+    let cache = Cache::new("borrow", "sqlite").with_mock(&mock);
+    cache.scratch.write("config.toml", CONFIG);
+
+    let errors = cache.fail(&["--profile", "bob", "tracked"]);
+    assert!(
+        errors.contains("set the api_key of your profile"),
+        "{}",
+        errors
+    );
+    assert!(keys(&cache, "mod_ref_lists").is_empty());
+
+    let errors = cache.fail(&["--profile", "carol", "tracked"]);
+    assert!(errors.contains("no profile named `carol`"), "{}", errors);
+}
@@ -0,0 +1,82 @@
+//! Sharing a kv cache between modcache processes, and what happens when the one that has it
+//! open goes away first.
+
+mod common;
+
+use std::process::Stdio;
+use std::sync::mpsc;
+use std::time::Duration;
+
+use common::{fixture, Cache};
+use tiny_http::{Header, Response, Server};
+
+static GAME: &str = "skyrimspecialedition";
+
+#[test]
+fn a_client_takes_over_when_the_owner_goes_away() {
+    // A Nexus that never answers the owner, so it holds the cache open until it's killed,
+    // and answers the client only when the test says so.
+    let server = Server::http("127.0.0.1:0").expect("stub server starts");
+    let port = server.server_addr().to_ip().expect("stub has an ip").port();
+    let (arrived, requests) = mpsc::channel();
+    let (release, released) = mpsc::channel::<()>();
+    std::thread::spawn(move || {
+        let json: Header = "content-type: application/json".parse().unwrap();
+        let mut held = Vec::new();
+        for request in server.incoming_requests() {
+            let url = request.url().to_string();
+            arrived.send(url.clone()).unwrap();
+            if url.contains("/mods/1.json") {
+                held.push(request);
+                continue;
+            }
+            let _ = released.recv();
+            let modinfo = fixture("mod").to_string();
+            let _ = request.respond(Response::from_string(modinfo).with_header(json.clone()));
+        }
+    });
+
+    let mut cache = Cache::new("takeover", "kv");
+    cache.base_url = Some(format!("http://127.0.0.1:{}", port));
+
+    let mut owner = cache.command();
+    owner
+        .args(["mod", "1", GAME])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    let mut owner = owner.spawn().expect("the owner starts");
+    let url = requests
+        .recv_timeout(Duration::from_secs(30))
+        .expect("the owner asks the Nexus");
+    assert!(url.contains("/mods/1.json"), "{}", url);
+
+    let mut client = cache.command();
+    client
+        .args(["-vv", "--json", "mod", "45870", GAME])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    let client = client.spawn().expect("the client starts");
+    let url = requests
+        .recv_timeout(Duration::from_secs(30))
+        .expect("the client asks the Nexus");
+    assert!(url.contains("/mods/45870.json"), "{}", url);
+
+    // The owner goes away while the client is waiting on the Nexus, so the client's next
+    // write finds nobody answering.
+    owner.kill().expect("the owner can be killed");
+    owner.wait().expect("the owner exits");
+    release.send(()).unwrap();
+
+    let output = client.wait_with_output().expect("the client runs");
+    let log = common::strip_ansi(&String::from_utf8_lossy(&output.stderr));
+    assert!(output.status.success(), "{}", log);
+    assert!(log.contains("going through it"), "{}", log);
+    assert!(log.contains("took over sharing it"), "{}", log);
+    assert!(log.contains("cached new nexus data"), "{}", log);
+    let fetched: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(fetched["mod_id"], 45870);
+
+    // What the client fetched after the takeover made it into the cache.
+    let cached = cache.json(&["--offline", "mod", "45870", GAME]);
+    assert_eq!(cached, fetched);
+}
@@ -0,0 +1,279 @@
+//! What every backend must agree on: which records a prefix finds, and bringing an old
+//! cache up to date.
+
+mod common;
+
+use common::{dump_header, dump_record, game_record, mod_record, Cache};
+use rusqlite::Connection;
+use serde_json::Value;
+
+/// Mods in two games whose names share a prefix, and words that do too.
+fn neighbors_dump(cache: &Cache) -> std::path::PathBuf {
+    let mut lines = vec![dump_header(1)];
+    for game in ["skyrim", "skyrimse"] {
+        lines.push(dump_record("games", game, None, &game_record(game)));
+    }
+    let mods = [
+        ("skyrim", 1, "Dragon Tweaks", "Makes every dragon tougher."),
+        (
+            "skyrim",
+            2,
+            "Dragonborn Voice",
+            "Replaces the dragonborn's voice.",
+        ),
+        ("skyrim", 10, "Dragon Shouts", "More dragon shouts."),
+        (
+            "skyrimse",
+            1,
+            "Dragon Tweaks SE",
+            "Makes every dragon tougher.",
+        ),
+    ];
+    for (game, id, name, description) in mods {
+        lines.push(dump_record(
+            "mods",
+            &format!("{}/{}", game, id),
+            None,
+            &mod_record(game, id, name, description),
+        ));
+    }
+    cache.scratch.write("neighbors.jsonl", &lines.join("\n"))
+}
+
+fn ids(found: &Value) -> Vec<u64> {
+    let mut ids: Vec<u64> = found
+        .as_array()
+        .expect("a list of mods")
+        .iter()
+        .map(|m| m["mod_id"].as_u64().expect("mods have ids"))
+        .collect();
+    ids.sort();
+    ids
+}
+
+fn prefixes_stop_at_their_own_keys(backend: &'static str) {
+    let cache = Cache::new(&format!("prefix-{}", backend), backend);
+    let dump = neighbors_dump(&cache);
+    let imported = cache.run(&["import", dump.to_str().unwrap()]);
+    assert!(imported.contains("Added 6"), "{}", imported);
+
+    // `skyrim/` mustn't reach into `skyrimse/`, nor `skyrim/1` into `skyrim/10`.
+    let text = cache.run(&["--offline", "mods", "skyrim"]);
+    assert!(text.contains("Dragon Shouts"), "{}", text);
+    assert!(!text.contains("Tweaks SE"), "{}", text);
+    let found = cache.json(&["--offline", "mod", "1", "skyrim"]);
+    assert_eq!(found["name"], "Dragon Tweaks");
+
+    // Postings for `dragon` aren't postings for `dragonborn`.
+    let found = cache.json(&["--offline", "search", "dragon", "skyrim"]);
+    assert_eq!(ids(&found), vec![1, 10]);
+    let found = cache.json(&["--offline", "search", "dragonborn", "skyrim"]);
+    assert_eq!(ids(&found), vec![2]);
+    let found = cache.json(&["--offline", "search", "dragon", "skyrimse"]);
+    assert_eq!(ids(&found), vec![1]);
+}
+
+#[test]
+fn kv_prefixes_stop_at_their_own_keys() {
+    prefixes_stop_at_their_own_keys("kv");
+}
+
+#[test]
+fn sqlite_prefixes_stop_at_their_own_keys() {
+    prefixes_stop_at_their_own_keys("sqlite");
+}
+
+fn top_level_categories(game: &Value) -> Vec<Value> {
+    game["categories"]
+        .as_array()
+        .expect("games have categories")
+        .iter()
+        .filter(|c| c["category_id"] == 20)
+        .map(|c| c["parent_category"].clone())
+        .collect()
+}
+
+/// Write records straight into a SQLite cache, as an older modcache would have left them.
+fn old_cache(cache: &Cache, records: &[(&str, &str, &Value)]) {
+    let conn = Connection::open(cache.path()).unwrap();
+    conn.execute_batch(
+        "CREATE TABLE records (
+            bucket TEXT NOT NULL,
+            key TEXT NOT NULL,
+            value TEXT NOT NULL,
+            PRIMARY KEY (bucket, key)
+        );",
+    )
+    .unwrap();
+    for (bucket, key, value) in records {
+        conn.execute(
+            "INSERT INTO records (bucket, key, value) VALUES (?1, ?2, ?3)",
+            [bucket, key, &value.to_string().as_str()],
+        )
+        .unwrap();
+    }
+}
+
+/// A record as it's stored in a SQLite cache.
+fn stored(cache: &Cache, bucket: &str, key: &str) -> String {
+    let conn = Connection::open(cache.path()).unwrap();
+    conn.query_row(
+        "SELECT value FROM records WHERE bucket = ?1 AND key = ?2",
+        [bucket, key],
+        |row| row.get(0),
+    )
+    .unwrap()
+}
+
+#[test]
+fn opening_an_old_cache_migrates_parent_categories() {
+    let cache = Cache::new("migrate", "sqlite");
+    let game = common::fixture("game");
+    assert_eq!(top_level_categories(&game), vec![Value::Bool(false)]);
+    let mut broken = common::game_record("broken");
+    broken["categories"] = Value::from("not a list");
+    // A cache from before schema versions: no meta bucket, and the Nexus's `false`.
+    old_cache(
+        &cache,
+        &[
+            ("games", "skyrimspecialedition", &game),
+            ("games", "broken", &broken),
+        ],
+    );
+
+    let shown = cache.run(&["--offline", "game", "skyrimspecialedition"]);
+    assert!(shown.contains("Skyrim Special Edition"), "{}", shown);
+
+    let get = |bucket: &str, key: &str| stored(&cache, bucket, key);
+    assert_eq!(get("meta", "schema_version"), "2");
+    let migrated: Value = serde_json::from_str(&get("games", "skyrimspecialedition")).unwrap();
+    assert_eq!(top_level_categories(&migrated), vec![Value::Null]);
+    // What couldn't be migrated is left alone and reported.
+    let left: Value = serde_json::from_str(&get("games", "broken")).unwrap();
+    assert_eq!(left, broken);
+    let report: Value = serde_json::from_str(&get("unmigrated", "games/broken")).unwrap();
+    assert_eq!(report["error"], "categories is not a list");
+}
+
+#[test]
+fn opening_an_old_cache_forgets_stored_api_keys() {
+    let cache = Cache::new("migrate-key", "sqlite");
+    let user = common::fixture("validate");
+    assert_eq!(user["key"], "deadbeefcafed00d");
+    let version = Value::from(1);
+    old_cache(
+        &cache,
+        &[
+            ("meta", "schema_version", &version),
+            ("authed_users", "authed_user", &user),
+        ],
+    );
+
+    cache.run(&["--offline", "cache", "stats"]);
+
+    let migrated = stored(&cache, "authed_users", "authed_user");
+    assert!(!migrated.contains("deadbeefcafed00d"), "{}", migrated);
+    let migrated: Value = serde_json::from_str(&migrated).unwrap();
+    assert_eq!(migrated.get("key"), None);
+    assert_eq!(migrated["email"], "notreal@example.com");
+    assert_eq!(stored(&cache, "meta", "schema_version"), "2");
+}
+
+#[test]
+fn importing_an_old_dump_migrates_parent_categories() {
+    let cache = Cache::new("migrate-import", "kv");
+    let lines = [
+        dump_header(0),
+        dump_record(
+            "games",
+            "skyrimspecialedition",
+            None,
+            &common::fixture("game"),
+        ),
+    ];
+    let dump = cache.scratch.write("old.jsonl", &lines.join("\n"));
+    cache.run(&["import", dump.to_str().unwrap()]);
+
+    let exported = cache.scratch.path("new.jsonl");
+    cache.run(&["export", exported.to_str().unwrap()]);
+    let text = std::fs::read_to_string(&exported).unwrap();
+    let header: Value = serde_json::from_str(text.lines().next().unwrap()).unwrap();
+    assert_eq!(header["schema_version"], 2);
+    let game: Value = text
+        .lines()
+        .skip(1)
+        .map(|line| serde_json::from_str::<Value>(line).unwrap())
+        .find(|record| record["bucket"] == "games")
+        .expect("the game was exported");
+    assert_eq!(top_level_categories(&game["value"]), vec![Value::Null]);
+}
+
+#[test]
+fn a_cache_from_before_the_index_is_searched_by_reading_every_mod() {
+    let cache = Cache::new("unindexed", "sqlite");
+    let dump = neighbors_dump(&cache);
+    cache.run(&["import", dump.to_str().unwrap()]);
+    {
+        // As if the mods were cached before there was an index.
+        let conn = Connection::open(cache.path()).unwrap();
+        conn.execute("DELETE FROM records WHERE bucket LIKE 'search_%'", [])
+            .unwrap();
+    }
+
+    let search = |expect_warning: bool| {
+        let mut command = cache.command();
+        command.args(["--offline", "--json", "search", "dragon", "skyrim"]);
+        let output = command.output().expect("modcache runs");
+        let log = String::from_utf8_lossy(&output.stderr);
+        assert!(output.status.success(), "{}", log);
+        assert_eq!(log.contains("cache reindex"), expect_warning, "{}", log);
+        let found: Value = serde_json::from_slice(&output.stdout).unwrap();
+        ids(&found)
+    };
+    assert_eq!(search(true), vec![1, 10]);
+
+    cache.run(&["cache", "reindex"]);
+    assert_eq!(search(false), vec![1, 10]);
+}
+
+/// What the search index keeps about a game.
+fn index_stats(cache: &Cache, game: &str) -> Value {
+    serde_json::from_str(&stored(cache, "search_stats", game)).unwrap()
+}
+
+fn index_totals(cache: &Cache, game: &str) -> Value {
+    index_stats(cache, game)["totals"].clone()
+}
+
+#[test]
+fn the_index_keeps_its_totals_as_mods_are_stored() {
+    let cache = Cache::new("totals", "sqlite");
+    let dump = neighbors_dump(&cache);
+    cache.run(&["import", dump.to_str().unwrap()]);
+    let totals = index_totals(&cache, "skyrim");
+    assert_eq!(totals["docs"], 3, "{}", totals);
+    let length = totals["length"].as_u64().unwrap();
+    assert!(length > 0);
+
+    let found = cache.json(&["--offline", "search", "dragon", "skyrim"]);
+    assert_eq!(ids(&found), vec![1, 10]);
+    assert_eq!(index_stats(&cache, "skyrim")["complete"], true);
+    assert_eq!(index_totals(&cache, "skyrim"), totals);
+
+    cache.run(&["cache", "reindex"]);
+    assert_eq!(index_totals(&cache, "skyrim"), totals);
+
+    {
+        // As if the index were complete before it kept totals.
+        let conn = Connection::open(cache.path()).unwrap();
+        conn.execute(
+            "UPDATE records SET value = '{\"complete\":true,\"version\":2}'
+             WHERE bucket = 'search_stats' AND key = 'skyrim'",
+            [],
+        )
+        .unwrap();
+    }
+    let found = cache.json(&["--offline", "search", "dragon", "skyrim"]);
+    assert_eq!(ids(&found), vec![1, 10]);
+    assert_eq!(index_totals(&cache, "skyrim"), totals);
+}
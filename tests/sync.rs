@@ -0,0 +1,54 @@
+//! Syncing with the Nexus's list of recently changed mods, against the mock.
+
+mod common;
+
+use common::{Cache, Mock};
+use rusqlite::Connection;
+
+static GAME: &str = "skyrimspecialedition";
+
+#[test]
+fn sync_refreshes_only_mods_active_since_they_were_fetched() {
+    let mock = Mock::start(&[]);
+    let cache = Cache::new("sync", "sqlite").with_mock(&mock);
+
+    // Every mod the mock knows is tracked, so the first sync fetches them all.
+    let first = cache.run(&["sync", GAME]);
+    assert!(!first.contains(" 0 of yours need refreshing"), "{}", first);
+    assert!(!first.contains("Refreshed 0 mods"), "{}", first);
+
+    // The mock reports activity after each mod's last update, but before we fetched it.
+    let again = cache.run(&["sync", GAME]);
+    assert!(again.contains(" 0 of yours need refreshing"), "{}", again);
+
+    {
+        // As if we'd fetched them all long ago.
+        let conn = Connection::open(cache.path()).unwrap();
+        let aged = conn
+            .execute(
+                "UPDATE records SET value = '\"2000-01-01T00:00:00Z\"'
+                 WHERE bucket = 'fetched' AND key LIKE 'mods/%'",
+                [],
+            )
+            .unwrap();
+        assert!(aged > 0);
+    }
+
+    // Nothing changed on the Nexus, so each refresh is a 304, which still counts as a check.
+    let mut stale = cache.command();
+    stale.args(["-vv", "sync", GAME]);
+    let output = stale.output().expect("modcache runs");
+    let log = common::strip_ansi(&String::from_utf8_lossy(&output.stderr));
+    let out = common::strip_ansi(&String::from_utf8_lossy(&output.stdout));
+    assert!(output.status.success(), "{}", log);
+    assert!(!out.contains(" 0 of yours need refreshing"), "{}", out);
+    assert!(out.contains("Refreshed 0 mods"), "{}", out);
+    assert!(log.contains("hasn't changed after all"), "{}", log);
+
+    let checked = cache.run(&["sync", GAME]);
+    assert!(
+        checked.contains(" 0 of yours need refreshing"),
+        "{}",
+        checked
+    );
+}